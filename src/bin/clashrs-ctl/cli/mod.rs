@@ -1,5 +1,13 @@
 use clap::{Args, Parser, Subcommand};
 use clashrsctl::config::{ConfigLogLevel, ConfigMode};
+use clashrsctl::dns::DnsType;
+use clashrsctl::history::RecordFormat;
+use clashrsctl::rate::SortKey;
+use clashrsctl::summary::GroupBy;
+use clashrsctl::usage::{Quota, UsageBy};
+
+use crate::output::OutputFormat;
+use crate::table::ColumnArgs;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -16,6 +24,15 @@ pub struct Cli {
     /// authentication secret
     pub secret: Option<String>,
 
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    /// Format of the responses
+    pub output: OutputFormat,
+
+    #[arg(long)]
+    /// Directory for subscriptions and other local state.
+    /// Defaults to `$XDG_CONFIG_HOME/clashrs-ctl`
+    pub data_dir: Option<String>,
+
     #[command(subcommand)]
     pub command: Command,
 }
@@ -24,17 +41,243 @@ pub struct Cli {
 pub enum Command {
     Config(Config),
     /// List all rules
-    Rules,
+    Rules {
+        #[command(flatten)]
+        columns: ColumnArgs,
+    },
+    /// Rule tools
+    Rule(Rule),
     Proxy(Proxy),
     /// Print realtime log.
     /// TODO: Log level not supported now.
     Log,
     /// Print the traffic.
     Traffic,
+    /// Full-screen dashboard of traffic, logs, connections and proxies
+    #[cfg(feature = "tui")]
+    Tui,
     /// Print the version of the clash core
-    Version,
+    Version {
+        /// Also detect the core flavor and the optional endpoints it provides
+        #[arg(short, long)]
+        capabilities: bool,
+    },
     /// Connection control
     Connection(Connection),
+    /// Manage profile subscriptions
+    Subscription(Subscription),
+    /// Work with profile files
+    Profile(Profile),
+    /// Operate the clash core
+    Core(Core),
+    /// DNS tools of the clash core
+    Dns(Dns),
+    /// Data usage accounting
+    Usage(Usage),
+}
+
+#[derive(Args, Debug)]
+pub struct Rule {
+    #[command(subcommand)]
+    pub command: RuleCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum RuleCommand {
+    /// Find the rule and policy that would handle a connection
+    Test {
+        /// `host`, `host:port`, `ip` or `ip:port`
+        target: String,
+        /// Source IP of the connection
+        #[arg(long)]
+        src_ip: Option<std::net::IpAddr>,
+        /// Source port of the connection
+        #[arg(long)]
+        src_port: Option<u16>,
+        /// Process name or path of the connection
+        #[arg(long)]
+        process: Option<String>,
+        /// Evaluate the rules of a profile file instead of the running ones
+        #[arg(long)]
+        profile: Option<String>,
+        /// Resolve the host with the DNS of the core for IP rules
+        #[arg(short, long)]
+        resolve: bool,
+    },
+    /// Report unreachable, duplicate and redundant rules and unknown policies
+    Lint {
+        /// Lint the rules and policies of a profile file instead of the running ones
+        #[arg(long)]
+        profile: Option<String>,
+    },
+    /// Count hits, bytes and hosts per rule over a period
+    Hits {
+        /// Time to collect for, e.g. `5m`. Bare numbers are seconds
+        #[arg(short, long, default_value = "60")]
+        duration: String,
+        /// Time between samples of the connections, e.g. `2s`
+        #[arg(short, long, default_value = "1")]
+        interval: String,
+        /// Read the matched rules from the log stream instead of the connections
+        #[arg(long)]
+        logs: bool,
+    },
+    /// Replay recorded connections against a profile and list policy changes
+    Simulate {
+        /// Connection history recorded by `connection record`
+        #[arg(long)]
+        history: String,
+        /// The candidate profile
+        #[arg(long)]
+        profile: String,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct Core {
+    #[command(subcommand)]
+    pub command: CoreCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum CoreCommand {
+    /// Restart the clash core
+    Restart,
+    /// Update the GeoIP database
+    UpdateGeo,
+    /// Print the realtime memory usage
+    Memory,
+}
+
+#[derive(Args, Debug)]
+pub struct Dns {
+    #[command(subcommand)]
+    pub command: DnsCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum DnsCommand {
+    /// Flush the fake-ip cache
+    FlushFakeip,
+    /// Resolve a host with the DNS of the clash core
+    Query {
+        host: String,
+        #[arg(value_enum, short = 't', long = "type", default_value = "A", ignore_case = true)]
+        record_type: DnsType,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct Usage {
+    /// The usage file. Defaults to `usage.jsonl` in the data directory
+    #[arg(short, long, global = true)]
+    pub file: Option<String>,
+    #[command(subcommand)]
+    pub command: UsageCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum UsageCommand {
+    /// Sample the connections until interrupted, appending the bytes used to the usage file
+    Record {
+        /// Time between samples, e.g. `5s`
+        #[arg(short, long, default_value = "5s")]
+        interval: String,
+        /// Alert when a key uses more than a size in a calendar month, e.g. `source:192.168.1.2=50GB`.
+        /// May be given more than once
+        #[arg(short, long)]
+        quota: Vec<Quota>,
+        /// Shell command to run on an alert, with `CLASHRS_QUOTA` and `CLASHRS_USED` set
+        #[arg(long)]
+        alert_command: Option<String>,
+    },
+    /// Sum the recorded usage
+    Report {
+        /// Start date, e.g. `2026-10-01`. Defaults to the start of the month
+        #[arg(long)]
+        since: Option<String>,
+        /// What to sum by
+        #[arg(short, long, value_enum, default_value_t = UsageBy::Source)]
+        by: UsageBy,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct Profile {
+    #[command(subcommand)]
+    pub command: ProfileCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ProfileCommand {
+    /// List the profiles in the library, marking the active one
+    List,
+    /// Copy a profile file into the library
+    Add {
+        name: String,
+        path: String,
+        /// Replace an existing profile with the same name
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Remove a profile from the library
+    Remove {
+        name: String,
+    },
+    /// Load a profile from the library and make it active
+    Use {
+        name: String,
+        /// Re-apply the selector choices saved for this profile
+        #[arg(short, long)]
+        restore: bool,
+    },
+    /// Print the active profile and when it was loaded
+    Current,
+    /// Merge a base profile with overlays and print the result
+    Render {
+        base: String,
+        overlays: Vec<String>,
+        /// Write the merged profile to this file instead of printing it
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Fail if the merge reports any conflict
+        #[arg(long)]
+        strict: bool,
+        /// Load the written profile afterwards
+        #[arg(long, requires = "output")]
+        load: bool,
+    },
+}
+
+#[derive(Args, Debug)]
+pub struct Subscription {
+    #[command(subcommand)]
+    pub command: SubscriptionCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum SubscriptionCommand {
+    /// List subscriptions with their last reported usage
+    List,
+    /// Add a subscription
+    Add {
+        name: String,
+        url: String,
+        /// Extra request header, e.g. `-H "Authorization: Bearer xxx"`
+        #[arg(short = 'H', long = "header")]
+        headers: Vec<String>,
+    },
+    /// Remove a subscription and its downloaded profile
+    Remove {
+        name: String,
+    },
+    /// Download a subscription and load it
+    Update {
+        name: String,
+        /// Only write the profile, do not load it
+        #[arg(long)]
+        no_load: bool,
+    },
 }
 
 #[derive(Args, Debug)]
@@ -45,11 +288,96 @@ pub struct Connection {
 
 #[derive(Subcommand, Debug, Clone)]
 pub enum ConnectionCommand {
-    List,
+    /// List the connections
+    List {
+        /// Only list connections matching a filter, e.g. `host ~ "*.example.com" and upload > 10MB`
+        #[arg(short, long)]
+        filter: Option<String>,
+        #[command(flatten)]
+        columns: ColumnArgs,
+    },
     CloseAll,
+    /// Close a connection by ID, or every connection matching a filter
     Close {
-        id: String,
-    }
+        #[arg(required_unless_present = "filter", conflicts_with = "filter")]
+        id: Option<String>,
+        /// Close connections matching a filter, e.g. `chains contains "node-jp"`
+        #[arg(short, long)]
+        filter: Option<String>,
+        /// Only list the connections that would be closed
+        #[arg(long, requires = "filter")]
+        dry_run: bool,
+    },
+    /// Show live per-connection throughput, refreshing every interval
+    Top {
+        /// Time between refreshes, e.g. `2s`. Bare numbers are seconds
+        #[arg(short, long, default_value = "1")]
+        interval: String,
+        /// Order of the rows
+        #[arg(short, long, value_enum, default_value_t = SortKey::Rate)]
+        sort: SortKey,
+        /// Sum the rates per host instead of listing connections
+        #[arg(long)]
+        by_host: bool,
+        /// Number of rows to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Group the current connections and show their traffic
+    Summary {
+        /// What to group by
+        #[arg(short, long, value_enum, default_value_t = GroupBy::Host)]
+        by: GroupBy,
+        /// Number of top destinations to show per group
+        #[arg(short, long, default_value_t = 3)]
+        top: usize,
+    },
+    /// Record one line per finished connection to a JSON lines or CSV file
+    Record {
+        /// The file to append to
+        #[arg(short, long)]
+        output: String,
+        /// Format of the file. Defaults to CSV for `.csv` files and JSON lines otherwise
+        #[arg(long, value_enum)]
+        format: Option<RecordFormat>,
+        /// Time between snapshots of the connections, e.g. `2s`. Bare numbers are seconds
+        #[arg(short, long, default_value = "2")]
+        interval: String,
+        /// Time to record for, e.g. `1h`. Records until interrupted if not set
+        #[arg(short, long)]
+        duration: Option<String>,
+        /// Move the file aside once it reaches a size, e.g. `100MB`
+        #[arg(long)]
+        rotate_size: Option<String>,
+        /// Move the file aside after writing to it for a period, e.g. `1d`
+        #[arg(long)]
+        rotate_every: Option<String>,
+    },
+    /// Keep closing connections that violate any of the given policies
+    #[command(group = clap::ArgGroup::new("policy").required(true).multiple(true))]
+    Reap {
+        /// Close connections without traffic for this long, e.g. `10m`
+        #[arg(long, group = "policy")]
+        idle: Option<String>,
+        /// Close connections open for longer than this, e.g. `2h`
+        #[arg(long, group = "policy")]
+        max_age: Option<String>,
+        /// Close connections that transferred more than this, e.g. `1GB`
+        #[arg(long, group = "policy")]
+        max_bytes: Option<String>,
+        /// Close connections matching a filter. May be given more than once
+        #[arg(short, long, group = "policy")]
+        filter: Vec<String>,
+        /// Time between checks, e.g. `30s`
+        #[arg(short, long, default_value = "10s")]
+        interval: String,
+        /// Only log the connections that would be closed
+        #[arg(long)]
+        dry_run: bool,
+        /// Also append each reaped connection as JSON to this file
+        #[arg(long)]
+        log: Option<String>,
+    },
 }
 
 #[derive(Args, Debug)]
@@ -66,7 +394,10 @@ pub struct Proxy{
 #[derive(Subcommand, Debug, Clone)]
 pub enum ProxyCommand {
     /// List all proxis
-    List,
+    List {
+        #[command(flatten)]
+        columns: ColumnArgs,
+    },
     /// Check and print the delay to the specified url with a proxy
     Delay {
         proxy: String,
@@ -81,6 +412,15 @@ pub enum ProxyCommand {
     Change {
         proxy: String,
         new_proxy: String,
+        /// Close the connections still using the previous selection
+        #[arg(long)]
+        close_stale: bool,
+    },
+    /// Pick the proxy of a selector from a searchable list
+    #[cfg(feature = "tui")]
+    Pick {
+        /// The selector group, picked from a list when omitted
+        group: Option<String>,
     },
 }
 
@@ -121,6 +461,19 @@ pub enum ConfigCommand {
         mode: Option<ConfigMode>,
         #[arg(value_enum, short, long)]
         log_level: Option<ConfigLogLevel>,
+        /// Roll the patch back unless it is confirmed within this many seconds
+        #[arg(long)]
+        confirm_timeout: Option<u64>,
+        /// Proxy to run a delay test through before asking for confirmation
+        #[arg(long, requires = "confirm_timeout")]
+        check_proxy: Option<String>,
+        /// URL used by the delay test
+        #[arg(long, requires = "check_proxy")]
+        check_url: Option<String>,
+    },
+    /// Compare the running configuration with a profile file
+    Diff {
+        profile: String,
     },
 }
 
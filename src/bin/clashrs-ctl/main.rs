@@ -1,18 +1,36 @@
 use clap::Parser;
 use clashrsctl::{ClashRequestBuilder, ClashRequest};
 use tokio;
-use crate::output::CliOutput;
+use crate::output::{render, render_document, CliOutput, OutputFormat, StreamPrinter};
 use futures::StreamExt;
 
 mod cli;
 mod output;
+mod table;
+#[cfg(feature = "tui")]
+mod tui;
+
+fn data_dir(dir: Option<String>) -> std::path::PathBuf {
+    if let Some(dir) = dir {
+        return dir.into();
+    }
+
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(std::path::PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(".config")))
+        .unwrap_or_else(|| ".".into());
+    base.join("clashrs-ctl")
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = cli::Cli::parse();
 
     #[cfg(debug_assertions)]
-    println!("{:?}", cli);
+    eprintln!("{:?}", cli);
+
+    let data_dir = data_dir(cli.data_dir);
+    let format = cli.output;
 
     let mut client = ClashRequestBuilder::new();
     if cli.server.is_some() { client = client.ip(&cli.server.unwrap()); }
@@ -21,106 +39,613 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     use cli::Command;
     match cli.command {
-        Command::Rules => {
-            let res = client.rule().send().await?;
+        Command::Rules { columns } => {
+            use table::RuleRow;
 
-            // TODO: prettify output
-            // println!("{:?}", res);
-            res.print();
+            let mut res = client.rule().send().await?;
+            let mut rows: Vec<RuleRow> = res.rules.into_iter().enumerate().collect();
+            let selected = table::prepare(&mut rows, &columns, format)?;
+            match format {
+                OutputFormat::Json | OutputFormat::Yaml => {
+                    res.rules = rows.into_iter().map(|(_, rule)| rule).collect();
+                    render_document(&res, format)?;
+                }
+                format => table::print(&rows, &selected, format, table::color_enabled()),
+            }
+        }
+        Command::Rule(cli::Rule { command }) => {
+            use cli::RuleCommand;
+            use clashrsctl::matcher::{Matcher, Query};
+
+            match command {
+                RuleCommand::Test { target, src_ip, src_port, process, profile, resolve } => {
+                    let mut query = Query::parse(&target).ok_or(format!("Invalid target: {}", target))?;
+                    if let Some(ip) = src_ip { query = query.src_ip(ip) }
+                    if let Some(port) = src_port { query = query.src_port(port) }
+                    if let Some(process) = process { query = query.process(&process) }
+                    if let (true, Some(host), None) = (resolve, query.host.clone(), query.ip) {
+                        let answer = client.clone().dns().query(&host, clashrsctl::dns::DnsType::A).send().await?;
+                        if let Some(ip) = answer.addresses().first() {
+                            query = query.ip(*ip);
+                        }
+                    }
+
+                    let matcher = match profile {
+                        Some(path) => {
+                            let profile = clashrsctl::profile::Profile::from_file(&path)?;
+                            let rules: Vec<_> = profile.rules.iter().cloned().zip(profile.typed_rules()).collect();
+                            Matcher::from_parsed(&rules)
+                        }
+                        None => Matcher::new(&client.rule().send().await?),
+                    };
+                    for invalid in matcher.invalid() {
+                        eprintln!("Warning: {}", invalid);
+                    }
+                    render_document(&matcher.evaluate(&query), format)?;
+                }
+                RuleCommand::Lint { profile } => {
+                    use std::collections::BTreeSet;
+                    use clashrsctl::rule::kind::TypedRule;
+
+                    let (rules, policies): (Vec<_>, BTreeSet<String>) = match profile {
+                        Some(path) => {
+                            let profile = clashrsctl::profile::Profile::from_file(&path)?;
+                            let policies = profile.proxy_names()
+                                .chain(profile.group_names())
+                                .chain(clashrsctl::proxy::BUILTIN_PROXIES)
+                                .map(str::to_owned)
+                                .collect();
+                            let rules = profile.rules.iter().map(|line| (line.clone(), TypedRule::parse_line(line))).collect();
+                            (rules, policies)
+                        }
+                        None => {
+                            let rules = client.clone().rule().send().await?;
+                            let proxies = client.proxies().send().await?;
+                            (rules.iter().map(|rule| (rule.to_line(), TypedRule::try_from(rule))).collect(), proxies.iter().map(|(name, _)| name.clone()).collect())
+                        }
+                    };
+
+                    let findings = clashrsctl::lint::lint(&rules, Some(&policies));
+                    match format {
+                        OutputFormat::Text => {
+                            findings.print();
+                            println!("{} finding(s) in {} rules", findings.len(), rules.len());
+                        }
+                        format => render(&findings, format)?,
+                    }
+                }
+                RuleCommand::Hits { duration, interval, logs } => {
+                    use std::time::Duration;
+                    use clashrsctl::{filter::parse_duration, hits::HitCollector};
+
+                    let parse = |s: &str| parse_duration(s).ok_or_else(|| format!("Invalid duration: {}", s));
+                    let duration = parse(&duration)?;
+                    let interval = parse(&interval)?.max(Duration::from_secs(1));
+
+                    let rules = client.clone().rule().send().await?;
+                    let mut collector = HitCollector::new(&rules);
+                    if logs {
+                        collector.consume_logs(client, duration).await?;
+                    } else {
+                        collector.sample_connections(client, duration, interval).await?;
+                    }
+
+                    let report = collector.report();
+                    render(&report, format)?;
+                }
+                RuleCommand::Simulate { history, profile } => {
+                    let records: Vec<clashrsctl::history::ConnectionRecord> = clashrsctl::jsonl::read(&history)?;
+                    let profile = clashrsctl::profile::Profile::from_file(&profile)?;
+                    let rules: Vec<_> = profile.rules.iter().cloned().zip(profile.typed_rules()).collect();
+                    let matcher = Matcher::from_parsed(&rules);
+                    for invalid in matcher.invalid() {
+                        eprintln!("Warning: {}", invalid);
+                    }
+
+                    let simulation = clashrsctl::simulate::simulate(&records, &matcher);
+                    render_document(&simulation, format)?;
+                }
+            }
         }
         Command::Config(cli::Config{ command }) => {
             use cli::ConfigCommand;
+            let base = client.clone();
             let client = client.config();
 
             match command {
                 ConfigCommand::List => {
                     let res = client.get().send().await?;
-
-                    // TODO: prettify output
-                    // println!("{:?}", res);
-                    res.print()
+                    render(&res, format)?
                 }
                 ConfigCommand::Load { path } => {
                     client.load(&path).send().await?
                 }
-                ConfigCommand::Patch { port, socks_port, redir_port, tproxy_port, mixed_port, ipv6, bind_address, allow_lan, mode, log_level } => {
-                    let mut client = client.patch();
+                ConfigCommand::Patch { port, socks_port, redir_port, tproxy_port, mixed_port, ipv6, bind_address, allow_lan, mode, log_level, confirm_timeout, check_proxy, check_url } => {
+                    let mut config = clashrsctl::config::Config::new();
 
-                    if let Some(port) = port { client = client.port(port) }
-                    if let Some(socks_port) = socks_port { client = client.socks_port(socks_port) }
-                    if let Some(redir_port) = redir_port { client = client.redir_port(redir_port) }
-                    if let Some(tproxy_port) = tproxy_port { client = client.tproxy_port(tproxy_port) }
-                    if let Some(mixed_port) = mixed_port { client = client.mixed_port(mixed_port) }
-                    if let Some(ipv6) = ipv6 { client = client.ipv6(ipv6) }
-                    if let Some(address) = bind_address { client = client.bind_address(&address) }
-                    if let Some(allow_lan) = allow_lan { client = client.allow_lan(allow_lan) }
-                    if let Some(mode) = mode { client = client.mode(mode) }
-                    if let Some(level) = log_level { client = client.log_level(level) }
+                    if let Some(port) = port { config = config.port(port) }
+                    if let Some(socks_port) = socks_port { config = config.socks_port(socks_port) }
+                    if let Some(redir_port) = redir_port { config = config.redir_port(redir_port) }
+                    if let Some(tproxy_port) = tproxy_port { config = config.tproxy_port(tproxy_port) }
+                    if let Some(mixed_port) = mixed_port { config = config.mixed_port(mixed_port) }
+                    if let Some(ipv6) = ipv6 { config = config.ipv6(ipv6) }
+                    if let Some(address) = bind_address { config = config.bind_address(&address) }
+                    if let Some(allow_lan) = allow_lan { config = config.allow_lan(allow_lan) }
+                    if let Some(mode) = mode { config = config.mode(mode) }
+                    if let Some(level) = log_level { config = config.log_level(level) }
 
-                    client.send().await?
+                    match confirm_timeout {
+                        None => client.patch().config(config).send().await?,
+                        Some(secs) => {
+                            use clashrsctl::confirm::{self, Change, HealthCheck, Outcome};
+
+                            let mut check = HealthCheck::new();
+                            if let Some(proxy) = check_proxy { check = check.proxy(&proxy) }
+                            if let Some(url) = check_url { check = check.url(&url) }
+
+                            let pending = confirm::apply(base, Change::Patch(config), &check).await?;
+                            eprintln!("Patch applied. Type 'yes' within {} seconds to keep it.", secs);
+
+                            // A detached thread, so that a pending read does not keep the
+                            // runtime alive after the rollback.
+                            let (tx, rx) = futures::channel::oneshot::channel();
+                            std::thread::spawn(move || {
+                                let mut line = String::new();
+                                let _ = tx.send(std::io::stdin().read_line(&mut line).is_ok() && line.trim() == "yes");
+                            });
+                            let confirmation = async { rx.await.unwrap_or(false) };
+
+                            let outcome = pending.confirm_within(std::time::Duration::from_secs(secs), confirmation).await?;
+                            render_document(&output::ConfirmReport { confirmed: outcome == Outcome::Confirmed }, format)?;
+                        }
+                    }
+                }
+                ConfigCommand::Diff { profile } => {
+                    let profile = clashrsctl::profile::Profile::from_file(&profile)?;
+                    let config = client.get().send().await?;
+                    let rules = base.clone().rule().send().await?;
+                    let proxies = base.proxies().send().await?;
+
+                    let diff = clashrsctl::diff::ConfigDiff::new(&profile, &config, &rules, &proxies);
+                    render_document(&diff, format)?;
                 }
             }
         }
         Command::Proxy(cli::Proxy{ command }) => {
             use cli::ProxyCommand;
 
+            let base = client.clone();
             let client = client.proxies();
             match command {
-                ProxyCommand::List => {
-                    let res = client.send().await?;
+                ProxyCommand::List { columns } => {
+                    use table::ProxyRow;
 
-                    // TODO: prettify output
-                    // println!("{:?}", res);
-                    res.print();
+                    let res = client.send().await?;
+                    let mut rows: Vec<ProxyRow> = res.iter().map(|(name, proxy)| (name.clone(), proxy.clone())).collect();
+                    let selected = table::prepare(&mut rows, &columns, format)?;
+                    match format {
+                        OutputFormat::Json | OutputFormat::Yaml => render_document(&res, format)?,
+                        format => table::print(&rows, &selected, format, table::color_enabled()),
+                    }
                 }
                 ProxyCommand::Info { proxy } => {
                     let res = client.get(&proxy).send().await?;
-
-                    // TODO: prettify output
-                    // println!("{:?}", res);
-                    res.print()
+                    render(&res, format)?
                 }
                 ProxyCommand::Delay { proxy, url, timeout } => {
-                    let clashrsctl::proxy::ProxyDelay { delay } = client.get(&proxy).delay(&url, timeout).send().await?;
-
-                    println!("{} ms", delay);
+                    let res = client.get(&proxy).delay(&url, timeout).send().await?;
+                    render(&res, format)?
                 }
-                ProxyCommand::Change { proxy, new_proxy } => {
+                ProxyCommand::Change { proxy, new_proxy, close_stale: false } => {
                     client.get(&proxy).change(&new_proxy).send().await?;
                 }
+                ProxyCommand::Change { proxy, new_proxy, close_stale: true } => {
+                    let results = clashrsctl::proxy::change_and_close_stale(base, &proxy, &new_proxy).await?;
+                    render(&output::CloseReport::new(results), format)?
+                }
+                #[cfg(feature = "tui")]
+                ProxyCommand::Pick { group } => {
+                    use tui::picker::{self, Choice};
+                    use std::io::IsTerminal;
+
+                    // Without a full terminal, prompt on stderr so stdout stays clean.
+                    let interactive = std::io::stdin().is_terminal()
+                        && std::io::stdout().is_terminal()
+                        && std::env::var("TERM").map_or(true, |term| term != "dumb");
+                    let choose = |title: &str, choices: Vec<Choice>| -> Result<Option<String>, Box<dyn std::error::Error>> {
+                        match interactive {
+                            true => picker::pick(title, choices),
+                            false => Ok(picker::pick_numbered(title, &choices, std::io::stdin().lock(), std::io::stderr())?),
+                        }
+                    };
+
+                    let proxies = base.proxies().send().await?;
+                    let group = match group {
+                        Some(group) => group,
+                        None => match choose("Selector group", picker::group_choices(&proxies))? {
+                            Some(group) => group,
+                            None => return Ok(()),
+                        },
+                    };
+                    let members = picker::member_choices(&proxies, &group).ok_or_else(|| format!("{} is not a selector group", group))?;
+                    if let Some(proxy) = choose(&group, members)? {
+                        client.get(&group).change(&proxy).send().await?;
+                        render_document(&output::Pick { group, proxy }, format)?;
+                    }
+                }
             }
         }
-        Command::Version => {
-            let version = client.version()
-                .send()
-                .await?;
+        Command::Version { capabilities } => {
+            if capabilities {
+                render_document(&clashrsctl::capability::Capabilities::probe(client).await?, format)?;
+            } else {
+                let version = client.version()
+                    .send()
+                    .await?;
 
-            println!("{}", version.version);
+                render(&version, format)?;
+            }
         }
         Command::Traffic => {
             let mut traffic_stream = client.traffic().send().await?;
+            let mut printer = StreamPrinter::new(format);
 
             while let Some(res) = traffic_stream.next().await {
-                res?.print();
+                printer.print(&res?)?;
             }
 
-            println!("Disconnected");
+            printer.finish();
         }
+        #[cfg(feature = "tui")]
+        Command::Tui => tui::run(client).await?,
         Command::Log => {
             let mut log_stream = client.logs().send().await?;
+            let mut printer = StreamPrinter::new(format);
 
             while let Some(res) = log_stream.next().await {
-                res?.print();
+                printer.print(&res?)?;
             }
 
-            println!("Disconnected");
+            printer.finish();
         }
         Command::Connection(cli::Connection { command}) => {
             use cli::ConnectionCommand;
+            use clashrsctl::filter::Filter;
+
             match command {
-                ConnectionCommand::List => client.connections().send().await?.print(),
+                ConnectionCommand::List { filter, columns } => {
+                    let mut connections = client.connections().send().await?;
+                    if let Some(filter) = filter {
+                        let filter = Filter::parse(&filter)?;
+                        let now = std::time::SystemTime::now();
+                        connections.connections.retain(|c| filter.matches(c, now));
+                    }
+                    let selected = table::prepare(&mut connections.connections, &columns, format)?;
+                    match format {
+                        OutputFormat::Json | OutputFormat::Yaml => render_document(&connections, format)?,
+                        OutputFormat::Text => output::print_connections(&connections, &selected),
+                        format => table::print(&connections.connections, &selected, format, table::color_enabled()),
+                    }
+                }
                 ConnectionCommand::CloseAll => client.connections().close().send().await?,
-                ConnectionCommand::Close { id } => client.connections().close_id(&id).send().await?,
+                ConnectionCommand::Close { id: Some(id), .. } => client.connections().close_id(&id).send().await?,
+                ConnectionCommand::Close { id: None, filter, dry_run } => {
+                    let filter = Filter::parse(&filter.unwrap_or_default())?;
+                    let now = std::time::SystemTime::now();
+                    let connections = client.clone().connections().send().await?;
+                    let matched: Vec<_> = connections.connections.into_iter().filter(|c| filter.matches(c, now)).collect();
+
+                    match (dry_run, format) {
+                        (true, OutputFormat::Text) => {
+                            matched.print();
+                            println!("{} connection(s) would be closed", matched.len());
+                        }
+                        (true, format) => render(&matched, format)?,
+                        (false, format) => {
+                            let results = client.connections().close_ids(matched.iter().map(|c| &c.id)).await;
+                            render(&output::CloseReport::new(results), format)?
+                        }
+                    }
+                }
+                ConnectionCommand::Top { interval, sort, by_host, limit } => {
+                    use std::time::{Duration, Instant};
+                    use clashrsctl::{filter::parse_duration, rate::RateSnapshot};
+
+                    let interval = parse_duration(&interval)
+                        .ok_or_else(|| format!("Invalid duration: {}", interval))?
+                        .max(Duration::from_secs(1));
+                    let mut prev = client.clone().connections().send().await?;
+                    let mut taken = Instant::now();
+                    let mut printer = StreamPrinter::new(format);
+                    loop {
+                        tokio::time::sleep(interval).await;
+                        let next = client.clone().connections().send().await?;
+                        let mut rates = RateSnapshot::between(&prev, &next, taken.elapsed());
+                        taken = Instant::now();
+                        prev = next;
+                        rates.sort(sort);
+
+                        match (format, by_host) {
+                            (OutputFormat::Text, by_host) => {
+                                // Clear the screen and move the cursor home.
+                                print!("\x1b[2J\x1b[H");
+                                match by_host {
+                                    true => output::print_host_rates(&rates, limit),
+                                    false => output::print_connection_rates(&rates, limit),
+                                }
+                            }
+                            (_, true) => printer.print(&rates.by_host().into_iter().take(limit).collect::<Vec<_>>())?,
+                            (_, false) => {
+                                rates.connections.truncate(limit);
+                                printer.print(&rates)?
+                            }
+                        }
+                    }
+                }
+                ConnectionCommand::Summary { by, top } => {
+                    let groups = client.connections().send().await?.group_by(by, top);
+                    match format {
+                        OutputFormat::Text => {
+                            println!("CONNS\tUP\tDOWN\tGROUP");
+                            groups.print();
+                        }
+                        format => render(&groups, format)?,
+                    }
+                }
+                ConnectionCommand::Record { output, format, interval, duration, rotate_size, rotate_every } => {
+                    use clashrsctl::filter::{parse_duration, parse_size};
+                    use clashrsctl::history::{ConnectionTracker, RecordFormat, RecordWriter};
+
+                    let parse = |s: &str| parse_duration(s).ok_or_else(|| format!("Invalid duration: {}", s));
+                    let interval = parse(&interval)?.max(std::time::Duration::from_secs(1));
+                    let duration = duration.as_deref().map(parse).transpose()?;
+
+                    let mut writer = RecordWriter::new(&output, format.unwrap_or_else(|| RecordFormat::from_path(&output)));
+                    if let Some(size) = rotate_size {
+                        writer = writer.rotate_size(parse_size(&size).ok_or_else(|| format!("Invalid size: {}", size))?);
+                    }
+                    if let Some(period) = rotate_every {
+                        writer = writer.rotate_every(parse(&period)?);
+                    }
+                    let stop = async {
+                        let _ = tokio::signal::ctrl_c().await;
+                    };
+                    ConnectionTracker::new()
+                        .record(client, writer, interval, duration, stop, |e| eprintln!("Failed to poll connections: {}", e))
+                        .await?
+                }
+                ConnectionCommand::Reap { idle, max_age, max_bytes, filter, interval, dry_run, log } => {
+                    use std::io::Write;
+                    use clashrsctl::filter::{parse_duration, parse_size};
+                    use clashrsctl::reap::{Policy, Reaper};
+
+                    let duration = |s: &str| parse_duration(s).ok_or_else(|| format!("Invalid duration: {}", s));
+                    let mut policies = Vec::new();
+                    if let Some(idle) = idle {
+                        policies.push(Policy::Idle(duration(&idle)?));
+                    }
+                    if let Some(max_age) = max_age {
+                        policies.push(Policy::MaxAge(duration(&max_age)?));
+                    }
+                    if let Some(max_bytes) = max_bytes {
+                        policies.push(Policy::MaxBytes(parse_size(&max_bytes).ok_or_else(|| format!("Invalid size: {}", max_bytes))?));
+                    }
+                    for filter in filter.iter() {
+                        policies.push(Policy::filter(filter)?);
+                    }
+                    let interval = duration(&interval)?.max(std::time::Duration::from_secs(1));
+
+                    let mut log = match log {
+                        Some(path) => Some(std::fs::OpenOptions::new().create(true).append(true).open(path)?),
+                        None => None,
+                    };
+                    Reaper::new(policies)
+                        .run(client, interval, dry_run, |reaped| {
+                            let action = if reaped.dry_run { "would close" } else { "closed" };
+                            match reaped.error.as_ref() {
+                                Some(e) => eprintln!("{}: failed to close ({}): {}", reaped.id, reaped.policy, e),
+                                None => println!("{} {} {} ({}), {}", action, reaped.id, reaped.host, reaped.process, reaped.policy),
+                            }
+                            if let Some(file) = log.as_mut() {
+                                let line = serde_json::to_string(reaped).unwrap_or_default();
+                                if let Err(e) = writeln!(file, "{}", line) {
+                                    eprintln!("Failed to write the log: {}", e);
+                                }
+                            }
+                        }, |e| eprintln!("Failed to poll connections: {}", e))
+                        .await?
+                }
+            }
+        }
+        Command::Subscription(cli::Subscription { command }) => {
+            use cli::SubscriptionCommand;
+            use clashrsctl::subscription::{Subscription, SubscriptionError, SubscriptionStore};
+
+            let mut store = SubscriptionStore::open(data_dir.join("subscriptions.json"))?;
+            let profile_path = |name: &str| data_dir.join("subscriptions").join(format!("{}.yaml", name));
+
+            match command {
+                SubscriptionCommand::List => render(&store.iter().cloned().collect::<Vec<_>>(), format)?,
+                SubscriptionCommand::Add { name, url, headers } => {
+                    let mut subscription = Subscription::new(&name, &url);
+                    for header in headers.iter() {
+                        match header.split_once(':') {
+                            Some((key, value)) => subscription = subscription.header(key.trim(), value.trim()),
+                            None => return Err(format!("Invalid header: {}", header).into()),
+                        }
+                    }
+                    store.add(subscription)?;
+                    store.save()?;
+                }
+                SubscriptionCommand::Remove { name } => {
+                    store.remove(&name)?;
+                    store.save()?;
+                    let _ = std::fs::remove_file(profile_path(&name));
+                }
+                SubscriptionCommand::Update { name, no_load } => {
+                    let path = profile_path(&name);
+                    std::fs::create_dir_all(data_dir.join("subscriptions"))?;
+
+                    let subscription = store.get_mut(&name).ok_or(SubscriptionError::NotFound(name.clone()))?;
+                    let profile = subscription.update(&path).await?;
+                    let update = output::SubscriptionUpdate {
+                        subscription: subscription.clone(),
+                        proxies: profile.proxies.len(),
+                        path: path.display().to_string(),
+                    };
+                    store.save()?;
+
+                    render_document(&update, format)?;
+                    if !no_load {
+                        client.config().load(clashrsctl::file::path_str(&path)?).send().await?;
+                    }
+                }
+            }
+        }
+        Command::Core(cli::Core { command }) => {
+            use cli::CoreCommand;
+            let capabilities = clashrsctl::capability::Capabilities::detect(client.clone()).await?;
+            match command {
+                CoreCommand::Restart => capabilities.send(client.core().restart()).await?,
+                CoreCommand::UpdateGeo => capabilities.send(client.core().update_geo()).await?,
+                CoreCommand::Memory => {
+                    let mut memory_stream = capabilities.send(client.core().memory()).await?;
+                    let mut printer = StreamPrinter::new(format);
+
+                    while let Some(res) = memory_stream.next().await {
+                        printer.print(&res?)?;
+                    }
+
+                    printer.finish();
+                }
+            }
+        }
+        Command::Dns(cli::Dns { command }) => {
+            use cli::DnsCommand;
+            let capabilities = clashrsctl::capability::Capabilities::detect(client.clone()).await?;
+            match command {
+                DnsCommand::FlushFakeip => capabilities.send(client.dns().flush_fakeip()).await?,
+                DnsCommand::Query { host, record_type } => {
+                    render_document(&capabilities.send(client.dns().query(&host, record_type)).await?, format)?
+                }
+            }
+        }
+        Command::Usage(cli::Usage { file, command }) => {
+            use cli::UsageCommand;
+            use clashrsctl::usage::{self, QuotaMonitor, UsageTracker};
+
+            let path = file.map_or_else(|| data_dir.join("usage.jsonl"), std::path::PathBuf::from);
+            let history = match path.exists() {
+                true => clashrsctl::jsonl::read(&path)?,
+                false => Vec::new(),
+            };
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+
+            match command {
+                UsageCommand::Record { interval, quota, alert_command } => {
+                    let interval = clashrsctl::filter::parse_duration(&interval)
+                        .ok_or_else(|| format!("Invalid duration: {}", interval))?
+                        .max(std::time::Duration::from_secs(1));
+                    if let Some(parent) = path.parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+
+                    let mut monitor = QuotaMonitor::new(quota, &history, now);
+                    UsageTracker::new()
+                        .record(client, &path, interval, |records| {
+                            let now = records.first().map_or(now, |r| r.time);
+                            for alert in monitor.add(records, now) {
+                                eprintln!(
+                                    "Quota exceeded: {} {} used {} of {} this month",
+                                    alert.quota.by,
+                                    alert.quota.key,
+                                    output::format_bytes(alert.used as f64),
+                                    output::format_bytes(alert.quota.limit as f64)
+                                );
+                                if let Some(command) = alert_command.as_ref() {
+                                    let mut alert_process = std::process::Command::new("sh");
+                                    alert_process
+                                        .arg("-c")
+                                        .arg(command)
+                                        .env("CLASHRS_QUOTA", alert.quota.to_string())
+                                        .env("CLASHRS_USED", alert.used.to_string());
+                                    tokio::task::spawn_blocking(move || {
+                                        if let Err(e) = alert_process.status() {
+                                            eprintln!("Failed to run the alert command: {}", e);
+                                        }
+                                    });
+                                }
+                            }
+                        }, |e| eprintln!("Failed to record usage: {}", e))
+                        .await?
+                }
+                UsageCommand::Report { since, by } => {
+                    let since = match since {
+                        Some(since) => usage::parse_date(&since).ok_or_else(|| format!("Invalid date: {}", since))?,
+                        None => usage::month_start(now),
+                    };
+                    let totals = usage::report(&history, by, since);
+                    match format {
+                        OutputFormat::Text => {
+                            println!("UP\tDOWN\tTOTAL\t{}", by.to_string().to_uppercase());
+                            totals.print();
+                        }
+                        format => render(&totals, format)?,
+                    }
+                }
+            }
+        }
+        Command::Profile(cli::Profile { command }) => {
+            use cli::ProfileCommand;
+            use clashrsctl::{library::ProfileLibrary, overlay::{self, Overlay}, profile::Profile};
+
+            let library = ProfileLibrary::new(data_dir.join("profiles"));
+            match command {
+                ProfileCommand::List => {
+                    let active = library.state()?.active;
+                    let profiles: Vec<output::ProfileEntry> = library
+                        .list()?
+                        .into_iter()
+                        .map(|name| output::ProfileEntry { active: active.as_ref() == Some(&name), name })
+                        .collect();
+                    render(&profiles, format)?
+                }
+                ProfileCommand::Add { name, path, force } => library.add(&name, &path, force)?,
+                ProfileCommand::Remove { name } => library.remove(&name)?,
+                ProfileCommand::Use { name, restore } => {
+                    let report = library.use_profile(&name, client, restore).await?;
+                    let switch = output::ProfileSwitch {
+                        profile: name,
+                        restored: report.restored.into_iter().collect(),
+                        failed: report.failed.into_iter().collect(),
+                    };
+                    render_document(&switch, format)?;
+                }
+                ProfileCommand::Current => render_document(&library.state()?, format)?,
+                ProfileCommand::Render { base, overlays, output, strict, load } => {
+                    let base = Profile::from_file(&base)?;
+                    let overlays = overlays.iter().map(Overlay::from_file).collect::<Result<Vec<_>, _>>()?;
+                    let merged = overlay::merge(&base, &overlays)?;
+
+                    for conflict in merged.conflicts.iter() {
+                        eprintln!("conflict: {}", conflict);
+                    }
+                    if strict && !merged.conflicts.is_empty() {
+                        return Err(format!("{} conflicts", merged.conflicts.len()).into());
+                    }
+
+                    match output {
+                        Some(path) => {
+                            merged.profile.save(&path)?;
+                            if load {
+                                client.config().load(&path).send().await?;
+                            }
+                        }
+                        None => print!("{}", merged.profile.to_yaml()?),
+                    }
+                }
             }
         }
     }
@@ -1,17 +1,175 @@
-use std::borrow::Borrow;
+use std::collections::BTreeMap;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::table::{self, Columns, ProxyRow, RuleRow};
 
 use clashrsctl::{
     config::{Config, ConfigLogLevel, ConfigMode},
-    proxy::{ProxyInfo, ProxyList},
+    proxy::{ProxyDelay, ProxyInfo, ProxyList},
     rule::{Rule, RuleList},
     stream::log::Log,
     stream::traffic::Traffic, connection::{ConnectionVec, Connection},
+    version::Version,
+    diff::{ConfigDiff, IndexedRule, NameDiff},
+    subscription::Subscription,
+    library::LibraryState,
+    clash_core::Memory,
+    dns::DnsAnswer,
+    capability::{Capabilities, Feature},
+    matcher::Evaluation,
+    lint::Finding,
+    hits::HitReport,
+    simulate::Simulation,
+    rate::{HostRate, RateSnapshot},
+    summary::ConnectionGroup,
+    usage::UsageTotal,
 };
 
 pub trait CliOutput {
     fn print(&self);
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// Pretty JSON; streams print one JSON object per line
+    Json,
+    Yaml,
+    /// Aligned columns
+    Table,
+    /// Tab-separated columns with a header
+    Tsv,
+}
+
+/// Columns of a response for the `table` and `tsv` formats.
+pub trait Tabular {
+    fn header() -> &'static [&'static str];
+    fn rows(&self) -> Vec<Vec<String>>;
+}
+
+impl<T: CliOutput> CliOutput for Vec<T> {
+    fn print(&self) {
+        for item in self.iter() {
+            item.print();
+        }
+    }
+}
+
+impl<T: Tabular> Tabular for Vec<T> {
+    fn header() -> &'static [&'static str] {
+        T::header()
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.iter().flat_map(|item| item.rows()).collect()
+    }
+}
+
+fn tsv_cell(cell: &str) -> String {
+    cell.replace(['\t', '\n', '\r'], " ")
+}
+
+fn print_tsv(header: Option<&[&str]>, rows: &[Vec<String>]) {
+    if let Some(header) = header {
+        println!("{}", header.join("\t"));
+    }
+    for row in rows {
+        println!("{}", row.iter().map(|cell| tsv_cell(cell)).collect::<Vec<_>>().join("\t"));
+    }
+}
+
+fn print_table(header: &[&str], rows: &[Vec<String>]) {
+    let rows: Vec<Vec<String>> = std::iter::once(header.iter().map(|h| h.to_string()).collect())
+        .chain(rows.iter().map(|row| row.iter().map(|cell| tsv_cell(cell)).collect()))
+        .collect();
+    let mut widths = vec![0; header.len()];
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    for row in rows.iter() {
+        let cells: Vec<String> = row.iter().zip(widths.iter()).map(|(cell, width)| format!("{:<1$}", cell, width)).collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
+}
+
+/// Print `value` in `format`.
+pub fn render<T>(value: &T, format: OutputFormat) -> Result<(), Box<dyn std::error::Error>>
+where
+    T: CliOutput + Tabular + Serialize,
+{
+    match format {
+        OutputFormat::Table => print_table(T::header(), &value.rows()),
+        OutputFormat::Tsv => print_tsv(Some(T::header()), &value.rows()),
+        _ => return render_document(value, format),
+    }
+    Ok(())
+}
+
+/// Print a response without a tabular form, falling back to text for
+/// `table` and `tsv`.
+pub fn render_document<T>(value: &T, format: OutputFormat) -> Result<(), Box<dyn std::error::Error>>
+where
+    T: CliOutput + Serialize,
+{
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(value)?),
+        OutputFormat::Yaml => print!("{}", serde_yaml::to_string(value)?),
+        _ => value.print(),
+    }
+    Ok(())
+}
+
+/// Prints the items of a stream as they arrive: one JSON object per line
+/// in `json`, one document each in `yaml`, and rows under a header printed
+/// once in `table` and `tsv`.
+pub struct StreamPrinter {
+    format: OutputFormat,
+    started: bool,
+}
+
+impl StreamPrinter {
+    pub fn new(format: OutputFormat) -> Self {
+        Self { format, started: false }
+    }
+
+    pub fn print<T>(&mut self, item: &T) -> Result<(), Box<dyn std::error::Error>>
+    where
+        T: CliOutput + Tabular + Serialize,
+    {
+        let started = std::mem::replace(&mut self.started, true);
+        match self.format {
+            OutputFormat::Text => item.print(),
+            OutputFormat::Json => println!("{}", serde_json::to_string(item)?),
+            OutputFormat::Yaml => print!("---\n{}", serde_yaml::to_string(item)?),
+            // Later rows are unknown, so tables cannot be aligned.
+            OutputFormat::Table | OutputFormat::Tsv => print_tsv((!started).then(T::header), &item.rows()),
+        }
+        Ok(())
+    }
+
+    /// Report the end of the stream, in text only.
+    pub fn finish(&self) {
+        if self.format == OutputFormat::Text {
+            println!("Disconnected");
+        }
+    }
+}
+
+/// A JSON value as a table cell, without the quotes of strings.
+fn cell(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => "".to_owned(),
+        serde_json::Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
 impl CliOutput for Rule {
     fn print(&self) {
         println!("{},\t{},\t{}", self.r#type, self.payload, self.proxy);
@@ -20,44 +178,23 @@ impl CliOutput for Rule {
 
 impl CliOutput for RuleList {
     fn print(&self) {
-        println!("TYPE,\tPAYLOAD,\tPROXY");
-        for rule in self.iter() {
-            rule.print();
-        }
+        let rows: Vec<RuleRow> = self.rules.iter().cloned().enumerate().collect();
+        table::print(&rows, RuleRow::DEFAULT, OutputFormat::Text, table::color_enabled());
     }
 }
 
 impl CliOutput for Config {
     fn print(&self) {
-        println!("HTTP port: {}", self.port.as_ref().unwrap_or_else(|| &0));
-        println!(
-            "SOCKS port: {}",
-            self.socks_port.as_ref().unwrap_or_else(|| &0)
-        );
-        println!(
-            "REDIR port: {}",
-            self.redir_port.as_ref().unwrap_or_else(|| &0)
-        );
-        println!(
-            "TPROXY port: {}",
-            self.tproxy_port.as_ref().unwrap_or_else(|| &0)
-        );
-        println!(
-            "MIXED port: {}",
-            self.mixed_port.as_ref().unwrap_or_else(|| &0)
-        );
-        println!(
-            "Allow LAN: {}",
-            self.allow_lan.as_ref().unwrap_or_else(|| &false)
-        );
-        println!("IPv6: {}", self.ipv6.as_ref().unwrap_or_else(|| &false));
-        println!(
-            "Bind Address: {}",
-            self.bind_address
-                .as_ref()
-                .map(|s| s.borrow())
-                .unwrap_or_else(|| "*")
-        );
+        let port = |port: Option<u16>| port.map_or("-".to_owned(), |p| p.to_string());
+        let flag = |flag: Option<bool>| flag.map_or("-".to_owned(), |f| f.to_string());
+        println!("HTTP port: {}", port(self.port));
+        println!("SOCKS port: {}", port(self.socks_port));
+        println!("REDIR port: {}", port(self.redir_port));
+        println!("TPROXY port: {}", port(self.tproxy_port));
+        println!("MIXED port: {}", port(self.mixed_port));
+        println!("Allow LAN: {}", flag(self.allow_lan));
+        println!("IPv6: {}", flag(self.ipv6));
+        println!("Bind Address: {}", self.bind_address.as_deref().unwrap_or("*"));
         println!(
             "Mode: {}",
             self.mode
@@ -67,7 +204,7 @@ impl CliOutput for Config {
                     ConfigMode::Rule => "Rule",
                     ConfigMode::Direct => "Direct",
                 })
-                .unwrap_or_else(|| "None")
+                .unwrap_or("-")
         );
         println!(
             "Log level: {}",
@@ -79,7 +216,7 @@ impl CliOutput for Config {
                     ConfigLogLevel::Error => "Error",
                     ConfigLogLevel::Debug => "Debug",
                 })
-                .unwrap_or_else(|| "None")
+                .unwrap_or("-")
         );
     }
 }
@@ -105,15 +242,14 @@ impl CliOutput for ProxyInfo {
 
 impl CliOutput for ProxyList {
     fn print(&self) {
-        for (name, map) in self.iter() {
-            println!("{}: {}", name, map["type"]);
-        }
+        let rows: Vec<ProxyRow> = self.iter().map(|(name, proxy)| (name.clone(), proxy.clone())).collect();
+        table::print(&rows, ProxyRow::DEFAULT, OutputFormat::Text, table::color_enabled());
     }
 }
 
 impl CliOutput for Traffic {
     fn print(&self) {
-        println!("up:{}, down:{}", self.up, self.down);
+        println!("up: {}/s, down: {}/s", format_bytes(self.up as f64), format_bytes(self.down as f64));
     }
 }
 
@@ -132,18 +268,703 @@ impl CliOutput for Log {
 
 impl CliOutput for ConnectionVec {
     fn print(&self) {
-        println!("Total upload: {}", self.upload_total);
-        println!("Total download: {}", self.download_total);
+        print_connections(self, Connection::DEFAULT);
+    }
+}
 
-        println!("ID\tType");
-        for connection in self.connections.iter() {
-            <Connection as CliOutput>::print(&connection);
+/// The totals, then a table of the connections with `columns`.
+pub fn print_connections(connections: &ConnectionVec, columns: &[&str]) {
+    println!(
+        "Total upload: {}, download: {}",
+        format_bytes(connections.upload_total as f64),
+        format_bytes(connections.download_total as f64)
+    );
+    table::print(&connections.connections, columns, OutputFormat::Text, table::color_enabled());
+}
+
+impl CliOutput for Connection {
+    fn print(&self) {
+        let age = self.age().map_or("-".to_owned(), format_age);
+        println!("{}\t{}\t{}", self.id, age, self.metadata.r#type);
+    }
+}
+
+impl CliOutput for IndexedRule {
+    fn print(&self) {
+        println!("#{}\t{},\t{},\t{}", self.index, self.rule.r#type, self.rule.payload, self.rule.proxy);
+    }
+}
+
+impl CliOutput for NameDiff {
+    fn print(&self) {
+        for name in self.added.iter() {
+            println!("+ {}", name);
+        }
+        for name in self.removed.iter() {
+            println!("- {}", name);
         }
     }
 }
 
-impl CliOutput for Connection {
+impl CliOutput for ConfigDiff {
     fn print(&self) {
-        println!("{}\t{}", self.id, self.metadata.r#type);
+        if self.is_empty() {
+            println!("No difference");
+            return;
+        }
+
+        if !self.general.is_empty() {
+            println!("General:");
+            for change in self.general.iter() {
+                println!("~ {}: {} -> {}",
+                         change.key,
+                         change.profile.as_deref().unwrap_or("-"),
+                         change.running.as_deref().unwrap_or("-"));
+            }
+        }
+
+        if !self.rules.is_empty() {
+            println!("Rules:");
+            for rule in self.rules.added.iter() {
+                print!("+ ");
+                rule.print();
+            }
+            for rule in self.rules.removed.iter() {
+                print!("- ");
+                rule.print();
+            }
+            for rule in self.rules.reordered.iter() {
+                print!("~ ");
+                rule.print();
+            }
+        }
+
+        if !self.groups.is_empty() {
+            println!("Groups:");
+            self.groups.print();
+        }
+
+        if !self.proxies.is_empty() {
+            println!("Proxies:");
+            self.proxies.print();
+        }
+    }
+}
+
+impl CliOutput for Subscription {
+    fn print(&self) {
+        print!("{}\t{}", self.name, self.url);
+        if let Some(info) = self.userinfo.as_ref() {
+            print!("\tused: {}/{}", info.used(), info.total);
+            if let Some(expire) = info.expire {
+                print!("\texpire: {}", expire);
+            }
+        }
+        println!();
+    }
+}
+
+/// A profile of the library, and whether it is the active one.
+#[derive(Serialize)]
+pub struct ProfileEntry {
+    pub name: String,
+    pub active: bool,
+}
+
+impl CliOutput for ProfileEntry {
+    fn print(&self) {
+        println!("{} {}", if self.active { "*" } else { " " }, self.name);
+    }
+}
+
+/// The outcome of closing a set of connections.
+#[derive(Serialize, Default)]
+pub struct CloseReport {
+    pub closed: Vec<String>,
+    /// Why closing failed, by connection id.
+    pub failed: BTreeMap<String, String>,
+}
+
+impl CloseReport {
+    pub fn new<E: std::fmt::Display>(results: impl IntoIterator<Item = (String, Result<(), E>)>) -> Self {
+        let mut report = Self::default();
+        for (id, result) in results {
+            match result {
+                Ok(()) => report.closed.push(id),
+                Err(e) => {
+                    report.failed.insert(id, e.to_string());
+                }
+            }
+        }
+        report
+    }
+}
+
+impl CliOutput for CloseReport {
+    fn print(&self) {
+        for (id, e) in self.failed.iter() {
+            eprintln!("{}: {}", id, e);
+        }
+        println!("Closed {} of {} connection(s)", self.closed.len(), self.closed.len() + self.failed.len());
+    }
+}
+
+/// The profile `profile use` switched to, and the saved selector choices
+/// it applied again.
+#[derive(Serialize)]
+pub struct ProfileSwitch {
+    pub profile: String,
+    pub restored: BTreeMap<String, String>,
+    /// Choices that could not be applied, by group.
+    pub failed: BTreeMap<String, String>,
+}
+
+impl CliOutput for ProfileSwitch {
+    fn print(&self) {
+        for (group, choice) in self.restored.iter() {
+            println!("{}: {}", group, choice);
+        }
+        for (group, choice) in self.failed.iter() {
+            eprintln!("cannot restore {}: {}", group, choice);
+        }
+    }
+}
+
+/// A subscription after an update, and where its profile was written.
+#[derive(Serialize)]
+pub struct SubscriptionUpdate {
+    pub subscription: Subscription,
+    pub proxies: usize,
+    pub path: String,
+}
+
+impl CliOutput for SubscriptionUpdate {
+    fn print(&self) {
+        self.subscription.print();
+        println!("{} proxies written to {}", self.proxies, self.path);
+    }
+}
+
+/// The proxy `proxy pick` selected in a group.
+#[cfg(feature = "tui")]
+#[derive(Serialize)]
+pub struct Pick {
+    pub group: String,
+    pub proxy: String,
+}
+
+#[cfg(feature = "tui")]
+impl CliOutput for Pick {
+    fn print(&self) {
+        println!("{}: {}", self.group, self.proxy);
+    }
+}
+
+/// Whether a commit-confirmed change was kept.
+#[derive(Serialize)]
+pub struct ConfirmReport {
+    pub confirmed: bool,
+}
+
+impl CliOutput for ConfirmReport {
+    fn print(&self) {
+        match self.confirmed {
+            true => println!("Confirmed"),
+            false => println!("Not confirmed, rolled back"),
+        }
+    }
+}
+
+impl CliOutput for LibraryState {
+    fn print(&self) {
+        match self.active.as_ref() {
+            Some(name) => println!("{}", name),
+            None => {
+                println!("No active profile");
+                return;
+            }
+        }
+
+        if let Some(loaded_at) = self.loaded_at {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(loaded_at);
+            println!("Loaded {} seconds ago", now.saturating_sub(loaded_at));
+        }
+    }
+}
+
+impl CliOutput for Memory {
+    fn print(&self) {
+        println!("inuse: {}, oslimit: {}", format_bytes(self.inuse as f64), format_bytes(self.oslimit as f64));
+    }
+}
+
+impl CliOutput for DnsAnswer {
+    fn print(&self) {
+        println!("Status: {}", self.rcode());
+        for record in self.answer.iter() {
+            let r#type = record.record_type().map(|t| t.name().to_owned()).unwrap_or_else(|| format!("TYPE{}", record.r#type));
+            println!("{}\t{}\t{}\t{}", record.name, record.ttl, r#type, record.data);
+        }
+    }
+}
+
+impl CliOutput for Capabilities {
+    fn print(&self) {
+        println!("Core: {}", self.flavor);
+        println!("Version: {}", self.version);
+        if let Some(semver) = self.semver.as_ref() {
+            println!("Semver: {}", semver);
+        }
+        println!("Features:");
+        for feature in Feature::ALL.iter() {
+            let mark = if self.supports(*feature) { "o" } else { "x" };
+            println!("{} {}", mark, feature);
+        }
+    }
+}
+
+impl CliOutput for Evaluation {
+    fn print(&self) {
+        match self.matched.as_ref() {
+            Some(m) => {
+                print!("Rule #{}: ", m.index);
+                m.rule.print();
+                println!("Policy: {}", m.policy());
+            }
+            None => println!("No rule matched"),
+        }
+
+        if !self.undecided.is_empty() {
+            let indices: Vec<String> = self.undecided.iter().map(|i| format!("#{}", i)).collect();
+            println!("Could not evaluate earlier rules: {}", indices.join(", "));
+        }
+    }
+}
+
+impl CliOutput for Finding {
+    fn print(&self) {
+        println!("#{}\t{}\t{}", self.index, self.rule, self.issue);
+    }
+}
+
+impl CliOutput for HitReport {
+    fn print(&self) {
+        println!("RANK\tINDEX\tHITS\tUPLOAD\tDOWNLOAD\tHOSTS\tRULE");
+        for (rank, hits) in self.rules.iter().enumerate() {
+            let index = hits.index.map_or("-".to_owned(), |i| format!("#{}", i));
+            println!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                rank + 1, index, hits.hits, hits.upload, hits.download, hits.hosts, hits.rule.to_line()
+            );
+        }
+    }
+}
+
+impl CliOutput for Simulation {
+    fn print(&self) {
+        for change in self.changes.iter() {
+            let uncertain = if change.uncertain { " (uncertain)" } else { "" };
+            println!("{} [{} connection(s)]{}", change, change.connections, uncertain);
+        }
+        let changed: usize = self.changes.iter().map(|c| c.connections).sum();
+        println!("{} of {} connection(s) would change policy, {} unchanged", changed, self.total, self.unchanged);
+    }
+}
+
+/// Bytes in binary units, e.g. `1.5 MiB`.
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", value as u64, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// A duration as `1d2h`, `3h4m`, `5m6s` or `7s`.
+pub fn format_age(age: std::time::Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        s if s >= 86400 => format!("{}d{}h", s / 86400, s % 86400 / 3600),
+        s if s >= 3600 => format!("{}h{}m", s / 3600, s % 3600 / 60),
+        s if s >= 60 => format!("{}m{}s", s / 60, s % 60),
+        s => format!("{}s", s),
+    }
+}
+
+fn print_rate_header(rates: &RateSnapshot) {
+    println!(
+        "{} connection(s)\tup {}/s\tdown {}/s",
+        rates.connections.len(),
+        format_bytes(rates.total.upload),
+        format_bytes(rates.total.download)
+    );
+    println!();
+}
+
+pub fn print_connection_rates(rates: &RateSnapshot, limit: usize) {
+    let now = std::time::SystemTime::now();
+    print_rate_header(rates);
+    println!("UP/s\tDOWN/s\tUP\tDOWN\tAGE\tHOST\tPROCESS\tCHAIN");
+    for c in rates.connections.iter().take(limit) {
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            format_bytes(c.rate.upload),
+            format_bytes(c.rate.download),
+            format_bytes(c.upload as f64),
+            format_bytes(c.download as f64),
+            c.age(now).map_or("-".to_owned(), format_age),
+            c.host,
+            c.process,
+            c.chains.iter().rev().cloned().collect::<Vec<_>>().join(" > "),
+        );
+    }
+}
+
+pub fn print_host_rates(rates: &RateSnapshot, limit: usize) {
+    print_rate_header(rates);
+    println!("UP/s\tDOWN/s\tUP\tDOWN\tCONNS\tHOST");
+    for h in rates.by_host().iter().take(limit) {
+        h.print();
+    }
+}
+
+impl CliOutput for RateSnapshot {
+    fn print(&self) {
+        print_connection_rates(self, usize::MAX);
+    }
+}
+
+impl CliOutput for HostRate {
+    fn print(&self) {
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            format_bytes(self.rate.upload),
+            format_bytes(self.rate.download),
+            format_bytes(self.upload as f64),
+            format_bytes(self.download as f64),
+            self.connections,
+            self.host,
+        );
+    }
+}
+
+impl CliOutput for ProxyDelay {
+    fn print(&self) {
+        println!("{} ms", self.delay);
+    }
+}
+
+impl CliOutput for ConnectionGroup {
+    fn print(&self) {
+        println!(
+            "{}\t{}\t{}\t{}",
+            self.connections,
+            format_bytes(self.upload as f64),
+            format_bytes(self.download as f64),
+            self.key
+        );
+        for destination in self.top_destinations.iter() {
+            println!(
+                "\t\t\t  {} ({}, {} connection(s))",
+                destination.host,
+                format_bytes(destination.bytes as f64),
+                destination.connections
+            );
+        }
+    }
+}
+
+impl CliOutput for UsageTotal {
+    fn print(&self) {
+        println!(
+            "{}\t{}\t{}\t{}",
+            format_bytes(self.upload as f64),
+            format_bytes(self.download as f64),
+            format_bytes((self.upload + self.download) as f64),
+            self.key
+        );
+    }
+}
+
+impl Tabular for Rule {
+    fn header() -> &'static [&'static str] {
+        &["TYPE", "PAYLOAD", "PROXY"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![table::raw_row(&(0, self.clone()), RuleRow::TABULAR)]
+    }
+}
+
+impl Tabular for RuleList {
+    fn header() -> &'static [&'static str] {
+        Rule::header()
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.rules.rows()
+    }
+}
+
+impl Tabular for Config {
+    fn header() -> &'static [&'static str] {
+        &["KEY", "VALUE"]
+    }
+
+    /// One row per field the core reported, keyed as in the JSON output.
+    fn rows(&self) -> Vec<Vec<String>> {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(map)) => map.iter().map(|(key, value)| vec![key.clone(), cell(value)]).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Tabular for ProxyInfo {
+    fn header() -> &'static [&'static str] {
+        &["TYPE", "NOW", "ALL"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![
+            self.r#type.clone().unwrap_or_default(),
+            self.now.clone().unwrap_or_default(),
+            self.all.as_ref().map(|all| all.join(",")).unwrap_or_default(),
+        ]]
+    }
+}
+
+impl Tabular for ProxyList {
+    fn header() -> &'static [&'static str] {
+        &["NAME", "TYPE", "NOW"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.iter()
+            .map(|(name, proxy)| table::raw_row(&(name.clone(), proxy.clone()), ProxyRow::TABULAR))
+            .collect()
+    }
+}
+
+impl Tabular for Traffic {
+    fn header() -> &'static [&'static str] {
+        &["UP", "DOWN"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.up.to_string(), self.down.to_string()]]
+    }
+}
+
+impl Tabular for Log {
+    fn header() -> &'static [&'static str] {
+        &["TYPE", "PAYLOAD"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![cell(&serde_json::to_value(&self.r#type).unwrap_or_default()), self.payload.clone()]]
+    }
+}
+
+impl Tabular for Connection {
+    fn header() -> &'static [&'static str] {
+        &["ID", "START", "NETWORK", "TYPE", "SOURCE", "DESTINATION", "HOST", "PROCESS", "RULE", "CHAINS", "UPLOAD", "DOWNLOAD"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![table::raw_row(self, Connection::TABULAR)]
+    }
+}
+
+impl Tabular for ConnectionVec {
+    fn header() -> &'static [&'static str] {
+        Connection::header()
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.connections.rows()
+    }
+}
+
+impl Tabular for Memory {
+    fn header() -> &'static [&'static str] {
+        &["INUSE", "OSLIMIT"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.inuse.to_string(), self.oslimit.to_string()]]
+    }
+}
+
+impl CliOutput for Version {
+    fn print(&self) {
+        println!("{}", self.version);
+    }
+}
+
+impl Tabular for Version {
+    fn header() -> &'static [&'static str] {
+        &["VERSION", "META", "PREMIUM"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.version.clone(), self.meta.to_string(), self.premium.to_string()]]
+    }
+}
+
+impl Tabular for Finding {
+    fn header() -> &'static [&'static str] {
+        &["INDEX", "RULE", "ISSUE"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.index.to_string(), self.rule.to_string(), self.issue.to_string()]]
+    }
+}
+
+impl Tabular for HitReport {
+    fn header() -> &'static [&'static str] {
+        &["RANK", "INDEX", "HITS", "UPLOAD", "DOWNLOAD", "HOSTS", "RULE"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.rules
+            .iter()
+            .enumerate()
+            .map(|(rank, hits)| {
+                vec![
+                    (rank + 1).to_string(),
+                    hits.index.map_or("".to_owned(), |i| i.to_string()),
+                    hits.hits.to_string(),
+                    hits.upload.to_string(),
+                    hits.download.to_string(),
+                    hits.hosts.to_string(),
+                    hits.rule.to_line(),
+                ]
+            })
+            .collect()
+    }
+}
+
+impl Tabular for ConnectionGroup {
+    fn header() -> &'static [&'static str] {
+        &["GROUP", "CONNECTIONS", "UPLOAD", "DOWNLOAD"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.key.clone(), self.connections.to_string(), self.upload.to_string(), self.download.to_string()]]
+    }
+}
+
+impl Tabular for UsageTotal {
+    fn header() -> &'static [&'static str] {
+        &["KEY", "UPLOAD", "DOWNLOAD"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.key.clone(), self.upload.to_string(), self.download.to_string()]]
+    }
+}
+
+impl Tabular for ProxyDelay {
+    fn header() -> &'static [&'static str] {
+        &["DELAY"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.delay.to_string()]]
+    }
+}
+
+impl Tabular for Subscription {
+    fn header() -> &'static [&'static str] {
+        &["NAME", "URL", "UPLOAD", "DOWNLOAD", "TOTAL", "EXPIRE", "UPDATED"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        let info = |f: fn(&clashrsctl::subscription::UserInfo) -> Option<u64>| {
+            self.userinfo.as_ref().and_then(f).map_or("".to_owned(), |n| n.to_string())
+        };
+        vec![vec![
+            self.name.clone(),
+            self.url.clone(),
+            info(|i| Some(i.upload)),
+            info(|i| Some(i.download)),
+            info(|i| Some(i.total)),
+            info(|i| i.expire),
+            self.updated.map_or("".to_owned(), |t| t.to_string()),
+        ]]
+    }
+}
+
+impl Tabular for ProfileEntry {
+    fn header() -> &'static [&'static str] {
+        &["NAME", "ACTIVE"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![self.name.clone(), self.active.to_string()]]
+    }
+}
+
+impl Tabular for CloseReport {
+    fn header() -> &'static [&'static str] {
+        &["ID", "ERROR"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        let closed = self.closed.iter().map(|id| vec![id.clone(), "".to_owned()]);
+        closed.chain(self.failed.iter().map(|(id, e)| vec![id.clone(), e.clone()])).collect()
+    }
+}
+
+impl Tabular for RateSnapshot {
+    fn header() -> &'static [&'static str] {
+        &["ID", "HOST", "PROCESS", "CHAINS", "UPLOAD_RATE", "DOWNLOAD_RATE", "UPLOAD", "DOWNLOAD"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        self.connections
+            .iter()
+            .map(|c| {
+                vec![
+                    c.id.clone(),
+                    c.host.clone(),
+                    c.process.clone(),
+                    c.chains.iter().rev().cloned().collect::<Vec<_>>().join(" > "),
+                    format!("{:.0}", c.rate.upload),
+                    format!("{:.0}", c.rate.download),
+                    c.upload.to_string(),
+                    c.download.to_string(),
+                ]
+            })
+            .collect()
+    }
+}
+
+impl Tabular for HostRate {
+    fn header() -> &'static [&'static str] {
+        &["HOST", "CONNECTIONS", "UPLOAD_RATE", "DOWNLOAD_RATE", "UPLOAD", "DOWNLOAD"]
+    }
+
+    fn rows(&self) -> Vec<Vec<String>> {
+        vec![vec![
+            self.host.clone(),
+            self.connections.to_string(),
+            format!("{:.0}", self.rate.upload),
+            format!("{:.0}", self.rate.download),
+            self.upload.to_string(),
+            self.download.to_string(),
+        ]]
     }
 }
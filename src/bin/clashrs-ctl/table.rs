@@ -0,0 +1,426 @@
+//! Aligned tables with selectable and sortable columns, in human units for
+//! text output and in raw values for `table` and `tsv`.
+
+use std::{
+    cmp::Ordering,
+    ffi::OsString,
+    io::IsTerminal,
+    time::{Duration, SystemTime},
+};
+
+use clap::Args;
+use clashrsctl::{
+    connection::{format_rfc3339, Connection},
+    rule::Rule,
+};
+use serde_json::Value;
+
+use crate::output::{format_age, format_bytes, OutputFormat};
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct ColumnArgs {
+    /// Comma-separated columns to show, e.g. `id,host,chain,down`
+    #[arg(long)]
+    pub columns: Option<String>,
+    /// Column to sort by. Sizes, rates and ages sort largest first
+    #[arg(short, long)]
+    pub sort: Option<String>,
+}
+
+pub enum Cell {
+    Text(String),
+    /// Sorts ascending, e.g. indices.
+    Number(u64),
+    Bytes(u64),
+    /// Bytes per second.
+    Rate(f64),
+    Age(Option<Duration>),
+    Time(Option<SystemTime>),
+    /// Milliseconds, `None` for a timeout or no test yet.
+    Delay(Option<u64>),
+}
+
+impl Cell {
+    fn human(&self) -> String {
+        match self {
+            Cell::Text(s) => s.clone(),
+            Cell::Number(n) => n.to_string(),
+            Cell::Bytes(b) => format_bytes(*b as f64),
+            Cell::Rate(r) => format!("{}/s", format_bytes(*r)),
+            Cell::Age(age) => age.map_or("-".to_owned(), format_age),
+            Cell::Time(time) => match time.and_then(|t| SystemTime::now().duration_since(t).ok()) {
+                Some(age) => format!("{} ago", format_age(age)),
+                None => "-".to_owned(),
+            },
+            Cell::Delay(delay) => delay.map_or("-".to_owned(), |d| format!("{} ms", d)),
+        }
+    }
+
+    fn raw(&self) -> String {
+        match self {
+            Cell::Text(s) => s.clone(),
+            Cell::Number(n) | Cell::Bytes(n) => n.to_string(),
+            Cell::Rate(r) => format!("{:.0}", r),
+            Cell::Age(age) => age.map_or("".to_owned(), |a| a.as_secs().to_string()),
+            Cell::Time(time) => time.map_or("".to_owned(), format_rfc3339),
+            Cell::Delay(delay) => delay.map_or("".to_owned(), |d| d.to_string()),
+        }
+    }
+
+    fn right_aligned(&self) -> bool {
+        !matches!(self, Cell::Text(_) | Cell::Time(_))
+    }
+
+    /// Order for sorting: text, numbers and delays ascending, sizes, rates
+    /// and ages descending, missing values last.
+    fn compare(&self, other: &Cell) -> Ordering {
+        fn last<T: Ord>(a: &Option<T>, b: &Option<T>, order: impl Fn(&T, &T) -> Ordering) -> Ordering {
+            match (a, b) {
+                (Some(a), Some(b)) => order(a, b),
+                (a, b) => b.is_some().cmp(&a.is_some()),
+            }
+        }
+        match (self, other) {
+            (Cell::Text(a), Cell::Text(b)) => a.to_lowercase().cmp(&b.to_lowercase()),
+            (Cell::Number(a), Cell::Number(b)) => a.cmp(b),
+            (Cell::Bytes(a), Cell::Bytes(b)) => b.cmp(a),
+            (Cell::Rate(a), Cell::Rate(b)) => b.total_cmp(a),
+            (Cell::Age(a), Cell::Age(b)) => last(a, b, |a, b| b.cmp(a)),
+            (Cell::Time(a), Cell::Time(b)) => last(a, b, |a, b| a.cmp(b)),
+            (Cell::Delay(a), Cell::Delay(b)) => last(a, b, |a, b| a.cmp(b)),
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Cyan,
+}
+
+impl Color {
+    fn paint(&self, s: &str) -> String {
+        let code = match self {
+            Color::Red => 31,
+            Color::Green => 32,
+            Color::Yellow => 33,
+            Color::Cyan => 36,
+        };
+        format!("\x1b[{}m{}\x1b[0m", code, s)
+    }
+}
+
+/// Whether to colour text output: only on a terminal, and never when
+/// `NO_COLOR` is set to anything but an empty string.
+pub fn color_enabled() -> bool {
+    use_color(std::io::stdout().is_terminal(), std::env::var_os("NO_COLOR"))
+}
+
+fn use_color(terminal: bool, no_color: Option<OsString>) -> bool {
+    terminal && no_color.is_none_or(|v| v.is_empty())
+}
+
+/// A row type with named columns.
+pub trait Columns {
+    /// Every column, in display order.
+    const COLUMNS: &'static [&'static str];
+    /// The columns shown without `--columns`.
+    const DEFAULT: &'static [&'static str];
+    /// The columns `table` and `tsv` show without `--columns`, as the
+    /// `Tabular` form of the same response does.
+    const TABULAR: &'static [&'static str];
+
+    fn cell(&self, column: &str, now: SystemTime) -> Cell;
+
+    /// The header of `column`.
+    fn heading(column: &str) -> String {
+        column.to_uppercase()
+    }
+
+    fn color(&self, _column: &str) -> Option<Color> {
+        None
+    }
+}
+
+fn column<T: Columns>(name: &str) -> Result<&'static str, String> {
+    let name = name.trim().to_lowercase();
+    T::COLUMNS
+        .iter()
+        .find(|c| **c == name)
+        .copied()
+        .ok_or_else(|| format!("Unknown column: {}. Expected one of {}", name, T::COLUMNS.join(", ")))
+}
+
+/// Check `--columns` and `--sort`, then sort `items`, returning the columns
+/// to show in `format`.
+pub fn prepare<T: Columns>(items: &mut [T], args: &ColumnArgs, format: OutputFormat) -> Result<Vec<&'static str>, String> {
+    let columns = match (args.columns.as_deref(), format) {
+        (Some(list), _) => list.split(',').map(column::<T>).collect::<Result<Vec<_>, _>>()?,
+        (None, OutputFormat::Table | OutputFormat::Tsv) => T::TABULAR.to_vec(),
+        (None, _) => T::DEFAULT.to_vec(),
+    };
+    if let Some(sort) = args.sort.as_deref() {
+        let sort = column::<T>(sort)?;
+        let now = SystemTime::now();
+        items.sort_by(|a, b| a.cell(sort, now).compare(&b.cell(sort, now)));
+    }
+    Ok(columns)
+}
+
+/// The raw cells of `columns` of `item`.
+pub fn raw_row<T: Columns>(item: &T, columns: &[&str]) -> Vec<String> {
+    let now = SystemTime::now();
+    columns.iter().map(|c| item.cell(c, now).raw()).collect()
+}
+
+/// Print `items` as a table: aligned in human units for text, with colour
+/// if `color`, aligned in raw values for `table`, and tab-separated for
+/// `tsv`.
+pub fn print<T: Columns>(items: &[T], columns: &[&str], format: OutputFormat, color: bool) {
+    let now = SystemTime::now();
+    let header: Vec<String> = columns.iter().map(|c| T::heading(c)).collect();
+    let rows: Vec<Vec<(Cell, Option<Color>)>> = items
+        .iter()
+        .map(|item| columns.iter().map(|c| (item.cell(c, now), item.color(c))).collect())
+        .collect();
+    let text = |cell: &Cell| match format {
+        OutputFormat::Text => cell.human(),
+        _ => cell.raw().replace(['\t', '\n', '\r'], " "),
+    };
+
+    if format == OutputFormat::Tsv {
+        println!("{}", header.join("\t"));
+        for row in rows.iter() {
+            println!("{}", row.iter().map(|(cell, _)| text(cell)).collect::<Vec<_>>().join("\t"));
+        }
+        return;
+    }
+
+    let cells: Vec<Vec<String>> = rows.iter().map(|row| row.iter().map(|(cell, _)| text(cell)).collect()).collect();
+    let mut widths: Vec<usize> = header.iter().map(|h| h.len()).collect();
+    for row in cells.iter() {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let color = color && format == OutputFormat::Text;
+    let line: Vec<String> = header.iter().zip(widths.iter()).map(|(h, w)| format!("{:<1$}", h, w)).collect();
+    let line = line.join("  ");
+    match color {
+        true => println!("\x1b[1m{}\x1b[0m", line.trim_end()),
+        false => println!("{}", line.trim_end()),
+    }
+    for (row, cells) in rows.iter().zip(cells.iter()) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(cells)
+            .zip(widths.iter())
+            .map(|(((cell, paint), text), width)| {
+                let padded = match cell.right_aligned() {
+                    true => format!("{:>1$}", text, width),
+                    false => format!("{:<1$}", text, width),
+                };
+                match (color, paint) {
+                    (true, Some(paint)) => paint.paint(&padded),
+                    _ => padded,
+                }
+            })
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+impl Columns for Connection {
+    const COLUMNS: &'static [&'static str] = &[
+        "id", "start", "age", "network", "type", "src", "dst", "host", "process", "rule", "chain", "up", "down",
+        "total", "rate",
+    ];
+    const DEFAULT: &'static [&'static str] = &["id", "age", "host", "process", "chain", "up", "down"];
+    const TABULAR: &'static [&'static str] =
+        &["id", "start", "network", "type", "src", "dst", "host", "process", "rule", "chain", "up", "down"];
+
+    fn cell(&self, column: &str, now: SystemTime) -> Cell {
+        let metadata = &self.metadata;
+        let addr = |ip: &str, port: &str| match port {
+            "" => ip.to_owned(),
+            port => format!("{}:{}", ip, port),
+        };
+        match column {
+            "id" => Cell::Text(self.id.clone()),
+            "start" => Cell::Time(self.start),
+            "age" => Cell::Age(self.age_at(now)),
+            "network" => Cell::Text(metadata.network.clone()),
+            "type" => Cell::Text(metadata.r#type.clone()),
+            "src" => Cell::Text(addr(&metadata.source_ip, &metadata.source_port)),
+            "dst" => Cell::Text(addr(&metadata.destination_ip, &metadata.destination_port)),
+            "host" => Cell::Text(metadata.destination().to_owned()),
+            "process" => Cell::Text(metadata.process_name().to_owned()),
+            "rule" => Cell::Text(match self.rule_payload.as_str() {
+                "" => self.rule.clone(),
+                payload => format!("{}({})", self.rule, payload),
+            }),
+            "chain" => Cell::Text(self.chains.iter().rev().cloned().collect::<Vec<_>>().join(" > ")),
+            "up" => Cell::Bytes(self.upload as u64),
+            "down" => Cell::Bytes(self.download as u64),
+            "total" => Cell::Bytes((self.upload + self.download) as u64),
+            "rate" => Cell::Rate(self.avg_rate_at(now).map_or(0.0, |r| r.total())),
+            _ => Cell::Text("".to_owned()),
+        }
+    }
+
+    fn heading(column: &str) -> String {
+        match column {
+            "src" => "SOURCE".to_owned(),
+            "dst" => "DESTINATION".to_owned(),
+            "chain" => "CHAINS".to_owned(),
+            "up" => "UPLOAD".to_owned(),
+            "down" => "DOWNLOAD".to_owned(),
+            column => column.to_uppercase(),
+        }
+    }
+
+    fn color(&self, column: &str) -> Option<Color> {
+        (column == "chain").then_some(Color::Cyan)
+    }
+}
+
+/// A proxy or group of `/proxies`, by name.
+pub type ProxyRow = (String, Value);
+
+impl Columns for ProxyRow {
+    const COLUMNS: &'static [&'static str] = &["name", "type", "now", "members", "udp", "delay"];
+    const DEFAULT: &'static [&'static str] = &["name", "type", "now", "delay"];
+    const TABULAR: &'static [&'static str] = &["name", "type", "now"];
+
+    fn cell(&self, column: &str, _now: SystemTime) -> Cell {
+        let (name, proxy) = self;
+        let text = |key: &str| Cell::Text(proxy[key].as_str().unwrap_or_default().to_owned());
+        match column {
+            "name" => Cell::Text(name.clone()),
+            "type" => text("type"),
+            "now" => text("now"),
+            "members" => Cell::Number(proxy["all"].as_array().map_or(0, |all| all.len() as u64)),
+            "udp" => Cell::Text(proxy["udp"].as_bool().map_or("".to_owned(), |udp| udp.to_string())),
+            // The last test; a delay of 0 is a timeout.
+            "delay" => Cell::Delay(
+                proxy["history"].as_array().and_then(|h| h.last()).and_then(|h| h["delay"].as_u64()).filter(|d| *d > 0),
+            ),
+            _ => Cell::Text("".to_owned()),
+        }
+    }
+
+    fn color(&self, column: &str) -> Option<Color> {
+        match (column, self.cell("delay", SystemTime::now())) {
+            ("now", _) => Some(Color::Cyan),
+            ("delay", Cell::Delay(Some(d))) if d < 300 => Some(Color::Green),
+            ("delay", Cell::Delay(Some(_))) => Some(Color::Yellow),
+            ("delay", _) => Some(Color::Red),
+            _ => None,
+        }
+    }
+}
+
+/// A rule with its index in the rule list.
+pub type RuleRow = (usize, Rule);
+
+impl Columns for RuleRow {
+    const COLUMNS: &'static [&'static str] = &["index", "type", "payload", "proxy"];
+    const DEFAULT: &'static [&'static str] = &["index", "type", "payload", "proxy"];
+    const TABULAR: &'static [&'static str] = &["type", "payload", "proxy"];
+
+    fn cell(&self, column: &str, _now: SystemTime) -> Cell {
+        let (index, rule) = self;
+        match column {
+            "index" => Cell::Number(*index as u64),
+            "type" => Cell::Text(rule.r#type.clone()),
+            "payload" => Cell::Text(rule.payload.clone()),
+            "proxy" => Cell::Text(rule.proxy.clone()),
+            _ => Cell::Text("".to_owned()),
+        }
+    }
+
+    fn color(&self, column: &str) -> Option<Color> {
+        match (column, self.1.proxy.as_str()) {
+            ("proxy", "DIRECT") => Some(Color::Green),
+            ("proxy", proxy) if proxy.starts_with("REJECT") => Some(Color::Red),
+            _ => None,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::output::Tabular;
+    use clashrsctl::{mock::ConnectionFixture, proxy::ProxyList};
+
+    fn connection(id: &str, upload: u64, start: &str) -> Connection {
+        ConnectionFixture { id, dst: "1.1.1.1", chains: &["DIRECT"], upload, start, ..Default::default() }.parse()
+    }
+
+    fn rule(r#type: &str, payload: &str) -> Rule {
+        Rule { r#type: r#type.to_owned(), payload: payload.to_owned(), proxy: "DIRECT".to_owned() }
+    }
+
+    fn args(columns: Option<&str>, sort: Option<&str>) -> ColumnArgs {
+        ColumnArgs { columns: columns.map(str::to_owned), sort: sort.map(str::to_owned) }
+    }
+
+    #[test]
+    fn test_prepare_columns() {
+        let mut rows: Vec<RuleRow> = vec![(0, rule("DOMAIN", "a.com"))];
+        assert_eq!(prepare(&mut rows, &args(Some("payload, PROXY"), None), OutputFormat::Text), Ok(vec!["payload", "proxy"]));
+        assert_eq!(prepare(&mut rows, &args(None, None), OutputFormat::Text), Ok(RuleRow::DEFAULT.to_vec()));
+        assert_eq!(prepare(&mut rows, &args(None, None), OutputFormat::Tsv), Ok(RuleRow::TABULAR.to_vec()));
+
+        let err = prepare(&mut rows, &args(Some("type,size"), None), OutputFormat::Text).unwrap_err();
+        assert!(err.starts_with("Unknown column: size."), "{}", err);
+        assert!(prepare(&mut rows, &args(None, Some("size")), OutputFormat::Text).is_err());
+    }
+
+    #[test]
+    fn test_prepare_sort() {
+        let mut rows: Vec<RuleRow> = vec![(2, rule("MATCH", "")), (0, rule("domain", "b.com")), (1, rule("DOMAIN", "A.com"))];
+        prepare(&mut rows, &args(None, Some("index")), OutputFormat::Text).unwrap();
+        assert_eq!(rows.iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![0, 1, 2]);
+        prepare(&mut rows, &args(None, Some("payload")), OutputFormat::Text).unwrap();
+        assert_eq!(rows.iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![2, 1, 0]);
+
+        // Sizes and ages sort largest first, unknown ages last.
+        let mut connections = vec![
+            connection("a", 10, "2024-01-02T00:00:00Z"),
+            connection("b", 30, "2024-01-01T00:00:00Z"),
+            connection("c", 20, "unknown"),
+        ];
+        let ids = |c: &[Connection]| c.iter().map(|c| c.id.clone()).collect::<Vec<_>>();
+        prepare(&mut connections, &args(None, Some("up")), OutputFormat::Text).unwrap();
+        assert_eq!(ids(&connections), vec!["b", "c", "a"]);
+        prepare(&mut connections, &args(None, Some("age")), OutputFormat::Text).unwrap();
+        assert_eq!(ids(&connections), vec!["b", "a", "c"]);
+    }
+
+    #[test]
+    fn test_tabular_headings() {
+        let headings = |columns: &[&str], heading: fn(&str) -> String| columns.iter().map(|c| heading(c)).collect::<Vec<_>>();
+        assert_eq!(headings(Connection::TABULAR, Connection::heading), <Connection as Tabular>::header());
+        assert_eq!(headings(ProxyRow::TABULAR, ProxyRow::heading), ProxyList::header());
+        assert_eq!(headings(RuleRow::TABULAR, RuleRow::heading), Rule::header());
+
+        let row = connection("a", 10, "2024-01-01T00:00:00Z").rows().remove(0);
+        assert_eq!(row[..2], ["a", "2024-01-01T00:00:00Z"]);
+        assert_eq!(row[9..], ["DIRECT", "10", "0"]);
+    }
+
+    #[test]
+    fn test_use_color() {
+        assert!(use_color(true, None));
+        assert!(use_color(true, Some(OsString::new())));
+        assert!(!use_color(true, Some("1".into())));
+        assert!(!use_color(false, None));
+    }
+}
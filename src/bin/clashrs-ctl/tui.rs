@@ -0,0 +1,634 @@
+//! A full-screen dashboard of traffic, logs, connections, proxy groups and
+//! the running mode, driven by the keyboard.
+//!
+//! [`App`] holds the state and turns keys into [`Action`]s; [`poll`] and
+//! [`perform`] talk to the controller and return [`Update`]s for the app to
+//! apply. Rendering lives in [`view`], so all of it runs without a terminal.
+
+pub mod picker;
+pub mod view;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use futures::{future::LocalBoxFuture, stream::FuturesUnordered, FutureExt, StreamExt};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use serde::Deserialize;
+use serde_json::Value;
+
+use clashrsctl::{
+    config::{Config, ConfigLogLevel, ConfigMode},
+    confirm::{DEFAULT_CHECK_TIMEOUT, DEFAULT_CHECK_URL},
+    connection::ConnectionVec,
+    proxy::ProxyList,
+    rate::RateSnapshot,
+    stream::{log::Log, traffic::Traffic, ClashStream},
+    ClashRequest, ClashRequestBuilder,
+};
+
+/// Traffic samples kept for the sparklines, one per second.
+const TRAFFIC_SAMPLES: usize = 300;
+/// Log lines kept, before the level filter.
+const LOG_LINES: usize = 500;
+/// Wait before reconnecting a stream that ended.
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+/// Proxies and config are refreshed every this many connection polls.
+const FULL_POLL_EVERY: u64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tab {
+    Connections,
+    Proxies,
+    Logs,
+}
+
+impl Tab {
+    pub const ALL: [Tab; 3] = [Tab::Connections, Tab::Proxies, Tab::Logs];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Tab::Connections => "Connections",
+            Tab::Proxies => "Proxies",
+            Tab::Logs => "Logs",
+        }
+    }
+
+    fn index(&self) -> usize {
+        Tab::ALL.iter().position(|t| t == self).unwrap_or_default()
+    }
+}
+
+/// Something the user asked for that needs the controller.
+#[derive(Debug, Clone)]
+pub enum Action {
+    Quit,
+    Close(String),
+    CloseAll,
+    Select { group: String, proxy: String },
+    TestDelay(String),
+    SetMode(ConfigMode),
+}
+
+/// New data from the controller, or the outcome of an action.
+#[derive(Debug)]
+pub enum Update {
+    Traffic(Traffic),
+    Log(Log),
+    Connections(ConnectionVec),
+    Proxies(ProxyList),
+    Config(Config),
+    /// A delay test result, `None` on timeout or failure.
+    Delay { proxy: String, delay: Option<u64> },
+    Notice(String),
+    /// The controller could not be reached or refused a request.
+    Error(String),
+}
+
+fn severity(level: &ConfigLogLevel) -> u8 {
+    match level {
+        ConfigLogLevel::Debug => 0,
+        ConfigLogLevel::Info => 1,
+        ConfigLogLevel::Warning => 2,
+        ConfigLogLevel::Error => 3,
+    }
+}
+
+pub struct App {
+    tab: Tab,
+    /// Upload and download rates in bytes per second, oldest first.
+    upload: VecDeque<u64>,
+    download: VecDeque<u64>,
+    logs: VecDeque<Log>,
+    /// Logs below this level are hidden.
+    log_level: ConfigLogLevel,
+    connections: Option<ConnectionVec>,
+    polled: Option<Instant>,
+    rates: RateSnapshot,
+    proxies: Option<ProxyList>,
+    /// Delays measured in this session, overriding the proxy history.
+    delays: HashMap<String, Option<u64>>,
+    config: Option<Config>,
+
+    connection: usize,
+    group: usize,
+    member: usize,
+    /// Whether up and down move through the members of the selected group.
+    members_focused: bool,
+
+    connected: bool,
+    status: String,
+}
+
+impl Default for App {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl App {
+    pub fn new() -> Self {
+        Self {
+            tab: Tab::Connections,
+            upload: VecDeque::with_capacity(TRAFFIC_SAMPLES),
+            download: VecDeque::with_capacity(TRAFFIC_SAMPLES),
+            logs: VecDeque::with_capacity(LOG_LINES),
+            log_level: ConfigLogLevel::Info,
+            connections: None,
+            polled: None,
+            rates: RateSnapshot::default(),
+            proxies: None,
+            delays: HashMap::new(),
+            config: None,
+            connection: 0,
+            group: 0,
+            member: 0,
+            members_focused: false,
+            connected: false,
+            status: "Connecting...".to_owned(),
+        }
+    }
+
+    pub fn apply(&mut self, update: Update) {
+        match update {
+            Update::Traffic(traffic) => {
+                if self.upload.len() == TRAFFIC_SAMPLES {
+                    self.upload.pop_front();
+                    self.download.pop_front();
+                }
+                self.upload.push_back(traffic.up as u64);
+                self.download.push_back(traffic.down as u64);
+            }
+            Update::Log(log) => {
+                if self.logs.len() == LOG_LINES {
+                    self.logs.pop_front();
+                }
+                self.logs.push_back(log);
+            }
+            Update::Connections(next) => {
+                let now = Instant::now();
+                let (prev, elapsed) = match (&self.connections, self.polled) {
+                    (Some(prev), Some(polled)) => (prev, now - polled),
+                    _ => (&next, Duration::ZERO),
+                };
+                self.rates = RateSnapshot::between(prev, &next, elapsed);
+                self.connection = self.connection.min(next.connections.len().saturating_sub(1));
+                self.connections = Some(next);
+                self.polled = Some(now);
+                if !self.connected {
+                    self.connected = true;
+                    self.status = "Connected".to_owned();
+                }
+            }
+            Update::Proxies(proxies) => {
+                self.proxies = Some(proxies);
+                self.group = self.group.min(self.groups().len().saturating_sub(1));
+                self.member = self.member.min(self.members().len().saturating_sub(1));
+            }
+            Update::Config(config) => self.config = Some(config),
+            Update::Delay { proxy, delay } => {
+                self.status = match delay {
+                    Some(delay) => format!("{}: {} ms", proxy, delay),
+                    None => format!("{}: timeout", proxy),
+                };
+                self.delays.insert(proxy, delay);
+            }
+            Update::Notice(notice) => self.status = notice,
+            Update::Error(error) => {
+                self.connected = false;
+                self.status = error;
+            }
+        }
+    }
+
+    /// The proxy groups, with `GLOBAL` last.
+    fn groups(&self) -> Vec<&str> {
+        let Some(proxies) = &self.proxies else {
+            return Vec::new();
+        };
+        let mut groups: Vec<&str> = proxies.group_names().collect();
+        if proxies.get("GLOBAL").is_some() {
+            groups.push("GLOBAL");
+        }
+        groups
+    }
+
+    fn group_info(&self) -> Option<(&str, &Value)> {
+        let name = *self.groups().get(self.group)?;
+        Some((name, self.proxies.as_ref()?.get(name)?))
+    }
+
+    /// Members of the selected group.
+    fn members(&self) -> Vec<&str> {
+        self.group_info()
+            .and_then(|(_, info)| info["all"].as_array())
+            .map(|all| all.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// The last known delay of a proxy, `Some(None)` if its test failed.
+    fn delay(&self, proxy: &str) -> Option<Option<u64>> {
+        if let Some(delay) = self.delays.get(proxy) {
+            return Some(*delay);
+        }
+        let delay = self.proxies.as_ref()?.last_delay(proxy)?;
+        Some((delay > 0).then_some(delay))
+    }
+
+    fn visible_logs(&self) -> impl DoubleEndedIterator<Item = &Log> {
+        let min = severity(&self.log_level);
+        self.logs.iter().filter(move |log| severity(&log.r#type) >= min)
+    }
+
+    fn move_selection(&mut self, down: bool) {
+        let step = |index: usize, len: usize| match down {
+            true => (index + 1).min(len.saturating_sub(1)),
+            false => index.saturating_sub(1),
+        };
+        match self.tab {
+            Tab::Connections => self.connection = step(self.connection, self.rates.connections.len()),
+            Tab::Proxies if self.members_focused => self.member = step(self.member, self.members().len()),
+            Tab::Proxies => {
+                let group = step(self.group, self.groups().len());
+                if group != self.group {
+                    self.group = group;
+                    self.member = 0;
+                }
+            }
+            Tab::Logs => {}
+        }
+    }
+
+    /// Handle a key press, returning the action it asks for if any.
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if key.kind != KeyEventKind::Press {
+            return None;
+        }
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            return (key.code == KeyCode::Char('c')).then_some(Action::Quit);
+        }
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Some(Action::Quit),
+            KeyCode::Tab => self.tab = Tab::ALL[(self.tab.index() + 1) % Tab::ALL.len()],
+            KeyCode::BackTab => self.tab = Tab::ALL[(self.tab.index() + Tab::ALL.len() - 1) % Tab::ALL.len()],
+            KeyCode::Char(c @ '1'..='3') => self.tab = Tab::ALL[c as usize - '1' as usize],
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(true),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(false),
+            KeyCode::Char('m') => {
+                let mode = match self.config.as_ref()?.mode.as_ref()? {
+                    ConfigMode::Rule => ConfigMode::Global,
+                    ConfigMode::Global => ConfigMode::Direct,
+                    ConfigMode::Direct => ConfigMode::Rule,
+                };
+                return Some(Action::SetMode(mode));
+            }
+            code => return self.handle_tab_key(code),
+        }
+        None
+    }
+
+    fn handle_tab_key(&mut self, code: KeyCode) -> Option<Action> {
+        match (self.tab, code) {
+            (Tab::Connections, KeyCode::Char('x') | KeyCode::Delete) => {
+                let connection = self.rates.connections.get(self.connection)?;
+                Some(Action::Close(connection.id.clone()))
+            }
+            (Tab::Connections, KeyCode::Char('X')) => Some(Action::CloseAll),
+            (Tab::Proxies, KeyCode::Left | KeyCode::Char('h')) => {
+                self.members_focused = false;
+                None
+            }
+            (Tab::Proxies, KeyCode::Right | KeyCode::Char('l')) => {
+                self.members_focused = !self.members().is_empty();
+                None
+            }
+            (Tab::Proxies, KeyCode::Enter) if self.members_focused => {
+                let (group, info) = self.group_info()?;
+                if info["type"] != "Selector" {
+                    self.status = format!("{} is not a selector", group);
+                    return None;
+                }
+                let proxy = self.members().get(self.member)?.to_string();
+                Some(Action::Select { group: group.to_owned(), proxy })
+            }
+            (Tab::Proxies, KeyCode::Enter) => {
+                self.members_focused = !self.members().is_empty();
+                None
+            }
+            (Tab::Proxies, KeyCode::Char('t')) => {
+                let proxy = match self.members_focused {
+                    true => self.members().get(self.member)?.to_string(),
+                    false => self.group_info()?.0.to_owned(),
+                };
+                self.status = format!("Testing {}...", proxy);
+                Some(Action::TestDelay(proxy))
+            }
+            (Tab::Logs, KeyCode::Char('l')) => {
+                self.log_level = match self.log_level {
+                    ConfigLogLevel::Debug => ConfigLogLevel::Info,
+                    ConfigLogLevel::Info => ConfigLogLevel::Warning,
+                    ConfigLogLevel::Warning => ConfigLogLevel::Error,
+                    ConfigLogLevel::Error => ConfigLogLevel::Debug,
+                };
+                None
+            }
+            (Tab::Logs, KeyCode::Char('c')) => {
+                self.logs.clear();
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Fetch the connections, and the proxies and config when `full`.
+pub async fn poll(client: ClashRequestBuilder, full: bool) -> Vec<Update> {
+    let mut updates = vec![match client.clone().connections().send().await {
+        Ok(connections) => Update::Connections(connections),
+        Err(e) => Update::Error(format!("Cannot reach the controller: {}", e)),
+    }];
+    if full {
+        match client.clone().proxies().send().await {
+            Ok(proxies) => updates.push(Update::Proxies(proxies)),
+            Err(e) => updates.push(Update::Error(format!("Cannot get the proxies: {}", e))),
+        }
+        match client.config().get().send().await {
+            Ok(config) => updates.push(Update::Config(config)),
+            Err(e) => updates.push(Update::Error(format!("Cannot get the config: {}", e))),
+        }
+    }
+    updates
+}
+
+/// Carry out an action against the controller. [`Action::Quit`] does nothing.
+pub async fn perform(client: ClashRequestBuilder, action: Action) -> Vec<Update> {
+    let result = match action {
+        Action::Quit => return Vec::new(),
+        Action::Close(id) => client
+            .connections()
+            .close_id(&id)
+            .send()
+            .await
+            .map(|_| vec![Update::Notice(format!("Closed connection {}", id))]),
+        Action::CloseAll => client
+            .connections()
+            .close()
+            .send()
+            .await
+            .map(|_| vec![Update::Notice("Closed all connections".to_owned())]),
+        Action::Select { group, proxy } => {
+            match client.clone().proxies().get(&group).change(&proxy).send().await {
+                Ok(_) => client.proxies().send().await.map(|proxies| {
+                    vec![Update::Proxies(proxies), Update::Notice(format!("{} now uses {}", group, proxy))]
+                }),
+                Err(e) => Err(e),
+            }
+        }
+        Action::TestDelay(proxy) => {
+            let delay = client.proxies().get(&proxy).delay(DEFAULT_CHECK_URL, DEFAULT_CHECK_TIMEOUT).send().await;
+            return vec![Update::Delay { proxy, delay: delay.ok().map(|d| d.delay) }];
+        }
+        Action::SetMode(mode) => match client.clone().config().patch().mode(mode).send().await {
+            Ok(_) => client.config().get().send().await.map(|config| {
+                let notice = format!("Mode: {}", config.mode.as_ref().map_or("-".to_owned(), |m| format!("{:?}", m).to_lowercase()));
+                vec![Update::Config(config), Update::Notice(notice)]
+            }),
+            Err(e) => Err(e),
+        },
+    };
+    result.unwrap_or_else(|e| vec![Update::Notice(format!("Failed: {}", e))])
+}
+
+enum Message {
+    Updates(Vec<Update>),
+    Polled(Vec<Update>),
+    Traffic(ClashStream<Traffic>),
+    Logs(ClashStream<Log>),
+    /// A stream failed to connect; try again after a delay.
+    Retry(&'static str, String),
+}
+
+fn connect(client: ClashRequestBuilder, stream: &'static str, delay: Duration) -> LocalBoxFuture<'static, Message> {
+    async move {
+        tokio::time::sleep(delay).await;
+        let result = match stream {
+            "traffic" => client.traffic().send().await.map(Message::Traffic),
+            _ => client.logs().level(ConfigLogLevel::Debug).send().await.map(Message::Logs),
+        };
+        result.unwrap_or_else(|e| Message::Retry(stream, e.to_string()))
+    }
+    .boxed_local()
+}
+
+async fn next<T>(stream: &mut Option<ClashStream<T>>) -> Option<Result<T, Box<dyn std::error::Error>>>
+where
+    T: Unpin + for<'b> Deserialize<'b>,
+{
+    match stream {
+        Some(stream) => stream.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Run the dashboard until the user quits, restoring the terminal after.
+pub async fn run(client: ClashRequestBuilder) -> Result<(), Box<dyn std::error::Error>> {
+    let mut terminal = ratatui::init();
+    let result = run_loop(&mut terminal, client).await;
+    ratatui::restore();
+    result
+}
+
+async fn run_loop(terminal: &mut ratatui::DefaultTerminal, client: ClashRequestBuilder) -> Result<(), Box<dyn std::error::Error>> {
+    // Reading the terminal blocks, so it gets a thread of its own.
+    let (events, mut event_rx) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if events.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut app = App::new();
+    let mut tasks: FuturesUnordered<LocalBoxFuture<'static, Message>> = FuturesUnordered::new();
+    tasks.push(connect(client.clone(), "traffic", Duration::ZERO));
+    tasks.push(connect(client.clone(), "logs", Duration::ZERO));
+    let mut traffic: Option<ClashStream<Traffic>> = None;
+    let mut logs: Option<ClashStream<Log>> = None;
+
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut polls = 0u64;
+    let mut polling = false;
+
+    loop {
+        terminal.draw(|frame| view::draw(frame, &app))?;
+
+        tokio::select! {
+            event = event_rx.recv() => match event {
+                Some(Event::Key(key)) => match app.handle_key(key) {
+                    Some(Action::Quit) => return Ok(()),
+                    Some(action) => tasks.push(perform(client.clone(), action).map(Message::Updates).boxed_local()),
+                    None => {}
+                },
+                Some(_) => {}
+                None => return Ok(()),
+            },
+            _ = tick.tick(), if !polling => {
+                polling = true;
+                tasks.push(poll(client.clone(), polls % FULL_POLL_EVERY == 0).map(Message::Polled).boxed_local());
+                polls += 1;
+            }
+            Some(message) = tasks.next(), if !tasks.is_empty() => match message {
+                Message::Updates(updates) => updates.into_iter().for_each(|u| app.apply(u)),
+                Message::Polled(updates) => {
+                    polling = false;
+                    updates.into_iter().for_each(|u| app.apply(u));
+                }
+                Message::Traffic(stream) => traffic = Some(stream),
+                Message::Logs(stream) => logs = Some(stream),
+                Message::Retry(stream, e) => {
+                    app.apply(Update::Error(format!("Cannot open the {} stream: {}", stream, e)));
+                    tasks.push(connect(client.clone(), stream, RECONNECT_DELAY));
+                }
+            },
+            item = next(&mut traffic) => match item {
+                Some(Ok(item)) => app.apply(Update::Traffic(item)),
+                _ => {
+                    traffic = None;
+                    tasks.push(connect(client.clone(), "traffic", RECONNECT_DELAY));
+                }
+            },
+            item = next(&mut logs) => match item {
+                Some(Ok(item)) => app.apply(Update::Log(item)),
+                _ => {
+                    logs = None;
+                    tasks.push(connect(client.clone(), "logs", RECONNECT_DELAY));
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clashrsctl::mock::{connection, MockController};
+    use ratatui::{backend::TestBackend, Terminal};
+
+    fn render(app: &App) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| view::draw(frame, app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content()
+            .chunks(buffer.area.width as usize)
+            .map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>() + "\n")
+            .collect()
+    }
+
+    fn press(app: &mut App, code: KeyCode) -> Option<Action> {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    fn mock() -> MockController {
+        let connections = format!(
+            r#"{{"downloadTotal":4096,"uploadTotal":1024,"connections":[{},{}]}}"#,
+            connection("a", "example.com", "1.1.1.1", &["node-hk", "Proxy"], 2048, 0),
+            connection("b", "", "8.8.8.8", &["DIRECT"], 10, 0),
+        );
+        let proxies = r#"{"proxies":{
+            "Proxy":{"type":"Selector","now":"node-hk","all":["node-hk","node-us"]},
+            "Auto":{"type":"URLTest","now":"node-us","all":["node-hk","node-us"]},
+            "node-hk":{"type":"Shadowsocks","history":[{"time":"2024-01-01T00:00:00Z","delay":120}]},
+            "node-us":{"type":"Shadowsocks","history":[]},
+            "DIRECT":{"type":"Direct"}}}"#;
+        let mock = MockController::new();
+        mock.route("GET", "connections", 200, &connections)
+            .route("GET", "proxies", 200, proxies)
+            .route("GET", "configs", 200, r#"{"port":7890,"mode":"rule","log-level":"info","allow-lan":false}"#)
+            .route("PUT", "proxies/Proxy", 204, "")
+            .route("DELETE", "connections/a", 204, "")
+            .route("PATCH", "configs", 204, "");
+        mock
+    }
+
+    #[tokio::test]
+    async fn test_dashboard() {
+        let mock = mock();
+        let client = mock.start().await;
+        let mut app = App::new();
+        poll(client.clone(), true).await.into_iter().for_each(|u| app.apply(u));
+        app.apply(Update::Traffic(Traffic { up: 1024, down: 2048 }));
+
+        let screen = render(&app);
+        assert!(screen.contains("example.com"), "{}", screen);
+        assert!(screen.contains("Proxy > node-hk"));
+        assert!(screen.contains("Mode       rule"));
+        assert!(screen.contains("1.0 KiB/s"));
+
+        let action = press(&mut app, KeyCode::Char('x')).unwrap();
+        perform(client.clone(), action).await.into_iter().for_each(|u| app.apply(u));
+        assert!(mock.requests().iter().any(|r| r.method == "DELETE" && r.path == "connections/a"));
+        assert!(render(&app).contains("Closed connection a"));
+
+        let action = press(&mut app, KeyCode::Char('m')).unwrap();
+        assert!(matches!(action, Action::SetMode(ConfigMode::Global)));
+        perform(client, action).await;
+        assert!(mock.requests().iter().any(|r| r.method == "PATCH" && r.body.contains(r#""mode":"global""#)));
+    }
+
+    #[tokio::test]
+    async fn test_proxies() {
+        let mock = mock();
+        let client = mock.start().await;
+        let mut app = App::new();
+        poll(client.clone(), true).await.into_iter().for_each(|u| app.apply(u));
+
+        press(&mut app, KeyCode::Char('2'));
+        let screen = render(&app);
+        assert!(screen.contains("120 ms"), "{}", screen);
+        assert!(!screen.contains("DIRECT"));
+
+        // `Auto` sorts first and cannot be switched.
+        press(&mut app, KeyCode::Right);
+        assert!(press(&mut app, KeyCode::Enter).is_none());
+        press(&mut app, KeyCode::Left);
+        press(&mut app, KeyCode::Down);
+        press(&mut app, KeyCode::Right);
+        press(&mut app, KeyCode::Down);
+        let action = press(&mut app, KeyCode::Enter).unwrap();
+        perform(client, action).await.into_iter().for_each(|u| app.apply(u));
+        let change = mock.requests().into_iter().find(|r| r.method == "PUT").unwrap();
+        assert_eq!(change.path, "proxies/Proxy");
+        assert!(change.body.contains("node-us"));
+        assert!(render(&app).contains("Proxy now uses node-us"));
+
+        app.apply(Update::Delay { proxy: "node-us".to_owned(), delay: None });
+        assert!(render(&app).contains("timeout"));
+    }
+
+    #[test]
+    fn test_logs() {
+        let mut app = App::new();
+        let log = |r#type, payload: &str| Log { r#type, payload: payload.to_owned() };
+        app.apply(Update::Log(log(ConfigLogLevel::Debug, "noise")));
+        app.apply(Update::Log(log(ConfigLogLevel::Info, "matched a rule")));
+        app.apply(Update::Log(log(ConfigLogLevel::Error, "dial failed")));
+        press(&mut app, KeyCode::Char('3'));
+
+        let screen = render(&app);
+        assert!(screen.contains("matched a rule") && screen.contains("dial failed") && !screen.contains("noise"));
+
+        press(&mut app, KeyCode::Char('l'));
+        press(&mut app, KeyCode::Char('l'));
+        let screen = render(&app);
+        assert!(!screen.contains("matched a rule") && screen.contains("dial failed"));
+
+        press(&mut app, KeyCode::Char('c'));
+        assert!(!render(&app).contains("dial failed"));
+        assert!(matches!(press(&mut app, KeyCode::Char('q')), Some(Action::Quit)));
+    }
+}
@@ -0,0 +1,338 @@
+//! Pick a selector group or one of its members by fuzzy search, or from a
+//! numbered list when there is no terminal to draw on.
+
+use std::io::{self, BufRead, Write};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, List, ListItem, ListState, Paragraph},
+    Frame,
+};
+
+use clashrsctl::proxy::ProxyList;
+
+/// An entry of the list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Choice {
+    pub name: String,
+    /// Shown after the name, e.g. the selection of a group or a delay.
+    pub detail: String,
+    /// Whether this is the current selection.
+    pub current: bool,
+}
+
+/// The `Selector` groups, detailed with their selections.
+pub fn group_choices(proxies: &ProxyList) -> Vec<Choice> {
+    proxies
+        .selections()
+        .into_iter()
+        .map(|(name, now)| Choice { name, detail: now, current: false })
+        .collect()
+}
+
+/// The members of a `Selector` group, detailed with their last delays, or
+/// `None` if there is no such selector.
+pub fn member_choices(proxies: &ProxyList, group: &str) -> Option<Vec<Choice>> {
+    let info = proxies.get(group).filter(|info| info["type"] == "Selector")?;
+    let now = info["now"].as_str().unwrap_or_default();
+    let members = info["all"].as_array()?.iter().filter_map(|m| m.as_str());
+    Some(
+        members
+            .map(|name| Choice {
+                name: name.to_owned(),
+                detail: match proxies.last_delay(name) {
+                    Some(0) => "timeout".to_owned(),
+                    Some(delay) => format!("{} ms", delay),
+                    None => String::new(),
+                },
+                current: name == now,
+            })
+            .collect(),
+    )
+}
+
+/// How well `candidate` matches `query`, higher is better, or `None` if the
+/// query is not a case-insensitive subsequence of it. Runs of consecutive
+/// characters and matches at word starts score extra.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<u32> {
+    let lower = |c: char| c.to_lowercase().next().unwrap_or(c);
+    let mut query = query.chars().filter(|c| !c.is_whitespace()).map(lower).peekable();
+    let mut score = 0;
+    let mut previous: Option<char> = None;
+    let mut consecutive = false;
+
+    for c in candidate.chars() {
+        let Some(&wanted) = query.peek() else {
+            break;
+        };
+        if lower(c) == wanted {
+            query.next();
+            score += 1;
+            if consecutive {
+                score += 4;
+            }
+            if previous.is_none_or(|p| !p.is_alphanumeric()) {
+                score += 2;
+            }
+            consecutive = true;
+        } else {
+            consecutive = false;
+        }
+        previous = Some(c);
+    }
+    query.peek().is_none().then_some(score)
+}
+
+/// Indices of the choices matching `query`, best first. Ties keep the
+/// order of the list.
+pub fn filter(choices: &[Choice], query: &str) -> Vec<usize> {
+    let mut matches: Vec<(usize, u32)> = choices
+        .iter()
+        .enumerate()
+        .filter_map(|(i, choice)| Some((i, fuzzy_score(query, &choice.name)?)))
+        .collect();
+    matches.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+    matches.into_iter().map(|(i, _)| i).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Picked(String),
+    Cancelled,
+}
+
+/// The state of the interactive list.
+pub struct Picker {
+    title: String,
+    choices: Vec<Choice>,
+    query: String,
+    matches: Vec<usize>,
+    /// Position in `matches`.
+    selected: usize,
+}
+
+impl Picker {
+    /// Start with the current choice selected.
+    pub fn new(title: &str, choices: Vec<Choice>) -> Self {
+        let matches = (0..choices.len()).collect();
+        let selected = choices.iter().position(|c| c.current).unwrap_or_default();
+        Self { title: title.to_owned(), choices, query: String::new(), matches, selected }
+    }
+
+    /// The selected choice, if any matches the query.
+    pub fn selected(&self) -> Option<&Choice> {
+        self.matches.get(self.selected).map(|i| &self.choices[*i])
+    }
+
+    fn set_query(&mut self, query: String) {
+        self.matches = filter(&self.choices, &query);
+        self.query = query;
+        self.selected = 0;
+    }
+
+    /// Handle a key press, returning the outcome once the user is done.
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<Outcome> {
+        if key.kind != KeyEventKind::Press {
+            return None;
+        }
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return Some(Outcome::Cancelled),
+            KeyCode::Char('c') if control => return Some(Outcome::Cancelled),
+            KeyCode::Enter => return self.selected().map(|c| Outcome::Picked(c.name.clone())),
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Char('p') if control => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected = (self.selected + 1).min(self.matches.len().saturating_sub(1)),
+            KeyCode::Char('n') if control => self.selected = (self.selected + 1).min(self.matches.len().saturating_sub(1)),
+            KeyCode::Char('u') if control => self.set_query(String::new()),
+            KeyCode::Backspace => {
+                let mut query = self.query.clone();
+                query.pop();
+                self.set_query(query);
+            }
+            KeyCode::Char(c) if !control => self.set_query(format!("{}{}", self.query, c)),
+            _ => {}
+        }
+        None
+    }
+
+    pub fn draw(&self, frame: &mut Frame) {
+        let [input, list] = Layout::vertical([Constraint::Length(3), Constraint::Min(1)]).areas(frame.area());
+
+        let count = format!(" {}/{} ", self.matches.len(), self.choices.len());
+        let prompt = Line::from(vec![Span::from("> ").bold(), Span::from(self.query.as_str())]);
+        let block = Block::bordered().title(format!(" {} ", self.title)).title_bottom(Line::from(count).right_aligned());
+        frame.render_widget(Paragraph::new(prompt).block(block), input);
+        frame.set_cursor_position((input.x + 3 + self.query.chars().count() as u16, input.y + 1));
+
+        let width = self.choices.iter().map(|c| c.name.chars().count()).max().unwrap_or_default();
+        let items = self.matches.iter().map(|i| {
+            let choice = &self.choices[*i];
+            let marker = if choice.current { "* " } else { "  " };
+            // Pad by characters; wide glyphs may still misalign slightly.
+            let padding = width - choice.name.chars().count();
+            ListItem::new(Line::from(vec![
+                Span::from(marker).fg(Color::Green),
+                Span::from(format!("{}{}  ", choice.name, " ".repeat(padding))),
+                Span::from(choice.detail.as_str()).fg(Color::DarkGray),
+            ]))
+        });
+        let help = " enter select  esc cancel ";
+        let widget = List::new(items)
+            .block(Block::bordered().title_bottom(help))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default().with_selected(self.selected().map(|_| self.selected));
+        frame.render_stateful_widget(widget, list, &mut state);
+    }
+}
+
+/// Let the user pick from `choices` full-screen, returning `None` if they
+/// cancelled.
+pub fn pick(title: &str, choices: Vec<Choice>) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let mut picker = Picker::new(title, choices);
+    let mut terminal = ratatui::init();
+    let result = loop {
+        if let Err(e) = terminal.draw(|frame| picker.draw(frame)) {
+            break Err(e);
+        }
+        match event::read() {
+            Ok(Event::Key(key)) => match picker.handle_key(key) {
+                Some(Outcome::Picked(name)) => break Ok(Some(name)),
+                Some(Outcome::Cancelled) => break Ok(None),
+                None => {}
+            },
+            Ok(_) => {}
+            Err(e) => break Err(e),
+        }
+    };
+    ratatui::restore();
+    Ok(result?)
+}
+
+/// Let the user pick from `choices` by number or search text, for when
+/// there is no terminal to draw on. Searching lists the matches again
+/// unless exactly one matches. Empty input or end of input cancels.
+pub fn pick_numbered<R: BufRead, W: Write>(title: &str, choices: &[Choice], mut input: R, mut output: W) -> io::Result<Option<String>> {
+    let width = choices.iter().map(|c| c.name.chars().count()).max().unwrap_or_default();
+    let mut shown: Vec<usize> = (0..choices.len()).collect();
+
+    loop {
+        writeln!(output, "{}:", title)?;
+        for (n, i) in shown.iter().enumerate() {
+            let choice = &choices[*i];
+            let padding = width - choice.name.chars().count();
+            let current = if choice.current { " (current)" } else { "" };
+            writeln!(output, "{:>4}) {}{}  {}{}", n + 1, choice.name, " ".repeat(padding), choice.detail, current)?;
+        }
+        write!(output, "Number or search text (empty to cancel): ")?;
+        output.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let answer = line.trim();
+        if answer.is_empty() {
+            return Ok(None);
+        }
+
+        if let Ok(n) = answer.parse::<usize>() {
+            match n.checked_sub(1).and_then(|n| shown.get(n)) {
+                Some(i) => return Ok(Some(choices[*i].name.clone())),
+                None => writeln!(output, "No choice numbered {}", n)?,
+            }
+            continue;
+        }
+        let matches = filter(choices, answer);
+        match matches.len() {
+            0 => writeln!(output, "Nothing matches `{}`", answer)?,
+            1 => return Ok(Some(choices[matches[0]].name.clone())),
+            _ => shown = matches,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ratatui::{backend::TestBackend, Terminal};
+
+    fn proxies() -> ProxyList {
+        serde_json::from_str(
+            r#"{"proxies":{
+            "Proxy":{"type":"Selector","now":"🇯🇵 Tokyo 03 | IPLC","all":["🇭🇰 Hong Kong 01","🇯🇵 Tokyo 03 | IPLC","🇺🇸 Los Angeles"]},
+            "Auto":{"type":"URLTest","now":"🇭🇰 Hong Kong 01","all":["🇭🇰 Hong Kong 01"]},
+            "🇭🇰 Hong Kong 01":{"type":"Trojan","history":[{"time":"2024-01-01T00:00:00Z","delay":0}]},
+            "🇯🇵 Tokyo 03 | IPLC":{"type":"Trojan","history":[{"time":"2024-01-01T00:00:00Z","delay":45}]},
+            "🇺🇸 Los Angeles":{"type":"Trojan","history":[]}}}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_choices() {
+        let proxies = proxies();
+        let groups = group_choices(&proxies);
+        assert_eq!(groups.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(), vec!["Proxy"]);
+        assert!(member_choices(&proxies, "Auto").is_none());
+
+        let members = member_choices(&proxies, "Proxy").unwrap();
+        let details: Vec<(&str, bool)> = members.iter().map(|c| (c.detail.as_str(), c.current)).collect();
+        assert_eq!(details, vec![("timeout", false), ("45 ms", true), ("", false)]);
+    }
+
+    #[test]
+    fn test_fuzzy() {
+        assert!(fuzzy_score("tk3", "🇯🇵 Tokyo 03 | IPLC").is_some());
+        assert!(fuzzy_score("tky4", "🇯🇵 Tokyo 03 | IPLC").is_none());
+        assert!(fuzzy_score("hk", "Hong Kong") > fuzzy_score("hk", "Shanghai Kunming"));
+
+        let members = member_choices(&proxies(), "Proxy").unwrap();
+        assert_eq!(filter(&members, "los"), vec![2]);
+        assert_eq!(filter(&members, "o"), vec![0, 1, 2]);
+        assert_eq!(filter(&members, "ong"), vec![0, 2]);
+    }
+
+    #[test]
+    fn test_picker() {
+        let press = |picker: &mut Picker, code| picker.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+        let mut picker = Picker::new("Proxy", member_choices(&proxies(), "Proxy").unwrap());
+        assert_eq!(picker.selected().unwrap().name, "🇯🇵 Tokyo 03 | IPLC");
+
+        let mut terminal = Terminal::new(TestBackend::new(60, 10)).unwrap();
+        terminal.draw(|frame| picker.draw(frame)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        assert!(screen.contains("45 ms") && screen.contains("3/3"), "{}", screen);
+
+        for c in "angel".chars() {
+            press(&mut picker, KeyCode::Char(c));
+        }
+        assert_eq!(picker.query, "angel");
+        assert_eq!(press(&mut picker, KeyCode::Enter), Some(Outcome::Picked("🇺🇸 Los Angeles".to_owned())));
+
+        press(&mut picker, KeyCode::Char('x'));
+        assert_eq!(press(&mut picker, KeyCode::Enter), None);
+        press(&mut picker, KeyCode::Backspace);
+        assert_eq!(press(&mut picker, KeyCode::Esc), Some(Outcome::Cancelled));
+    }
+
+    #[test]
+    fn test_pick_numbered() {
+        let members = member_choices(&proxies(), "Proxy").unwrap();
+        let mut output = Vec::new();
+        let picked = pick_numbered("Proxy", &members, "2\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(picked.as_deref(), Some("🇯🇵 Tokyo 03 | IPLC"));
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("   2) 🇯🇵 Tokyo 03 | IPLC  45 ms (current)"), "{}", output);
+
+        // Searching narrows the list, then numbers refer to the matches.
+        let mut output = Vec::new();
+        let picked = pick_numbered("Proxy", &members, "ong\n2\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(picked.as_deref(), Some("🇺🇸 Los Angeles"));
+        assert_eq!(pick_numbered("Proxy", &members, "iplc\n".as_bytes(), io::sink()).unwrap().as_deref(), Some("🇯🇵 Tokyo 03 | IPLC"));
+        assert_eq!(pick_numbered("Proxy", &members, "9\n".as_bytes(), io::sink()).unwrap(), None);
+    }
+}
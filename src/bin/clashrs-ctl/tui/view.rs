@@ -0,0 +1,231 @@
+//! Drawing of the dashboard from an [`App`].
+
+use std::time::SystemTime;
+
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, List, ListItem, ListState, Paragraph, Row, Sparkline, Table, TableState, Tabs},
+    Frame,
+};
+
+use super::{App, Tab};
+use crate::output::{format_age, format_bytes};
+use clashrsctl::config::{ConfigLogLevel, ConfigMode};
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [top, tabs, body, status] = Layout::vertical([
+        Constraint::Length(7),
+        Constraint::Length(1),
+        Constraint::Min(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [traffic, core] = Layout::horizontal([Constraint::Min(20), Constraint::Length(36)]).areas(top);
+
+    draw_traffic(frame, app, traffic);
+    draw_core(frame, app, core);
+
+    let titles = Tab::ALL.iter().enumerate().map(|(i, tab)| format!("{} {}", i + 1, tab.title()));
+    let tabs_widget = Tabs::new(titles)
+        .select(app.tab.index())
+        .highlight_style(Style::new().add_modifier(Modifier::BOLD | Modifier::REVERSED));
+    frame.render_widget(tabs_widget, tabs);
+
+    match app.tab {
+        Tab::Connections => draw_connections(frame, app, body),
+        Tab::Proxies => draw_proxies(frame, app, body),
+        Tab::Logs => draw_logs(frame, app, body),
+    }
+    draw_status(frame, app, status);
+}
+
+fn rate(bytes: u64) -> String {
+    format!("{}/s", format_bytes(bytes as f64))
+}
+
+fn draw_traffic(frame: &mut Frame, app: &App, area: Rect) {
+    let up = app.upload.back().copied().unwrap_or_default();
+    let down = app.download.back().copied().unwrap_or_default();
+    let block = Block::bordered().title(format!(" Traffic  ↑ {}  ↓ {} ", rate(up), rate(down)));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    // The latest samples that fit, on a shared scale.
+    let width = inner.width as usize;
+    let recent = |samples: &std::collections::VecDeque<u64>| -> Vec<u64> {
+        samples.iter().skip(samples.len().saturating_sub(width)).copied().collect()
+    };
+    let (up, down) = (recent(&app.upload), recent(&app.download));
+    let max = up.iter().chain(down.iter()).copied().max().unwrap_or_default().max(1);
+
+    let [down_area, up_area] = Layout::vertical([Constraint::Fill(1), Constraint::Fill(1)]).areas(inner);
+    frame.render_widget(Sparkline::default().data(&down).max(max).style(Style::new().fg(Color::Cyan)), down_area);
+    frame.render_widget(Sparkline::default().data(&up).max(max).style(Style::new().fg(Color::Magenta)), up_area);
+}
+
+fn draw_core(frame: &mut Frame, app: &App, area: Rect) {
+    let config = app.config.as_ref();
+    let mode = match config.and_then(|c| c.mode.as_ref()) {
+        Some(ConfigMode::Rule) => "rule",
+        Some(ConfigMode::Global) => "global",
+        Some(ConfigMode::Direct) => "direct",
+        None => "-",
+    };
+    let level = config.and_then(|c| c.log_level.as_ref()).map_or("-", level_name);
+    let ports: Vec<String> = config
+        .map(|c| {
+            [("http", c.port), ("socks", c.socks_port), ("mixed", c.mixed_port)]
+                .into_iter()
+                .filter_map(|(name, port)| Some(format!("{} {}", name, port?)))
+                .collect()
+        })
+        .unwrap_or_default();
+    let allow_lan = match config.and_then(|c| c.allow_lan) {
+        Some(true) => "yes",
+        Some(false) => "no",
+        None => "-",
+    };
+    let totals = app.connections.as_ref().map_or("-".to_owned(), |c| {
+        format!(
+            "{}  ↑ {} ↓ {}",
+            c.connections.len(),
+            format_bytes(c.upload_total as f64),
+            format_bytes(c.download_total as f64)
+        )
+    });
+
+    let field = |label: &str, value: String| Line::from(vec![Span::from(format!("{:<11}", label)).bold(), Span::from(value)]);
+    let lines = vec![
+        field("Mode", mode.to_owned()),
+        field("Log level", level.to_owned()),
+        field("Ports", if ports.is_empty() { "-".to_owned() } else { ports.join(", ") }),
+        field("Allow LAN", allow_lan.to_owned()),
+        field("Conns", totals),
+    ];
+    frame.render_widget(Paragraph::new(lines).block(Block::bordered().title(" Core ")), area);
+}
+
+fn draw_connections(frame: &mut Frame, app: &App, area: Rect) {
+    let now = SystemTime::now();
+    let rows = app.rates.connections.iter().map(|c| {
+        Row::new(vec![
+            c.host.clone(),
+            c.process.clone(),
+            c.chains.iter().rev().cloned().collect::<Vec<_>>().join(" > "),
+            rate(c.rate.upload as u64),
+            rate(c.rate.download as u64),
+            format_bytes((c.upload + c.download) as f64),
+            c.age(now).map_or("-".to_owned(), format_age),
+        ])
+    });
+    let widths = [
+        Constraint::Fill(3),
+        Constraint::Length(14),
+        Constraint::Fill(2),
+        Constraint::Length(11),
+        Constraint::Length(11),
+        Constraint::Length(10),
+        Constraint::Length(7),
+    ];
+    let table = Table::new(rows, widths)
+        .header(Row::new(["Host", "Process", "Chains", "Up", "Down", "Total", "Age"]).bold())
+        .block(Block::bordered().title(format!(" Connections ({}) ", app.rates.connections.len())))
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+
+    let mut state = TableState::default().with_selected((!app.rates.connections.is_empty()).then_some(app.connection));
+    frame.render_stateful_widget(table, area, &mut state);
+}
+
+fn draw_proxies(frame: &mut Frame, app: &App, area: Rect) {
+    let [groups_area, members_area] = Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(area);
+    let focused = Style::new().add_modifier(Modifier::REVERSED);
+    let unfocused = Style::new().add_modifier(Modifier::BOLD);
+
+    let proxies = app.proxies.as_ref();
+    let groups = app.groups();
+    let items = groups.iter().map(|name| {
+        let info = proxies.and_then(|p| p.get(name));
+        let r#type = info.and_then(|i| i["type"].as_str()).unwrap_or_default();
+        let now = info.and_then(|i| i["now"].as_str()).unwrap_or_default();
+        ListItem::new(format!("{:<16} {:<11} {}", name, r#type, now))
+    });
+    let list = List::new(items)
+        .block(Block::bordered().title(" Groups "))
+        .highlight_style(if app.members_focused { unfocused } else { focused });
+    let mut state = ListState::default().with_selected((!groups.is_empty()).then_some(app.group));
+    frame.render_stateful_widget(list, groups_area, &mut state);
+
+    let group = app.group_info();
+    let now = group.and_then(|(_, info)| info["now"].as_str());
+    let members = app.members();
+    let items = members.iter().map(|name| {
+        let marker = if Some(*name) == now { "*" } else { " " };
+        let (delay, color) = match app.delay(name) {
+            Some(Some(delay)) => (format!("{} ms", delay), if delay < 300 { Color::Green } else { Color::Yellow }),
+            Some(None) => ("timeout".to_owned(), Color::Red),
+            None => ("-".to_owned(), Color::DarkGray),
+        };
+        ListItem::new(Line::from(vec![Span::from(format!("{} {:<24} ", marker, name)), Span::from(delay).fg(color)]))
+    });
+    let title = match group {
+        Some((name, info)) => format!(" {} ({}) ", name, info["type"].as_str().unwrap_or_default()),
+        None => " Members ".to_owned(),
+    };
+    let list = List::new(items)
+        .block(Block::bordered().title(title))
+        .highlight_style(if app.members_focused { focused } else { Style::new() });
+    let mut state = ListState::default().with_selected((app.members_focused && !members.is_empty()).then_some(app.member));
+    frame.render_stateful_widget(list, members_area, &mut state);
+}
+
+fn level_name(level: &ConfigLogLevel) -> &'static str {
+    match level {
+        ConfigLogLevel::Debug => "debug",
+        ConfigLogLevel::Info => "info",
+        ConfigLogLevel::Warning => "warning",
+        ConfigLogLevel::Error => "error",
+    }
+}
+
+fn draw_logs(frame: &mut Frame, app: &App, area: Rect) {
+    let block = Block::bordered().title(format!(" Logs ({} and above) ", level_name(&app.log_level)));
+    let height = block.inner(area).height as usize;
+
+    // The newest lines that fit, oldest first.
+    let mut lines: Vec<Line> = app
+        .visible_logs()
+        .rev()
+        .take(height)
+        .map(|log| {
+            let color = match log.r#type {
+                ConfigLogLevel::Debug => Color::DarkGray,
+                ConfigLogLevel::Info => Color::Reset,
+                ConfigLogLevel::Warning => Color::Yellow,
+                ConfigLogLevel::Error => Color::Red,
+            };
+            Line::from(vec![
+                Span::from(format!("{:<8}", level_name(&log.r#type))).fg(color),
+                Span::from(log.payload.clone()),
+            ])
+        })
+        .collect();
+    lines.reverse();
+    frame.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_status(frame: &mut Frame, app: &App, area: Rect) {
+    let keys = match app.tab {
+        Tab::Connections => "j/k move  x close  X close all",
+        Tab::Proxies => "h/l focus  enter select  t test delay",
+        Tab::Logs => "l level  c clear",
+    };
+    let keys = format!("{}  m mode  tab switch  q quit", keys);
+    let [status, help] = Layout::horizontal([Constraint::Min(10), Constraint::Length(keys.len() as u16)]).areas(area);
+
+    let (dot, color) = if app.connected { ("●", Color::Green) } else { ("○", Color::Red) };
+    let line = Line::from(vec![Span::from(dot).fg(color), Span::from(" "), Span::from(app.status.as_str())]);
+    frame.render_widget(Paragraph::new(line), status);
+    frame.render_widget(Paragraph::new(keys).fg(Color::DarkGray), help);
+}
@@ -0,0 +1,294 @@
+//! Detect what the running core is and which optional endpoints it has.
+//! Requests for endpoints that probing found missing fail before they
+//! reach the core; a `404` for any other optional endpoint is reported as
+//! the feature being unsupported.
+
+use std::collections::BTreeSet;
+
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    clash_core::CoreError,
+    version::{CoreFlavor, SemVer, Version},
+    ClashRequest, ClashRequestBuilder,
+};
+
+/// Endpoints that only some cores provide.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Feature {
+    /// `GET /group`
+    Group,
+    /// `GET /providers/proxies` and `GET /providers/rules`
+    Providers,
+    /// `GET /dns/query`
+    DnsQuery,
+    /// `POST /cache/fakeip/flush`
+    FakeIpFlush,
+    /// `POST /restart`
+    Restart,
+    /// `POST /configs/geo`
+    GeoUpdate,
+    /// `GET /memory`
+    Memory,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 7] = [
+        Feature::Group,
+        Feature::Providers,
+        Feature::DnsQuery,
+        Feature::FakeIpFlush,
+        Feature::Restart,
+        Feature::GeoUpdate,
+        Feature::Memory,
+    ];
+
+    /// A side-effect free `GET` path answering `404` when the feature is
+    /// missing, if there is one.
+    fn probe_path(&self) -> Option<&'static str> {
+        match self {
+            Feature::Group => Some("group"),
+            Feature::Providers => Some("providers/proxies"),
+            Feature::DnsQuery => Some("dns/query"),
+            _ => None,
+        }
+    }
+
+    /// Whether a core of `flavor` is expected to have the feature. This is a
+    /// guess for display only; requests are never refused on it.
+    fn expected(&self, flavor: CoreFlavor) -> bool {
+        match flavor {
+            CoreFlavor::Meta => true,
+            CoreFlavor::Premium => matches!(self, Feature::Providers | Feature::DnsQuery | Feature::FakeIpFlush),
+            CoreFlavor::Clash => matches!(self, Feature::Providers),
+        }
+    }
+}
+
+impl std::fmt::Display for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Feature::Group => "group",
+            Feature::Providers => "providers",
+            Feature::DnsQuery => "dns query",
+            Feature::FakeIpFlush => "fake-ip flush",
+            Feature::Restart => "restart",
+            Feature::GeoUpdate => "GeoIP update",
+            Feature::Memory => "memory",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug)]
+pub struct UnsupportedFeature {
+    pub feature: Feature,
+    pub flavor: CoreFlavor,
+    pub version: String,
+}
+
+impl std::fmt::Display for UnsupportedFeature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The core ({} {}) does not support {}", self.flavor, self.version, self.feature)
+    }
+}
+
+impl std::error::Error for UnsupportedFeature {}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Capabilities {
+    pub flavor: CoreFlavor,
+    pub version: String,
+    pub semver: Option<SemVer>,
+    pub features: BTreeSet<Feature>,
+    /// Features checked against the core rather than guessed.
+    #[serde(skip)]
+    pub probed: BTreeSet<Feature>,
+}
+
+impl From<&Version> for Capabilities {
+    /// Infer the features from the flavor alone.
+    fn from(version: &Version) -> Self {
+        let flavor = version.flavor();
+        Self {
+            flavor,
+            version: version.version.clone(),
+            semver: version.semver(),
+            features: Feature::ALL.iter().copied().filter(|f| f.expected(flavor)).collect(),
+            probed: BTreeSet::new(),
+        }
+    }
+}
+
+impl Capabilities {
+    /// Classify the core from `/version` only.
+    pub async fn detect(client: ClashRequestBuilder) -> Result<Self, Box<dyn std::error::Error>> {
+        let version = client.version().send().await?;
+        Ok(Self::from(&version))
+    }
+
+    /// Classify the core from `/version`, then check the features that
+    /// can be probed without side effects against the core itself.
+    pub async fn probe(client: ClashRequestBuilder) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut capabilities = Self::detect(client.clone()).await?;
+
+        for feature in Feature::ALL.iter() {
+            if let Some(path) = feature.probe_path() {
+                let probe = Probe::new(&client, path);
+                let (code, _) = crate::get_with_status_code_request(probe).await?;
+                if code == reqwest::StatusCode::NOT_FOUND {
+                    capabilities.features.remove(feature);
+                } else {
+                    capabilities.features.insert(*feature);
+                }
+                capabilities.probed.insert(*feature);
+            }
+        }
+
+        Ok(capabilities)
+    }
+
+    pub fn supports(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    fn unsupported(&self, feature: Feature) -> UnsupportedFeature {
+        UnsupportedFeature {
+            feature,
+            flavor: self.flavor,
+            version: self.version.clone(),
+        }
+    }
+
+    pub fn require(&self, feature: Feature) -> Result<(), UnsupportedFeature> {
+        if self.supports(feature) {
+            Ok(())
+        } else {
+            Err(self.unsupported(feature))
+        }
+    }
+
+    /// Send `request`, failing early if it needs a feature probing found
+    /// missing. A feature that was only guessed is tried, and a `404` for it
+    /// becomes [`UnsupportedFeature`].
+    pub async fn send<R: ClashRequest + Send>(&self, request: R) -> Result<R::Response, Box<dyn std::error::Error>> {
+        let feature = request.feature();
+        if let Some(feature) = feature.filter(|f| self.probed.contains(f)) {
+            self.require(feature)?;
+        }
+        request.send().await.map_err(|e| match (feature, e.downcast_ref::<CoreError>()) {
+            (Some(feature), Some(CoreError::NotSupported)) => Box::new(self.unsupported(feature)) as Box<dyn std::error::Error>,
+            _ => e,
+        })
+    }
+}
+
+struct Probe {
+    ip: String,
+    port: u16,
+    secret: Option<String>,
+    path: &'static str,
+}
+
+impl Probe {
+    fn new(client: &ClashRequestBuilder, path: &'static str) -> Self {
+        let client = client.clone().or_default();
+        Self {
+            ip: client.ip.unwrap(),
+            port: client.port.unwrap(),
+            secret: client.secret,
+            path,
+        }
+    }
+}
+
+#[async_trait]
+impl ClashRequest for Probe {
+    type Response = ();
+
+    fn get_dest(&self) -> String {
+        self.ip.clone()
+    }
+
+    fn get_port(&self) -> u16 {
+        self.port
+    }
+
+    fn get_secret(&self) -> Option<String> {
+        self.secret.clone()
+    }
+
+    fn get_method(&self) -> String {
+        "GET".to_owned()
+    }
+
+    fn get_path(&self) -> String {
+        self.path.to_owned()
+    }
+
+    fn get_query_parameter(&self) -> String {
+        "".to_owned()
+    }
+
+    fn get_body(&self) -> String {
+        "".to_owned()
+    }
+
+    async fn send(self) -> Result<Self::Response, Box<dyn std::error::Error>> {
+        crate::get_with_status_code_request(self).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{dns::DnsType, mock::MockController};
+
+    #[test]
+    fn test_infer_from_version() {
+        let clash = Capabilities::from(&Version::try_from(r#"{"version":"v1.18.0"}"#.to_owned()).unwrap());
+        assert!(clash.supports(Feature::Providers));
+        assert!(!clash.supports(Feature::Restart));
+
+        let err = clash.require(Feature::Memory).unwrap_err();
+        assert_eq!(err.to_string(), "The core (Clash v1.18.0) does not support memory");
+    }
+
+    #[tokio::test]
+    async fn test_probe_and_fail_fast() {
+        let mock = MockController::new();
+        mock.route("GET", "version", 200, r#"{"premium":true,"version":"2023.08.17"}"#)
+            .route("GET", "providers/proxies", 200, r#"{"providers":{}}"#)
+            .route("GET", "group", 200, r#"{"proxies":[]}"#);
+        let client = mock.start().await;
+
+        let capabilities = Capabilities::probe(client.clone()).await.unwrap();
+        assert_eq!(capabilities.flavor, CoreFlavor::Premium);
+        assert!(capabilities.supports(Feature::Group));
+        assert!(!capabilities.supports(Feature::DnsQuery));
+
+        let before = mock.requests().len();
+        let err = capabilities.send(client.clone().dns().query("example.com", DnsType::A)).await.unwrap_err();
+        assert!(err.to_string().contains("does not support dns query"));
+        assert_eq!(mock.requests().len(), before);
+
+        // Restart cannot be probed, so it is tried and the 404 reported.
+        let err = capabilities.send(client.core().restart()).await.unwrap_err();
+        assert!(err.to_string().contains("does not support restart"));
+        assert_eq!(mock.requests().len(), before + 1);
+    }
+
+    #[tokio::test]
+    async fn test_guess_does_not_refuse() {
+        let mock = MockController::new();
+        mock.route("GET", "version", 200, r#"{"version":"v1.18.0"}"#)
+            .route("POST", "cache/fakeip/flush", 204, "");
+        let client = mock.start().await;
+
+        let capabilities = Capabilities::detect(client.clone()).await.unwrap();
+        assert!(!capabilities.supports(Feature::FakeIpFlush));
+        capabilities.send(client.dns().flush_fakeip()).await.unwrap();
+    }
+}
@@ -0,0 +1,240 @@
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::{Serialize, Deserialize};
+
+use crate::{capability::Feature, stream::ClashStream, ClashRequest, ClashRequestBuilder};
+
+#[derive(Debug)]
+pub enum CoreError {
+    /// The core answered `404`, so it does not provide the endpoint.
+    NotSupported,
+    FormatError,
+    UnknownError(u16),
+}
+
+impl std::fmt::Display for CoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use CoreError::*;
+        match self {
+            NotSupported => write!(f, "Endpoint not supported by the core"),
+            FormatError => write!(f, "Request format error"),
+            UnknownError(code) => write!(f, "Unknown error: {}", code),
+        }
+    }
+}
+
+impl std::error::Error for CoreError {}
+
+impl CoreError {
+    pub(crate) fn check(code: StatusCode) -> Result<(), Box<dyn std::error::Error>> {
+        match code {
+            c if c.is_success() => Ok(()),
+            StatusCode::BAD_REQUEST => Err(Box::new(CoreError::FormatError)),
+            StatusCode::NOT_FOUND => Err(Box::new(CoreError::NotSupported)),
+            c => Err(Box::new(CoreError::UnknownError(c.as_u16()))),
+        }
+    }
+}
+
+/// Operational endpoints of the core itself.
+pub struct ClashCore {
+    ip: String,
+    port: u16,
+    secret: Option<String>,
+}
+
+impl From<ClashRequestBuilder> for ClashCore {
+    fn from(r: ClashRequestBuilder) -> Self {
+        Self {
+            ip: r.ip.unwrap(),
+            port: r.port.unwrap(),
+            secret: r.secret,
+        }
+    }
+}
+
+impl ClashCore {
+    pub fn restart(self) -> ClashRestart {
+        ClashRestart { ip: self.ip, port: self.port, secret: self.secret }
+    }
+
+    pub fn update_geo(self) -> ClashGeoUpdate {
+        ClashGeoUpdate { ip: self.ip, port: self.port, secret: self.secret }
+    }
+
+    pub fn memory(self) -> ClashMemory {
+        ClashMemory { ip: self.ip, port: self.port, secret: self.secret }
+    }
+}
+
+pub struct ClashRestart {
+    ip: String,
+    port: u16,
+    secret: Option<String>,
+}
+
+#[async_trait]
+impl ClashRequest for ClashRestart {
+    type Response = ();
+
+    fn get_dest(&self) -> String {
+        self.ip.clone()
+    }
+
+    fn get_port(&self) -> u16 {
+        self.port
+    }
+
+    fn get_secret(&self) -> Option<String> {
+        self.secret.clone()
+    }
+
+    fn get_method(&self) -> String {
+        "POST".to_owned()
+    }
+
+    fn get_path(&self) -> String {
+        "restart".to_owned()
+    }
+
+    fn get_query_parameter(&self) -> String {
+        "".to_owned()
+    }
+
+    fn get_body(&self) -> String {
+        "{}".to_owned()
+    }
+
+    fn feature(&self) -> Option<Feature> {
+        Some(Feature::Restart)
+    }
+
+    async fn send(self) -> Result<Self::Response, Box<dyn std::error::Error>> {
+        use crate::post_request;
+        CoreError::check(post_request(self).await?)
+    }
+}
+
+pub struct ClashGeoUpdate {
+    ip: String,
+    port: u16,
+    secret: Option<String>,
+}
+
+#[async_trait]
+impl ClashRequest for ClashGeoUpdate {
+    type Response = ();
+
+    fn get_dest(&self) -> String {
+        self.ip.clone()
+    }
+
+    fn get_port(&self) -> u16 {
+        self.port
+    }
+
+    fn get_secret(&self) -> Option<String> {
+        self.secret.clone()
+    }
+
+    fn get_method(&self) -> String {
+        "POST".to_owned()
+    }
+
+    fn get_path(&self) -> String {
+        "configs/geo".to_owned()
+    }
+
+    fn get_query_parameter(&self) -> String {
+        "".to_owned()
+    }
+
+    fn get_body(&self) -> String {
+        "{}".to_owned()
+    }
+
+    fn feature(&self) -> Option<Feature> {
+        Some(Feature::GeoUpdate)
+    }
+
+    async fn send(self) -> Result<Self::Response, Box<dyn std::error::Error>> {
+        use crate::post_request;
+        CoreError::check(post_request(self).await?)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Memory {
+    pub inuse: u64,
+    pub oslimit: u64,
+}
+
+pub struct ClashMemory {
+    ip: String,
+    port: u16,
+    secret: Option<String>,
+}
+
+#[async_trait]
+impl ClashRequest for ClashMemory {
+    type Response = ClashStream<Memory>;
+
+    fn get_dest(&self) -> String {
+        self.ip.clone()
+    }
+
+    fn get_port(&self) -> u16 {
+        self.port
+    }
+
+    fn get_secret(&self) -> Option<String> {
+        self.secret.clone()
+    }
+
+    fn get_method(&self) -> String {
+        "GET".to_owned()
+    }
+
+    fn get_path(&self) -> String {
+        "memory".to_owned()
+    }
+
+    fn get_query_parameter(&self) -> String {
+        "".to_owned()
+    }
+
+    fn get_body(&self) -> String {
+        "".to_owned()
+    }
+
+    fn feature(&self) -> Option<Feature> {
+        Some(Feature::Memory)
+    }
+
+    async fn send(self) -> Result<Self::Response, Box<dyn std::error::Error>> {
+        use crate::stream::get_stream_response;
+
+        let response = get_stream_response(self).await?;
+        CoreError::check(response.status())?;
+        Ok(ClashStream::new(Box::pin(response.bytes_stream())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::mock::MockController;
+    use crate::ClashRequest;
+
+    #[tokio::test]
+    async fn test_restart_and_update_geo() {
+        let mock = MockController::new();
+        mock.route("POST", "restart", 200, r#"{"status":"ok"}"#);
+        let client = mock.start().await;
+
+        client.clone().core().restart().send().await.unwrap();
+        let err = client.core().update_geo().send().await.unwrap_err();
+
+        assert_eq!(err.to_string(), "Endpoint not supported by the core");
+        assert_eq!(mock.requests()[1].path, "configs/geo");
+    }
+}
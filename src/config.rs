@@ -62,7 +62,7 @@ pub struct ClashConfigLoad {
     secret: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub port: Option<u16>,
@@ -348,6 +348,12 @@ impl Config {
     }
 }
 impl ClashConfigPatch {
+    /// Replace the whole patch, e.g. to restore a snapshot taken with
+    /// `ClashConfig::get`.
+    pub fn config(self, config: Config) -> Self {
+        Self { config, ..self }
+    }
+
     pub fn tproxy_port(self, port: u16) -> Self {
         Self { config: self.config.tproxy_port(port), ..self }
     }
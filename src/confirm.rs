@@ -0,0 +1,260 @@
+//! Commit-confirmed configuration changes.
+//!
+//! A change is applied only after the current configuration has been
+//! snapshotted. Unless a health check passes and the operator confirms
+//! within the given time, the snapshot is restored, the same way
+//! `commit confirmed` works on network gear.
+
+use std::future::Future;
+use std::time::Duration;
+
+use crate::{
+    config::Config,
+    ClashRequest, ClashRequestBuilder,
+};
+
+pub const DEFAULT_CHECK_URL: &str = "http://www.gstatic.com/generate_204";
+pub const DEFAULT_CHECK_TIMEOUT: u32 = 5000;
+
+#[derive(Debug)]
+pub enum ConfirmError {
+    /// The health check failed and the snapshot was restored.
+    HealthCheckFailed(String),
+    /// The snapshot could not be restored. The core may be left in the
+    /// new configuration.
+    RollbackFailed(String),
+}
+
+impl std::fmt::Display for ConfirmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use ConfirmError::*;
+        match self {
+            HealthCheckFailed(reason) => write!(f, "Health check failed, change rolled back: {}", reason),
+            RollbackFailed(reason) => write!(f, "Rollback failed: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfirmError {}
+
+/// The change to apply.
+#[derive(Debug, Clone)]
+pub enum Change {
+    /// Send a patch through `ClashConfigPatch`.
+    Patch(Config),
+    /// Load a profile through `ClashConfigLoad`.
+    Load { path: String, force: bool },
+}
+
+/// Checks run after applying a change. The controller is always
+/// required to answer; a delay test through a proxy is optional.
+#[derive(Debug, Clone)]
+pub struct HealthCheck {
+    proxy: Option<String>,
+    url: String,
+    timeout: u32,
+}
+
+impl HealthCheck {
+    pub fn new() -> Self {
+        Self {
+            proxy: None,
+            url: DEFAULT_CHECK_URL.to_owned(),
+            timeout: DEFAULT_CHECK_TIMEOUT,
+        }
+    }
+
+    /// Also require a successful delay test through `proxy`.
+    pub fn proxy(self, proxy: &str) -> Self {
+        Self { proxy: Some(proxy.to_owned()), ..self }
+    }
+
+    pub fn url(self, url: &str) -> Self {
+        Self { url: url.to_owned(), ..self }
+    }
+
+    pub fn timeout(self, timeout: u32) -> Self {
+        Self { timeout, ..self }
+    }
+
+    pub async fn run(&self, client: &ClashRequestBuilder) -> Result<(), Box<dyn std::error::Error>> {
+        client.clone().version().send().await?;
+
+        if let Some(proxy) = self.proxy.as_ref() {
+            client.clone()
+                .proxies()
+                .get(proxy)
+                .delay(&self.url, self.timeout)
+                .send()
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The operator confirmed in time; the change stays.
+    Confirmed,
+    /// No confirmation arrived in time; the snapshot was restored.
+    RolledBack,
+}
+
+/// A change that has been applied and passed its health check, waiting
+/// for confirmation.
+pub struct PendingChange {
+    client: ClashRequestBuilder,
+    snapshot: Config,
+    rollback_path: Option<String>,
+}
+
+/// Snapshot the running configuration, apply `change` and run `check`.
+///
+/// If the health check fails the snapshot is restored before returning
+/// `ConfirmError::HealthCheckFailed`.
+pub async fn apply(
+    client: ClashRequestBuilder,
+    change: Change,
+    check: &HealthCheck,
+) -> Result<PendingChange, Box<dyn std::error::Error>> {
+    let snapshot = client.clone().config().get().send().await?;
+    let pending = PendingChange {
+        client: client.clone(),
+        snapshot,
+        rollback_path: None,
+    };
+
+    match change {
+        Change::Patch(config) => client.clone().config().patch().config(config).send().await?,
+        Change::Load { path, force } => {
+            let load = client.clone().config().load(&path);
+            if force { load.force().send().await? } else { load.send().await? }
+        }
+    }
+
+    if let Err(e) = check.run(&client).await {
+        pending.rollback().await?;
+        return Err(Box::new(ConfirmError::HealthCheckFailed(e.to_string())));
+    }
+
+    Ok(pending)
+}
+
+impl PendingChange {
+    /// Reload this profile on rollback before restoring the snapshot.
+    /// A snapshot only covers the general settings, so reverting a
+    /// `Change::Load` needs the previously loaded profile as well.
+    pub fn rollback_path(self, path: &str) -> Self {
+        Self { rollback_path: Some(path.to_owned()), ..self }
+    }
+
+    pub fn snapshot(&self) -> &Config {
+        &self.snapshot
+    }
+
+    /// Keep the change if `confirmation` resolves to `true` within
+    /// `timeout`, otherwise restore the snapshot.
+    pub async fn confirm_within<F>(self, timeout: Duration, confirmation: F) -> Result<Outcome, Box<dyn std::error::Error>>
+    where
+        F: Future<Output = bool>,
+    {
+        match tokio::time::timeout(timeout, confirmation).await {
+            Ok(true) => Ok(Outcome::Confirmed),
+            _ => {
+                self.rollback().await?;
+                Ok(Outcome::RolledBack)
+            }
+        }
+    }
+
+    /// Restore the snapshot right away.
+    pub async fn rollback(self) -> Result<(), ConfirmError> {
+        let failed = |e: Box<dyn std::error::Error>| ConfirmError::RollbackFailed(e.to_string());
+
+        if let Some(path) = self.rollback_path {
+            self.client.clone().config().load(&path).force().send().await.map_err(failed)?;
+        }
+
+        self.client
+            .config()
+            .patch()
+            .config(self.snapshot)
+            .send()
+            .await
+            .map_err(failed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::config::ConfigMode;
+    use crate::mock::MockController;
+
+    const SNAPSHOT: &str = r#"{"port":7890,"mode":"rule","allow-lan":false}"#;
+
+    fn controller() -> MockController {
+        let mock = MockController::new();
+        mock.route("GET", "configs", 200, SNAPSHOT)
+            .route("PATCH", "configs", 204, "")
+            .route("GET", "version", 200, r#"{"version":"v1.11.0"}"#);
+        mock
+    }
+
+    #[tokio::test]
+    async fn test_rollback_without_confirmation() {
+        let mock = controller();
+        let client = mock.start().await;
+
+        let change = Change::Patch(Config::new().mode(ConfigMode::Global));
+        let pending = apply(client, change, &HealthCheck::new()).await.unwrap();
+        let outcome = pending
+            .confirm_within(Duration::from_millis(10), futures::future::pending())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, Outcome::RolledBack);
+        let patches: Vec<_> = mock.requests().into_iter().filter(|r| r.method == "PATCH").collect();
+        assert_eq!(patches.len(), 2);
+        assert!(patches[0].body.contains("global"));
+        assert!(patches[1].body.contains("rule"));
+    }
+
+    #[tokio::test]
+    async fn test_confirmed_change_is_kept() {
+        let mock = controller();
+        let client = mock.start().await;
+
+        let change = Change::Patch(Config::new().mode(ConfigMode::Global));
+        let pending = apply(client, change, &HealthCheck::new()).await.unwrap();
+        let outcome = pending
+            .confirm_within(Duration::from_secs(5), async { true })
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, Outcome::Confirmed);
+        assert_eq!(mock.requests().iter().filter(|r| r.method == "PATCH").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failed_health_check_rolls_back() {
+        let mock = controller();
+        let client = mock.start().await;
+
+        let check = HealthCheck::new().proxy("node-jp");
+        let change = Change::Patch(Config::new().port(8000));
+        let err = apply(client, change, &check).await.err().unwrap();
+
+        assert!(err.to_string().starts_with("Health check failed"));
+        assert_eq!(mock.requests().iter().filter(|r| r.method == "PATCH").count(), 2);
+    }
+}
@@ -1,6 +1,28 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
 use crate::{ClashRequest, ClashRequestBuilder};
 use serde::{Serialize, Deserialize};
 use async_trait::async_trait;
+use futures::StreamExt;
+
+#[derive(Debug)]
+pub enum ConnectionError {
+    /// The controller refused to close a connection.
+    CloseFailed(u16),
+}
+
+impl std::fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionError::CloseFailed(code) => write!(f, "Failed to close connection: {}", code),
+        }
+    }
+}
+
+impl std::error::Error for ConnectionError {}
 
 pub struct ClashConnections {
     ip: String,
@@ -32,6 +54,9 @@ impl From<ClashRequestBuilder> for ClashConnections {
     }
 }
 
+/// Close requests [`ClashConnections::close_ids`] keeps in flight.
+pub const CLOSE_CONCURRENCY: usize = 16;
+
 impl ClashConnections {
     pub fn close(self) -> ClashCloseConnections {
         ClashCloseConnections {
@@ -44,6 +69,120 @@ impl ClashConnections {
     pub fn close_id(self, id: &str) -> ClashCloseID {
         ClashCloseID { ip: self.ip, port: self.port, secret: self.secret, id: id.to_owned() }
     }
+
+    /// Close the connections with the given IDs, at most
+    /// [`CLOSE_CONCURRENCY`] at a time over one HTTP client, returning the
+    /// result for each ID in the order given.
+    pub async fn close_ids<I>(self, ids: I) -> Vec<(String, Result<(), Box<dyn std::error::Error>>)>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let client = reqwest::Client::new();
+        let client = &client;
+        futures::stream::iter(ids)
+            .map(|id| {
+                let id = id.as_ref().to_owned();
+                let request = ClashCloseID { ip: self.ip.clone(), port: self.port, secret: self.secret.clone(), id: id.clone() };
+                async move { (id, request.send_with(client).await) }
+            })
+            .buffered(CLOSE_CONCURRENCY)
+            .collect()
+            .await
+    }
+}
+
+/// Parse an RFC 3339 timestamp such as `2023-08-17T12:34:56.123456789+08:00`.
+pub(crate) fn parse_rfc3339(s: &str) -> Option<SystemTime> {
+    let (date, time) = s.split_once(['T', 't', ' '])?;
+    let mut date = date.splitn(3, '-').map(|n| n.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+
+    let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+        Some(pos) => time.split_at(pos),
+        None => return None,
+    };
+    let offset = match offset {
+        "Z" | "z" => 0,
+        o => {
+            let sign = if o.starts_with('-') { -1 } else { 1 };
+            let (h, m) = o[1..].split_once(':')?;
+            sign * (h.parse::<i64>().ok()? * 3600 + m.parse::<i64>().ok()? * 60)
+        }
+    };
+
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut time = time.splitn(3, ':').map(|n| n.parse::<i64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    let nanos = match fraction {
+        "" => 0,
+        f if f.len() <= 9 && f.bytes().all(|b| b.is_ascii_digit()) => f.parse::<u32>().ok()? * 10u32.pow(9 - f.len() as u32),
+        _ => return None,
+    };
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    // Days since the epoch of a proleptic Gregorian date.
+    let (y, m) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second - offset;
+    let secs = u64::try_from(secs).ok()?;
+    Some(UNIX_EPOCH + Duration::new(secs, nanos))
+}
+
+/// Format a time as RFC 3339 in UTC, with as many fraction digits as
+/// needed.
+pub fn format_rfc3339(time: SystemTime) -> String {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since.as_secs() as i64;
+    let (days, rem) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+
+    // The inverse of the day count in `parse_rfc3339`.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    let fraction = match since.subsec_nanos() {
+        0 => "".to_owned(),
+        n => format!(".{:09}", n).trim_end_matches('0').to_owned(),
+    };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+        year, month, day, rem / 3600, rem % 3600 / 60, rem % 60, fraction
+    )
+}
+
+/// Serde for optional RFC 3339 timestamps. Unparsable values become
+/// `None` rather than failing the whole response.
+mod rfc3339 {
+    use std::time::SystemTime;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(time: &Option<SystemTime>, serializer: S) -> Result<S::Ok, S::Error> {
+        match time {
+            Some(time) => serializer.serialize_str(&super::format_rfc3339(*time)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<SystemTime>, D::Error> {
+        let s = Option::<String>::deserialize(deserializer)?;
+        Ok(s.as_deref().and_then(super::parse_rfc3339))
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -55,38 +194,219 @@ pub struct Connection {
     pub rule_payload: String,
     pub upload: usize,
     pub download: usize,
-    start: String,
+    /// When the core accepted the connection.
+    #[serde(with = "rfc3339", default)]
+    pub start: Option<SystemTime>,
     pub metadata: Metadata,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Lenient decoding of fields whose JSON type differs between cores.
+mod lenient {
+    use serde::{Deserialize, Deserializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(u64),
+    }
+
+    /// A string that some cores send as a number, or `null`.
+    pub fn string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+        Ok(match Option::<StringOrNumber>::deserialize(deserializer)? {
+            Some(StringOrNumber::String(s)) => s,
+            Some(StringOrNumber::Number(n)) => n.to_string(),
+            None => "".to_owned(),
+        })
+    }
+
+    /// An optional number that some cores send as a string.
+    pub fn number<'de, D: Deserializer<'de>, T: TryFrom<u64>>(deserializer: D) -> Result<Option<T>, D::Error> {
+        Ok(match Option::<StringOrNumber>::deserialize(deserializer)? {
+            Some(StringOrNumber::String(s)) => s.parse::<u64>().ok().and_then(|n| T::try_from(n).ok()),
+            Some(StringOrNumber::Number(n)) => T::try_from(n).ok(),
+            None => None,
+        })
+    }
+}
+
+/// Where a connection comes from and goes to. Fields of the legacy Clash
+/// shape default to empty when a core leaves them out; fields only mihomo
+/// reports are optional.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Metadata {
+    #[serde(default)]
     pub network: String,
+    #[serde(default)]
     pub r#type: String,
-    #[serde(rename(serialize = "sourceIP", deserialize = "sourceIP"))]
+    #[serde(rename(serialize = "sourceIP", deserialize = "sourceIP"), default)]
     pub source_ip: String,
-    #[serde(rename(serialize = "destinationIP", deserialize = "destinationIP"))]
+    #[serde(rename(serialize = "destinationIP", deserialize = "destinationIP"), default)]
     pub destination_ip: String,
-    #[serde(rename(serialize = "sourcePort", deserialize = "sourcePort"))]
+    #[serde(rename(serialize = "sourcePort", deserialize = "sourcePort"), default, deserialize_with = "lenient::string")]
     pub source_port: String,
-    #[serde(rename(serialize = "destinationPort", deserialize = "destinationPort"))]
+    #[serde(rename(serialize = "destinationPort", deserialize = "destinationPort"), default, deserialize_with = "lenient::string")]
     pub destination_port: String,
+    #[serde(default)]
     pub host: String,
-    #[serde(rename(serialize = "dnsMode", deserialize = "dnsMode"))]
+    #[serde(rename(serialize = "dnsMode", deserialize = "dnsMode"), default)]
     pub dns_mode: String,
-    #[serde(rename(serialize = "processPath", deserialize = "processPath"))]
+    #[serde(rename(serialize = "processPath", deserialize = "processPath"), default)]
     pub process_path: String,
+
+    #[serde(rename(serialize = "inboundIP", deserialize = "inboundIP"), default, skip_serializing_if = "Option::is_none")]
+    pub inbound_ip: Option<String>,
+    #[serde(rename(serialize = "inboundPort", deserialize = "inboundPort"), default, deserialize_with = "lenient::number", skip_serializing_if = "Option::is_none")]
+    pub inbound_port: Option<u16>,
+    #[serde(rename(serialize = "inboundName", deserialize = "inboundName"), default, skip_serializing_if = "Option::is_none")]
+    pub inbound_name: Option<String>,
+    #[serde(rename(serialize = "inboundUser", deserialize = "inboundUser"), default, skip_serializing_if = "Option::is_none")]
+    pub inbound_user: Option<String>,
+    #[serde(default, deserialize_with = "lenient::number", skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    /// Process name, without the path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process: Option<String>,
+    #[serde(rename(serialize = "sniffHost", deserialize = "sniffHost"), default, skip_serializing_if = "Option::is_none")]
+    pub sniff_host: Option<String>,
+    #[serde(rename(serialize = "specialProxy", deserialize = "specialProxy"), default, skip_serializing_if = "Option::is_none")]
+    pub special_proxy: Option<String>,
+    #[serde(rename(serialize = "remoteDestination", deserialize = "remoteDestination"), default, skip_serializing_if = "Option::is_none")]
+    pub remote_destination: Option<String>,
+    #[serde(default, deserialize_with = "lenient::number", skip_serializing_if = "Option::is_none")]
+    pub dscp: Option<u8>,
+}
+
+fn socket_addr(ip: Option<IpAddr>, port: Option<u16>) -> Option<SocketAddr> {
+    Some(SocketAddr::new(ip?, port?))
+}
+
+impl Metadata {
+    pub fn src_ip(&self) -> Option<IpAddr> {
+        self.source_ip.parse().ok()
+    }
+
+    pub fn dst_ip(&self) -> Option<IpAddr> {
+        self.destination_ip.parse().ok()
+    }
+
+    pub fn src_port(&self) -> Option<u16> {
+        self.source_port.parse().ok()
+    }
+
+    pub fn dst_port(&self) -> Option<u16> {
+        self.destination_port.parse().ok()
+    }
+
+    pub fn src_addr(&self) -> Option<SocketAddr> {
+        socket_addr(self.src_ip(), self.src_port())
+    }
+
+    /// The destination IP and port; `None` for connections to a host the
+    /// core has not resolved.
+    pub fn dst_addr(&self) -> Option<SocketAddr> {
+        socket_addr(self.dst_ip(), self.dst_port())
+    }
+
+    /// The address of the inbound the connection arrived on (mihomo only).
+    pub fn inbound_addr(&self) -> Option<SocketAddr> {
+        socket_addr(self.inbound_ip.as_deref()?.parse().ok(), self.inbound_port)
+    }
+
+    /// The process name, from `process` or the last part of `processPath`.
+    pub fn process_name(&self) -> &str {
+        match self.process.as_deref() {
+            Some(process) if !process.is_empty() => process,
+            _ => self.process_path.rsplit(['/', '\\']).next().unwrap_or(&self.process_path),
+        }
+    }
+
+    /// The host, or the destination IP when there is none.
+    pub fn destination(&self) -> &str {
+        match self.host.as_str() {
+            "" => &self.destination_ip,
+            host => host,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ConnectionVec {
-    #[serde(rename(serialize = "downloadTotoal", deserialize = "downloadTotal"))]
+    #[serde(rename(serialize = "downloadTotal", deserialize = "downloadTotal"))]
     pub download_total: usize,
     #[serde(rename(serialize = "uploadTotal", deserialize = "uploadTotal"))]
     pub upload_total: usize,
     pub connections: Vec<Connection>,
 }
 
+/// Bytes per second in each direction.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rate {
+    pub upload: f64,
+    pub download: f64,
+}
+
+impl Rate {
+    pub fn new(upload: u64, download: u64, elapsed: Duration) -> Self {
+        let secs = elapsed.as_secs_f64();
+        if secs <= 0.0 {
+            return Self::default();
+        }
+        Self {
+            upload: upload as f64 / secs,
+            download: download as f64 / secs,
+        }
+    }
+
+    pub fn total(&self) -> f64 {
+        self.upload + self.download
+    }
+}
+
+impl std::ops::Add for Rate {
+    type Output = Rate;
+
+    fn add(self, other: Rate) -> Rate {
+        Rate {
+            upload: self.upload + other.upload,
+            download: self.download + other.download,
+        }
+    }
+}
+
+impl Connection {
+    /// Time since the connection started.
+    pub fn age(&self) -> Option<Duration> {
+        self.age_at(SystemTime::now())
+    }
+
+    /// Time between the start of the connection and `now`.
+    pub fn age_at(&self, now: SystemTime) -> Option<Duration> {
+        now.duration_since(self.start?).ok()
+    }
+
+    /// Average throughput over the whole life of the connection.
+    pub fn avg_rate(&self) -> Option<Rate> {
+        self.avg_rate_at(SystemTime::now())
+    }
+
+    pub fn avg_rate_at(&self, now: SystemTime) -> Option<Rate> {
+        let age = self.age_at(now)?;
+        Some(Rate::new(self.upload as u64, self.download as u64, age))
+    }
+}
+
+impl ConnectionVec {
+    /// The connections routed through `group` but not through `member`,
+    /// i.e. those still using an earlier selection of `group`.
+    pub fn stale(&self, group: &str, member: &str) -> Vec<&Connection> {
+        self.connections
+            .iter()
+            .filter(|c| c.chains.iter().any(|n| n == group) && !c.chains.iter().any(|n| n == member))
+            .collect()
+    }
+}
+
 impl TryFrom<String> for ConnectionVec {
     type Error = serde_json::Error;
 
@@ -155,7 +475,7 @@ impl ClashRequest for ClashCloseConnections {
     }
 
     fn get_path(&self) -> String {
-        "conntections".to_owned()
+        "connections".to_owned()
     }
 
     fn get_query_parameter(&self) -> String {
@@ -167,11 +487,12 @@ impl ClashRequest for ClashCloseConnections {
     }
 
     async fn send(self) -> Result<Self::Response, Box<dyn std::error::Error>> {
-        use crate::get_with_status_code_request;
+        use crate::delete_request;
 
-        let _code = get_with_status_code_request(self).await?;
-
-        // HACK: The official doc says nothing about the return of this request
+        let code = delete_request(self).await?;
+        if !code.is_success() {
+            return Err(Box::new(ConnectionError::CloseFailed(code.as_u16())));
+        }
         Ok(())
     }
 }
@@ -209,18 +530,24 @@ impl ClashRequest for ClashCloseID {
     }
 
     async fn send(self) -> Result<Self::Response, Box<dyn std::error::Error>> {
-        use crate::get_with_status_code_request;
-
-        let _code = get_with_status_code_request(self).await?;
+        self.send_with(&reqwest::Client::new()).await
+    }
+}
 
-        // HACK: The official doc says nothing about the return of this request
+impl ClashCloseID {
+    async fn send_with(self, client: &reqwest::Client) -> Result<(), Box<dyn std::error::Error>> {
+        let code = crate::delete_request_with(client, self).await?;
+        if !code.is_success() {
+            return Err(Box::new(ConnectionError::CloseFailed(code.as_u16())));
+        }
         Ok(())
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::ClashRequest;
+    use super::*;
+    use crate::mock::{connection, snapshot, MockController};
 
     #[tokio::test]
     async fn test_get_connection_info() {
@@ -235,5 +562,106 @@ mod test {
 
         println!("{:?}", connections_info);
     }
-}
 
+    #[test]
+    fn test_parse_rfc3339() {
+        let t = parse_rfc3339("2023-08-17T12:34:56.5+08:00").unwrap();
+        assert_eq!(t.duration_since(UNIX_EPOCH).unwrap(), Duration::new(1692246896, 500_000_000));
+
+        let t = parse_rfc3339("1970-01-02T00:00:00.000000001Z").unwrap();
+        assert_eq!(t.duration_since(UNIX_EPOCH).unwrap(), Duration::new(86400, 1));
+
+        assert!(parse_rfc3339("2023-13-01T00:00:00Z").is_none());
+        assert!(parse_rfc3339("2023-01-01T00:00:00").is_none());
+    }
+
+    #[test]
+    fn test_start_and_age() {
+        for s in ["2023-08-17T04:34:56.5Z", "1970-01-01T00:00:00Z", "2000-02-29T23:59:59.123456789Z"] {
+            assert_eq!(format_rfc3339(parse_rfc3339(s).unwrap()), s);
+        }
+
+        let mut connection: Connection = serde_json::from_str(
+            r#"{"id":"a","chains":[],"rule":"Match","rulePayload":"","upload":1000,"download":3000,
+            "start":"2023-08-17T12:34:56.123456789+08:00","metadata":{"network":"tcp","type":"HTTP",
+            "sourceIP":"","destinationIP":"","sourcePort":"","destinationPort":"","host":"","dnsMode":"","processPath":""}}"#
+        ).unwrap();
+        let start = connection.start.unwrap();
+        let now = start + Duration::from_secs(10);
+        assert_eq!(connection.age_at(now), Some(Duration::from_secs(10)));
+        assert_eq!(connection.avg_rate_at(now), Some(Rate { upload: 100.0, download: 300.0 }));
+        assert!(serde_json::to_string(&connection).unwrap().contains(r#""start":"2023-08-17T04:34:56.123456789Z""#));
+
+        connection.start = None;
+        assert!(connection.age().is_none());
+    }
+
+    #[test]
+    fn test_json_shape() {
+        let mut connections = snapshot(&[connection("a", "example.com", "1.1.1.1", &["node", "Proxy"], 1, 2)]);
+        connections.download_total = 20;
+        connections.upload_total = 10;
+        let json = serde_json::to_value(&connections).unwrap();
+
+        assert_eq!((&json["downloadTotal"], &json["uploadTotal"]), (&20.into(), &10.into()));
+        let c = &json["connections"][0];
+        assert_eq!((&c["id"], &c["rulePayload"], &c["upload"], &c["download"]), (&"a".into(), &"".into(), &1.into(), &2.into()));
+        assert_eq!(c["chains"], serde_json::json!(["node", "Proxy"]));
+        assert_eq!(c["start"], "2024-01-01T00:00:00Z");
+        assert_eq!((&c["metadata"]["sourceIP"], &c["metadata"]["destinationPort"]), (&"192.168.1.2".into(), &"443".into()));
+        assert_eq!(ConnectionVec::try_from(json.to_string()).unwrap().connections[0].id, "a");
+    }
+
+    #[test]
+    fn test_metadata_shapes() {
+        let legacy: Metadata = serde_json::from_str(
+            r#"{"network":"tcp","type":"HTTP","sourceIP":"192.168.1.2","destinationIP":"","sourcePort":"5000",
+            "destinationPort":"443","host":"example.com","dnsMode":"normal","processPath":"/usr/bin/curl"}"#
+        ).unwrap();
+        assert_eq!(legacy.src_addr(), Some("192.168.1.2:5000".parse().unwrap()));
+        assert_eq!(legacy.dst_addr(), None);
+        assert_eq!((legacy.process_name(), legacy.destination()), ("curl", "example.com"));
+
+        let mihomo: Metadata = serde_json::from_str(
+            r#"{"network":"udp","type":"Tun","sourceIP":"fd00::2","destinationIP":"8.8.8.8","sourcePort":"5353",
+            "destinationPort":53,"inboundIP":"127.0.0.1","inboundPort":"7890","inboundName":"DEFAULT-MIXED",
+            "inboundUser":"","host":"","dnsMode":"normal","uid":1000,"process":"chrome","sniffHost":"dns.google",
+            "specialProxy":"","specialRules":"","remoteDestination":"8.8.8.8","dscp":0}"#
+        ).unwrap();
+        assert_eq!(mihomo.dst_addr(), Some("8.8.8.8:53".parse().unwrap()));
+        assert_eq!(mihomo.src_ip(), Some("fd00::2".parse().unwrap()));
+        assert_eq!(mihomo.inbound_addr(), Some("127.0.0.1:7890".parse().unwrap()));
+        assert_eq!((mihomo.uid, mihomo.dscp), (Some(1000), Some(0)));
+        assert_eq!((mihomo.process_name(), mihomo.process_path.as_str()), ("chrome", ""));
+        assert_eq!(mihomo.sniff_host.as_deref(), Some("dns.google"));
+        assert_eq!(mihomo.destination(), "8.8.8.8");
+    }
+
+    #[tokio::test]
+    async fn test_close_ids() {
+        let mock = MockController::new();
+        mock.route("DELETE", "connections/a", 204, "")
+            .route("DELETE", "connections/b", 204, "")
+            .route("DELETE", "connections", 204, "");
+        let client = mock.start().await;
+
+        let results = client.clone().connections().close_ids(["a", "b", "c"]).await;
+        assert!(results[0].1.is_ok() && results[1].1.is_ok());
+        assert_eq!(results[2].0, "c");
+        assert!(results[2].1.is_err());
+
+        // More IDs than are closed at once still come back in order.
+        let ids: Vec<String> = (0..CLOSE_CONCURRENCY * 3).map(|i| i.to_string()).collect();
+        for id in ids.iter() {
+            mock.route("DELETE", &format!("connections/{}", id), 204, "");
+        }
+        let results = client.clone().connections().close_ids(&ids).await;
+        assert!(results.iter().map(|(id, _)| id).eq(ids.iter()));
+        assert!(results.iter().all(|(_, result)| result.is_ok()));
+
+        client.connections().close().send().await.unwrap();
+        let requests = mock.requests();
+        assert!(requests.iter().all(|r| r.method == "DELETE"));
+        assert!(requests.iter().any(|r| r.path == "connections"));
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::{
+    config::Config,
+    profile::Profile,
+    proxy::ProxyList,
+    rule::{Rule, RuleList},
+};
+
+/// General settings compared between the running core and a profile.
+const SETTINGS: [&str; 10] = [
+    "port",
+    "socks-port",
+    "redir-port",
+    "tproxy-port",
+    "mixed-port",
+    "allow-lan",
+    "bind-address",
+    "mode",
+    "log-level",
+    "ipv6",
+];
+
+/// How the running core differs from a profile.
+///
+/// "Added" always means present in the running core but not in the
+/// profile, "removed" means declared in the profile but missing from the
+/// running core.
+#[derive(Serialize, Debug, Default)]
+pub struct ConfigDiff {
+    pub general: Vec<SettingChange>,
+    pub rules: RuleDiff,
+    pub groups: NameDiff,
+    pub proxies: NameDiff,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SettingChange {
+    pub key: String,
+    pub profile: Option<String>,
+    pub running: Option<String>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct RuleDiff {
+    /// Rules only the running core has, with their running index.
+    pub added: Vec<IndexedRule>,
+    /// Rules only the profile has, with their profile index.
+    pub removed: Vec<IndexedRule>,
+    /// Rules both have but in a different relative order, with their
+    /// running index.
+    pub reordered: Vec<IndexedRule>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct IndexedRule {
+    pub index: usize,
+    #[serde(flatten)]
+    pub rule: Rule,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct NameDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl ConfigDiff {
+    pub fn new(profile: &Profile, config: &Config, rules: &RuleList, proxies: &ProxyList) -> Self {
+        Self {
+            general: diff_settings(profile, config),
+            rules: diff_rules(&profile.rule_list(), rules),
+            groups: diff_names(profile.group_names(), proxies.group_names()),
+            proxies: diff_names(profile.proxy_names(), proxies.proxy_names()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.general.is_empty()
+            && self.rules.is_empty()
+            && self.groups.is_empty()
+            && self.proxies.is_empty()
+    }
+}
+
+impl RuleDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.reordered.is_empty()
+    }
+}
+
+impl NameDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+// Unset ports are reported as `0` and unset flags as `false` by the
+// controller, so both count as absent. Strings are compared case
+// insensitively because profiles write `mode: Rule`.
+fn normalize_json(value: &serde_json::Value) -> Option<String> {
+    use serde_json::Value;
+    match value {
+        Value::Null | Value::Bool(false) => None,
+        Value::Number(n) if n.as_u64() == Some(0) => None,
+        Value::String(s) => Some(s.to_lowercase()),
+        v => Some(v.to_string()),
+    }
+}
+
+fn normalize_yaml(value: &serde_yaml::Value) -> Option<String> {
+    use serde_yaml::Value;
+    match value {
+        Value::Null | Value::Bool(false) => None,
+        Value::Bool(true) => Some("true".to_owned()),
+        Value::Number(n) if n.as_u64() == Some(0) => None,
+        Value::Number(n) => Some(n.to_string()),
+        Value::String(s) => Some(s.to_lowercase()),
+        v => serde_yaml::to_string(v).ok().map(|s| s.trim().to_owned()),
+    }
+}
+
+fn diff_settings(profile: &Profile, config: &Config) -> Vec<SettingChange> {
+    let running = serde_json::to_value(config).unwrap_or_default();
+
+    SETTINGS
+        .iter()
+        .filter_map(|key| {
+            let profile = profile.setting(key).and_then(normalize_yaml);
+            let running = running.get(key).and_then(normalize_json);
+            (profile != running).then(|| SettingChange {
+                key: key.to_string(),
+                profile,
+                running,
+            })
+        })
+        .collect()
+}
+
+type RuleKey = (String, String, String, usize);
+
+// Key each rule by its normalized form plus its occurrence count, so that
+// duplicated rules are matched one to one.
+fn rule_keys(rules: &RuleList) -> Vec<RuleKey> {
+    let mut seen: HashMap<(String, String, String), usize> = HashMap::new();
+    rules
+        .iter()
+        .map(|rule| {
+            let key = (rule.normalized_type(), rule.payload.to_lowercase(), rule.proxy.clone());
+            let n = seen.entry(key.clone()).or_insert(0);
+            *n += 1;
+            (key.0, key.1, key.2, *n)
+        })
+        .collect()
+}
+
+// Indices (into `seq`) of one longest strictly increasing subsequence.
+fn longest_increasing(seq: &[usize]) -> HashSet<usize> {
+    let mut tails: Vec<usize> = Vec::new();
+    let mut prev: Vec<Option<usize>> = vec![None; seq.len()];
+
+    for (i, value) in seq.iter().enumerate() {
+        let pos = tails.partition_point(|&t| seq[t] < *value);
+        if pos > 0 {
+            prev[i] = Some(tails[pos - 1]);
+        }
+        if pos == tails.len() {
+            tails.push(i);
+        } else {
+            tails[pos] = i;
+        }
+    }
+
+    let mut result = HashSet::new();
+    let mut cur = tails.last().copied();
+    while let Some(i) = cur {
+        result.insert(i);
+        cur = prev[i];
+    }
+    result
+}
+
+fn diff_rules(profile: &RuleList, running: &RuleList) -> RuleDiff {
+    let profile_keys = rule_keys(profile);
+    let running_keys = rule_keys(running);
+    let profile_index: HashMap<&RuleKey, usize> =
+        profile_keys.iter().enumerate().map(|(i, k)| (k, i)).collect();
+    let running_set: HashSet<&RuleKey> = running_keys.iter().collect();
+
+    let mut diff = RuleDiff::default();
+    let mut common = Vec::new();
+    for ((index, key), rule) in running_keys.iter().enumerate().zip(running.iter()) {
+        match profile_index.get(key) {
+            Some(&i) => common.push((index, i, rule)),
+            None => diff.added.push(IndexedRule { index, rule: rule.clone() }),
+        }
+    }
+
+    for ((index, key), rule) in profile_keys.iter().enumerate().zip(profile.iter()) {
+        if !running_set.contains(key) {
+            diff.removed.push(IndexedRule { index, rule: rule.clone() });
+        }
+    }
+
+    let order: Vec<usize> = common.iter().map(|(_, i, _)| *i).collect();
+    let in_order = longest_increasing(&order);
+    diff.reordered = common
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !in_order.contains(i))
+        .map(|(_, (index, _, rule))| IndexedRule { index: *index, rule: (*rule).clone() })
+        .collect();
+
+    diff
+}
+
+fn diff_names<'a>(
+    profile: impl Iterator<Item = &'a str>,
+    running: impl Iterator<Item = &'a str>,
+) -> NameDiff {
+    let profile: HashSet<&str> = profile.collect();
+    let running: HashSet<&str> = running.collect();
+
+    let mut added: Vec<String> = running.difference(&profile).map(|s| s.to_string()).collect();
+    let mut removed: Vec<String> = profile.difference(&running).map(|s| s.to_string()).collect();
+    added.sort();
+    removed.sort();
+
+    NameDiff { added, removed }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rules(lines: &[&str]) -> RuleList {
+        lines.iter().filter_map(|l| Rule::from_line(l)).collect::<Vec<_>>().into()
+    }
+
+    #[test]
+    fn test_diff_rules() {
+        let profile = rules(&[
+            "DOMAIN,a.com,DIRECT",
+            "DOMAIN,b.com,DIRECT",
+            "DOMAIN,c.com,DIRECT",
+            "DOMAIN,d.com,DIRECT",
+            "MATCH,Proxy",
+        ]);
+        let running = rules(&[
+            "Domain,c.com,DIRECT",
+            "Domain,a.com,DIRECT",
+            "Domain,b.com,DIRECT",
+            "Domain,e.com,DIRECT",
+            "Match,,Proxy",
+        ]);
+
+        let diff = diff_rules(&profile, &running);
+        let payloads = |rules: &[IndexedRule]| rules.iter().map(|r| r.rule.payload.clone()).collect::<Vec<_>>();
+
+        assert_eq!(payloads(&diff.added), vec!["e.com"]);
+        assert_eq!(payloads(&diff.removed), vec!["d.com"]);
+        assert_eq!(payloads(&diff.reordered), vec!["c.com"]);
+        assert_eq!(diff.reordered[0].index, 0);
+    }
+
+    #[test]
+    fn test_diff_settings() {
+        let profile = Profile::try_from("mixed-port: 7890\nmode: Rule\nallow-lan: false\n".to_owned()).unwrap();
+        let config: Config = serde_json::from_str(
+            r#"{"port":0,"mixed-port":9990,"mode":"rule","allow-lan":false,"ipv6":true}"#,
+        ).unwrap();
+
+        let diff = diff_settings(&profile, &config);
+        let keys: Vec<&str> = diff.iter().map(|c| c.key.as_str()).collect();
+
+        assert_eq!(keys, vec!["mixed-port", "ipv6"]);
+        assert_eq!(diff[0].profile.as_deref(), Some("7890"));
+        assert_eq!(diff[0].running.as_deref(), Some("9990"));
+    }
+}
@@ -0,0 +1,305 @@
+use async_trait::async_trait;
+use clap::ValueEnum;
+use serde::{Serialize, Deserialize};
+use urlencoding::encode;
+
+use crate::{capability::Feature, clash_core::CoreError, ClashRequest, ClashRequestBuilder};
+
+pub struct ClashDns {
+    ip: String,
+    port: u16,
+    secret: Option<String>,
+}
+
+impl From<ClashRequestBuilder> for ClashDns {
+    fn from(r: ClashRequestBuilder) -> Self {
+        Self {
+            ip: r.ip.unwrap(),
+            port: r.port.unwrap(),
+            secret: r.secret,
+        }
+    }
+}
+
+impl ClashDns {
+    pub fn flush_fakeip(self) -> ClashFlushFakeIp {
+        ClashFlushFakeIp { ip: self.ip, port: self.port, secret: self.secret }
+    }
+
+    pub fn query(self, name: &str, r#type: DnsType) -> ClashDnsQuery {
+        ClashDnsQuery {
+            ip: self.ip,
+            port: self.port,
+            secret: self.secret,
+            name: name.to_owned(),
+            r#type,
+        }
+    }
+}
+
+pub struct ClashFlushFakeIp {
+    ip: String,
+    port: u16,
+    secret: Option<String>,
+}
+
+#[async_trait]
+impl ClashRequest for ClashFlushFakeIp {
+    type Response = ();
+
+    fn get_dest(&self) -> String {
+        self.ip.clone()
+    }
+
+    fn get_port(&self) -> u16 {
+        self.port
+    }
+
+    fn get_secret(&self) -> Option<String> {
+        self.secret.clone()
+    }
+
+    fn get_method(&self) -> String {
+        "POST".to_owned()
+    }
+
+    fn get_path(&self) -> String {
+        "cache/fakeip/flush".to_owned()
+    }
+
+    fn get_query_parameter(&self) -> String {
+        "".to_owned()
+    }
+
+    fn get_body(&self) -> String {
+        "".to_owned()
+    }
+
+    fn feature(&self) -> Option<Feature> {
+        Some(Feature::FakeIpFlush)
+    }
+
+    async fn send(self) -> Result<Self::Response, Box<dyn std::error::Error>> {
+        use crate::post_request;
+        CoreError::check(post_request(self).await?)
+    }
+}
+
+/// DNS record types, as accepted by `/dns/query`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "UPPERCASE")]
+#[value(rename_all = "UPPERCASE")]
+pub enum DnsType {
+    A,
+    NS,
+    CNAME,
+    SOA,
+    PTR,
+    MX,
+    TXT,
+    AAAA,
+    SRV,
+    SVCB,
+    HTTPS,
+}
+
+impl DnsType {
+    pub fn code(&self) -> u16 {
+        use DnsType::*;
+        match self {
+            A => 1,
+            NS => 2,
+            CNAME => 5,
+            SOA => 6,
+            PTR => 12,
+            MX => 15,
+            TXT => 16,
+            AAAA => 28,
+            SRV => 33,
+            SVCB => 64,
+            HTTPS => 65,
+        }
+    }
+
+    pub fn from_code(code: u16) -> Option<Self> {
+        Self::value_variants().iter().copied().find(|t| t.code() == code)
+    }
+
+    pub fn name(&self) -> &'static str {
+        use DnsType::*;
+        match self {
+            A => "A",
+            NS => "NS",
+            CNAME => "CNAME",
+            SOA => "SOA",
+            PTR => "PTR",
+            MX => "MX",
+            TXT => "TXT",
+            AAAA => "AAAA",
+            SRV => "SRV",
+            SVCB => "SVCB",
+            HTTPS => "HTTPS",
+        }
+    }
+}
+
+pub struct ClashDnsQuery {
+    ip: String,
+    port: u16,
+    secret: Option<String>,
+
+    name: String,
+    r#type: DnsType,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DnsQuestion {
+    #[serde(rename(serialize = "Name", deserialize = "Name"))]
+    pub name: String,
+    #[serde(rename(serialize = "Qtype", deserialize = "Qtype"))]
+    pub qtype: u16,
+    #[serde(rename(serialize = "Qclass", deserialize = "Qclass"))]
+    pub qclass: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DnsRecord {
+    pub name: String,
+    pub r#type: u16,
+    #[serde(rename(serialize = "TTL", deserialize = "TTL"))]
+    pub ttl: u32,
+    pub data: String,
+}
+
+impl DnsRecord {
+    pub fn record_type(&self) -> Option<DnsType> {
+        DnsType::from_code(self.r#type)
+    }
+}
+
+/// The answer set of `/dns/query`, in the JSON shape of DNS-over-HTTPS.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DnsAnswer {
+    #[serde(rename(serialize = "Status", deserialize = "Status"))]
+    pub status: u16,
+    #[serde(rename(serialize = "Question", deserialize = "Question"), default)]
+    pub question: Vec<DnsQuestion>,
+    #[serde(rename(serialize = "Answer", deserialize = "Answer"), default)]
+    pub answer: Vec<DnsRecord>,
+    #[serde(rename(serialize = "Authority", deserialize = "Authority"), default)]
+    pub authority: Vec<DnsRecord>,
+    #[serde(rename(serialize = "Additional", deserialize = "Additional"), default)]
+    pub additional: Vec<DnsRecord>,
+}
+
+impl DnsAnswer {
+    /// The response code, e.g. `NOERROR` or `NXDOMAIN`.
+    pub fn rcode(&self) -> String {
+        match self.status {
+            0 => "NOERROR".to_owned(),
+            1 => "FORMERR".to_owned(),
+            2 => "SERVFAIL".to_owned(),
+            3 => "NXDOMAIN".to_owned(),
+            4 => "NOTIMP".to_owned(),
+            5 => "REFUSED".to_owned(),
+            code => format!("RCODE{}", code),
+        }
+    }
+
+    /// Addresses from the `A` and `AAAA` records of the answer section.
+    pub fn addresses(&self) -> Vec<std::net::IpAddr> {
+        self.answer
+            .iter()
+            .filter(|r| matches!(r.record_type(), Some(DnsType::A | DnsType::AAAA)))
+            .filter_map(|r| r.data.parse().ok())
+            .collect()
+    }
+}
+
+impl TryFrom<String> for DnsAnswer {
+    type Error = serde_json::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        serde_json::from_str(&value)
+    }
+}
+
+#[async_trait]
+impl ClashRequest for ClashDnsQuery {
+    type Response = DnsAnswer;
+
+    fn get_dest(&self) -> String {
+        self.ip.clone()
+    }
+
+    fn get_port(&self) -> u16 {
+        self.port
+    }
+
+    fn get_secret(&self) -> Option<String> {
+        self.secret.clone()
+    }
+
+    fn get_method(&self) -> String {
+        "GET".to_owned()
+    }
+
+    fn get_path(&self) -> String {
+        "dns/query".to_owned()
+    }
+
+    fn get_query_parameter(&self) -> String {
+        format!("name={}&type={}", encode(&self.name), self.r#type.name())
+    }
+
+    fn get_body(&self) -> String {
+        "".to_owned()
+    }
+
+    fn feature(&self) -> Option<Feature> {
+        Some(Feature::DnsQuery)
+    }
+
+    async fn send(self) -> Result<Self::Response, Box<dyn std::error::Error>> {
+        use crate::get_with_status_code_request;
+
+        let (code, text) = get_with_status_code_request(self).await?;
+        CoreError::check(code)?;
+        Ok(text.try_into()?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockController;
+
+    const ANSWER: &str = r#"{"Status":0,"TC":false,"RD":true,"RA":true,"AD":false,"CD":false,
+        "Question":[{"Name":"example.com.","Qtype":28,"Qclass":1}],
+        "Answer":[{"name":"example.com.","type":5,"TTL":60,"data":"edge.example.net."},
+                  {"name":"edge.example.net.","type":28,"TTL":60,"data":"2606:2800:220:1::1"}]}"#;
+
+    #[tokio::test]
+    async fn test_dns_query() {
+        let mock = MockController::new();
+        mock.route("GET", "dns/query", 200, ANSWER);
+        let client = mock.start().await;
+
+        let answer = client.dns().query("example.com", DnsType::AAAA).send().await.unwrap();
+
+        assert_eq!(mock.requests()[0].query, "name=example.com&type=AAAA");
+        assert_eq!(answer.rcode(), "NOERROR");
+        assert_eq!(answer.answer[0].record_type(), Some(DnsType::CNAME));
+        assert_eq!(answer.addresses(), vec!["2606:2800:220:1::1".parse::<std::net::IpAddr>().unwrap()]);
+    }
+
+    #[tokio::test]
+    async fn test_flush_fakeip() {
+        let mock = MockController::new();
+        mock.route("POST", "cache/fakeip/flush", 204, "");
+        let client = mock.start().await;
+
+        client.dns().flush_fakeip().send().await.unwrap();
+        assert_eq!(mock.requests()[0].method, "POST");
+    }
+}
@@ -0,0 +1,25 @@
+//! Helpers for the files kept in the data directory.
+
+use std::path::Path;
+
+/// Whether `name` can name a file in the data directory: it is not empty,
+/// holds no path separators and does not start with a dot.
+pub fn is_valid_name(name: &str) -> bool {
+    !(name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']))
+}
+
+/// Write `contents` to `path` through a temporary file and a rename, so
+/// that readers such as the core never see a half-written file.
+pub fn write_atomic(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let path = path.as_ref();
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// `path` as a string for the controller API, which takes UTF-8 paths.
+pub fn path_str(path: &Path) -> Result<&str, Box<dyn std::error::Error>> {
+    path.to_str().ok_or_else(|| format!("Path is not valid UTF-8: {}", path.display()).into())
+}
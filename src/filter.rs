@@ -0,0 +1,508 @@
+//! A small filter language over connections, e.g.
+//! `host ~ "*.googlevideo.com" and upload > 10MB or age > 1h`.
+//!
+//! Comparisons are `FIELD OP VALUE`, combined with `and`, `or`, `not` and
+//! parentheses. Operators are `==`, `!=`, `~` (glob), `!~`, `contains`,
+//! `>`, `>=`, `<` and `<=`. Values are bare words or quoted strings.
+//!
+//! Fields: `id`, `host`, `process`, `chains`, `rule`, `payload`,
+//! `network`, `type`, `src`, `dst`, `src_port`, `dst_port`, `upload`,
+//! `download`, `total` and `age`. Byte sizes take `K`, `M`, `G` and `T`
+//! suffixes (powers of 1024, optionally followed by `B` or `iB`), ages
+//! take `s`, `m`, `h` and `d`. `src` and `dst` compare against IPs or
+//! CIDRs.
+
+use std::{
+    net::IpAddr,
+    time::{Duration, SystemTime},
+};
+
+use ipnet::IpNet;
+use regex::Regex;
+
+use crate::{connection::Connection, rule::Rule};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterError {
+    UnexpectedEnd,
+    UnexpectedToken(String),
+    UnknownField(String),
+    /// The operator does not apply to the field.
+    InvalidOperator { field: String, op: String },
+    InvalidValue { field: String, value: String },
+}
+
+impl std::fmt::Display for FilterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use FilterError::*;
+        match self {
+            UnexpectedEnd => write!(f, "Unexpected end of filter"),
+            UnexpectedToken(t) => write!(f, "Unexpected token: {}", t),
+            UnknownField(field) => write!(f, "Unknown field: {}", field),
+            InvalidOperator { field, op } => write!(f, "Operator {} does not apply to {}", op, field),
+            InvalidValue { field, value } => write!(f, "Invalid value for {}: {}", field, value),
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Id,
+    Host,
+    Process,
+    Chains,
+    Rule,
+    Payload,
+    Network,
+    Type,
+    Src,
+    Dst,
+    SrcPort,
+    DstPort,
+    Upload,
+    Download,
+    Total,
+    Age,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        use Field::*;
+        let field = match name.to_lowercase().as_str() {
+            "id" => Id,
+            "host" => Host,
+            "process" => Process,
+            "chains" | "chain" => Chains,
+            "rule" => Rule,
+            "payload" | "rule_payload" => Payload,
+            "network" => Network,
+            "type" => Type,
+            "src" | "source" => Src,
+            "dst" | "destination" => Dst,
+            "src_port" | "sport" => SrcPort,
+            "dst_port" | "dport" => DstPort,
+            "upload" | "up" => Upload,
+            "download" | "down" => Download,
+            "total" => Total,
+            "age" => Age,
+            _ => return None,
+        };
+        Some(field)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Glob,
+    NotGlob,
+    Contains,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Op {
+    fn parse(op: &str) -> Option<Self> {
+        use Op::*;
+        let op = match op.to_lowercase().as_str() {
+            "==" | "=" => Eq,
+            "!=" => Ne,
+            "~" | "=~" => Glob,
+            "!~" => NotGlob,
+            "contains" => Contains,
+            ">" => Gt,
+            ">=" => Ge,
+            "<" => Lt,
+            "<=" => Le,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    fn compare<T: PartialOrd>(&self, left: T, right: T) -> bool {
+        match self {
+            Op::Eq => left == right,
+            Op::Ne => left != right,
+            Op::Gt => left > right,
+            Op::Ge => left >= right,
+            Op::Lt => left < right,
+            Op::Le => left <= right,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Text(String),
+    Glob(Regex),
+    Net(IpNet),
+    Number(u64),
+}
+
+#[derive(Debug, Clone)]
+struct Comparison {
+    field: Field,
+    op: Op,
+    value: Value,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Compare(Comparison),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+/// A parsed filter expression.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+}
+
+fn tokenize(s: &str) -> Result<Vec<String>, FilterError> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' | ')' => tokens.push(c.to_string()),
+            '"' | '\'' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some(q) if q == c => break,
+                        Some('\\') => text.extend(chars.next()),
+                        Some(ch) => text.push(ch),
+                        None => return Err(FilterError::UnexpectedEnd),
+                    }
+                }
+                // Mark quoted tokens so that `"and"` stays a value.
+                tokens.push(format!("\"{}", text));
+            }
+            '=' | '!' | '~' | '<' | '>' | '&' | '|' => {
+                let mut op = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if matches!(next, '=' | '~' | '&' | '|') {
+                        op.push(next);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(op);
+            }
+            _ => {
+                let mut word = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || matches!(next, '(' | ')' | '=' | '!' | '~' | '<' | '>' | '&' | '|' | '"' | '\'') {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                tokens.push(word);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parse a byte size such as `10MB`, `1.5G` or `512`.
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let unit = unit.to_uppercase();
+    let unit = unit.trim_end_matches("IB").trim_end_matches('B');
+    let scale: u64 = match unit {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return None,
+    };
+    Some((number * scale as f64) as u64)
+}
+
+/// Parse a duration such as `90s`, `5m`, `1h` or `2d`. Bare numbers are
+/// seconds.
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let split = s.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let scale = match unit.to_lowercase().as_str() {
+        "" | "s" => 1.0,
+        "m" | "min" => 60.0,
+        "h" => 3600.0,
+        "d" => 86400.0,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(number * scale).ok()
+}
+
+fn glob(pattern: &str) -> Regex {
+    let mut regex = String::from("(?i)^");
+    for c in pattern.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).unwrap()
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next(&mut self) -> Result<String, FilterError> {
+        let token = self.tokens.get(self.pos).cloned().ok_or(FilterError::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn keyword(&self, words: &[&str]) -> bool {
+        self.peek().is_some_and(|t| words.iter().any(|w| t.eq_ignore_ascii_case(w)))
+    }
+
+    fn or(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.and()?;
+        while self.keyword(&["or", "||"]) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, FilterError> {
+        let mut expr = self.not()?;
+        while self.keyword(&["and", "&&"]) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, FilterError> {
+        if self.keyword(&["not", "!"]) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, FilterError> {
+        if self.peek() == Some("(") {
+            self.pos += 1;
+            let expr = self.or()?;
+            return match self.next()?.as_str() {
+                ")" => Ok(expr),
+                t => Err(FilterError::UnexpectedToken(t.to_owned())),
+            };
+        }
+
+        let name = self.next()?;
+        let field = Field::parse(&name).ok_or_else(|| FilterError::UnknownField(name.clone()))?;
+        let op_token = self.next()?;
+        let op = Op::parse(&op_token).ok_or_else(|| FilterError::UnexpectedToken(op_token.clone()))?;
+        let raw = self.next()?;
+        let value = raw.strip_prefix('"').unwrap_or(&raw);
+
+        let invalid_op = || FilterError::InvalidOperator { field: name.clone(), op: op_token.clone() };
+        let invalid_value = || FilterError::InvalidValue { field: name.clone(), value: value.to_owned() };
+
+        let value = match (field, op) {
+            (Field::Upload | Field::Download | Field::Total, Op::Glob | Op::NotGlob | Op::Contains) => return Err(invalid_op()),
+            (Field::Upload | Field::Download | Field::Total, _) => Value::Number(parse_size(value).ok_or_else(invalid_value)?),
+            (Field::Age, Op::Glob | Op::NotGlob | Op::Contains) => return Err(invalid_op()),
+            (Field::Age, _) => Value::Number(parse_duration(value).ok_or_else(invalid_value)?.as_millis() as u64),
+            (Field::SrcPort | Field::DstPort, Op::Glob | Op::NotGlob | Op::Contains) => return Err(invalid_op()),
+            (Field::SrcPort | Field::DstPort, _) => Value::Number(value.parse().map_err(|_| invalid_value())?),
+            (_, Op::Gt | Op::Ge | Op::Lt | Op::Le) => return Err(invalid_op()),
+            (_, Op::Glob | Op::NotGlob) => Value::Glob(glob(value)),
+            (Field::Src | Field::Dst, Op::Eq | Op::Ne) => match value.parse::<IpNet>() {
+                Ok(net) => Value::Net(net),
+                Err(_) => Value::Net(value.parse::<IpAddr>().map_err(|_| invalid_value())?.into()),
+            },
+            _ => Value::Text(value.to_owned()),
+        };
+
+        Ok(Expr::Compare(Comparison { field, op, value }))
+    }
+}
+
+impl Filter {
+    pub fn parse(s: &str) -> Result<Self, FilterError> {
+        let mut parser = Parser { tokens: tokenize(s)?, pos: 0 };
+        let expr = parser.or()?;
+        match parser.peek() {
+            None => Ok(Self { expr }),
+            Some(t) => Err(FilterError::UnexpectedToken(t.to_owned())),
+        }
+    }
+
+    /// Whether `connection` matches, with ages measured at `now`.
+    pub fn matches(&self, connection: &Connection, now: SystemTime) -> bool {
+        Self::eval(&self.expr, connection, now)
+    }
+
+    fn eval(expr: &Expr, connection: &Connection, now: SystemTime) -> bool {
+        match expr {
+            Expr::And(a, b) => Self::eval(a, connection, now) && Self::eval(b, connection, now),
+            Expr::Or(a, b) => Self::eval(a, connection, now) || Self::eval(b, connection, now),
+            Expr::Not(e) => !Self::eval(e, connection, now),
+            Expr::Compare(c) => Self::compare(c, connection, now),
+        }
+    }
+
+    fn compare(c: &Comparison, connection: &Connection, now: SystemTime) -> bool {
+        let metadata = &connection.metadata;
+        let number = |n: u64| match c.value {
+            Value::Number(v) => c.op.compare(n, v),
+            _ => false,
+        };
+        let texts: Vec<&str> = match c.field {
+            Field::Upload => return number(connection.upload as u64),
+            Field::Download => return number(connection.download as u64),
+            Field::Total => return number((connection.upload + connection.download) as u64),
+            Field::Age => {
+                let age = connection.age_at(now);
+                return age.is_some_and(|age| number(age.as_millis() as u64));
+            }
+            Field::SrcPort => return metadata.src_port().is_some_and(|p| number(p.into())),
+            Field::DstPort => return metadata.dst_port().is_some_and(|p| number(p.into())),
+            Field::Src | Field::Dst => {
+                let (ip, text) = match c.field {
+                    Field::Src => (metadata.src_ip(), &metadata.source_ip),
+                    _ => (metadata.dst_ip(), &metadata.destination_ip),
+                };
+                if let Value::Net(net) = &c.value {
+                    let contained = ip.is_some_and(|ip| net.contains(&ip));
+                    return contained == (c.op == Op::Eq);
+                }
+                vec![text.as_str()]
+            }
+            Field::Id => vec![connection.id.as_str()],
+            Field::Host => vec![metadata.host.as_str()],
+            Field::Process => vec![metadata.process_name(), metadata.process_path.as_str()],
+            Field::Chains => connection.chains.iter().map(String::as_str).collect(),
+            Field::Rule => vec![connection.rule.as_str()],
+            Field::Payload => vec![connection.rule_payload.as_str()],
+            Field::Network => vec![metadata.network.as_str()],
+            Field::Type => vec![metadata.r#type.as_str()],
+        };
+
+        let any = |f: &dyn Fn(&str) -> bool| texts.iter().any(|t| f(t));
+        match (&c.value, c.op) {
+            (Value::Glob(re), Op::Glob) => any(&|t| re.is_match(t)),
+            (Value::Glob(re), Op::NotGlob) => !any(&|t| re.is_match(t)),
+            (Value::Text(v), Op::Contains) if c.field == Field::Chains => any(&|t| t == v),
+            (Value::Text(v), Op::Contains) => any(&|t| t.to_lowercase().contains(&v.to_lowercase())),
+            (Value::Text(v), Op::Eq | Op::Ne) => {
+                let eq = |t: &str| match c.field {
+                    // `rule == "MATCH"` matches the controller's `Match`.
+                    Field::Rule => same_rule_type(t, v),
+                    Field::Host | Field::Network | Field::Type => t.eq_ignore_ascii_case(v),
+                    _ => t == v,
+                };
+                any(&eq) == (c.op == Op::Eq)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn same_rule_type(a: &str, b: &str) -> bool {
+    let normalize = |t: &str| Rule { r#type: t.to_owned(), payload: "".to_owned(), proxy: "".to_owned() }.normalized_type();
+    normalize(a) == normalize(b)
+}
+
+impl std::str::FromStr for Filter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+    use crate::mock::ConnectionFixture;
+
+    fn connection() -> Connection {
+        ConnectionFixture {
+            host: "rr1.googlevideo.com",
+            dst: "142.250.1.1",
+            chains: &["node-jp", "Proxy"],
+            upload: 20971520,
+            download: 1024,
+            rule: "DomainSuffix",
+            payload: "googlevideo.com",
+            start: "1970-01-01T00:00:00Z",
+            process: "/usr/bin/steam",
+            ..Default::default()
+        }
+        .parse()
+    }
+
+    fn matches(filter: &str) -> bool {
+        let now = UNIX_EPOCH + Duration::from_secs(7200);
+        Filter::parse(filter).unwrap().matches(&connection(), now)
+    }
+
+    #[test]
+    fn test_fields() {
+        assert!(matches(r#"host ~ "*.googlevideo.com""#));
+        assert!(matches(r#"process == "steam""#));
+        assert!(matches(r#"chains contains "node-jp""#));
+        assert!(matches(r#"rule == "DOMAIN-SUFFIX""#));
+        assert!(!matches(r#"rule == "MATCH""#));
+        assert!(matches("src == 192.168.1.0/24"));
+        assert!(matches("dst != 10.0.0.0/8"));
+        assert!(matches("upload > 10MB"));
+        assert!(!matches("total < 1KiB"));
+        assert!(matches("age > 1h"));
+        assert!(matches("dst_port == 443"));
+    }
+
+    #[test]
+    fn test_combinators() {
+        assert!(matches(r#"host ~ "*.example.com" or (process == steam and not upload < 1M)"#));
+        assert!(!matches(r#"host ~ "*.example.com" || process == curl"#));
+        assert!(matches(r#"!(network == udp) && host !~ "*.example.com""#));
+        assert!(matches("host==rr1.googlevideo.com&&upload>1M"));
+        assert!(matches("process==curl||dst_port==443"));
+        assert!(!matches("host==a.com||(up<1M&&!(dst_port==80))"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Filter::parse("hots == a").unwrap_err(), FilterError::UnknownField("hots".to_owned()));
+        assert!(matches!(Filter::parse("host > a"), Err(FilterError::InvalidOperator { .. })));
+        assert!(matches!(Filter::parse("upload > lots"), Err(FilterError::InvalidValue { .. })));
+        assert!(matches!(Filter::parse("src == 192.168.1"), Err(FilterError::InvalidValue { .. })));
+        assert_eq!(Filter::parse("(host == a").unwrap_err(), FilterError::UnexpectedEnd);
+        assert!(matches!(Filter::parse("host == a b"), Err(FilterError::UnexpectedToken(_))));
+    }
+}
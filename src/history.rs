@@ -0,0 +1,387 @@
+//! A record of past connections, built by diffing `/connections` snapshots
+//! by ID, with one entry per connection lifecycle.
+
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    future::Future,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    connection::{format_rfc3339, Connection, ConnectionVec},
+    jsonl, ClashRequest, ClashRequestBuilder,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionRecord {
+    pub id: String,
+    /// Unix time in seconds of the first snapshot holding the connection.
+    pub first_seen: u64,
+    /// Unix time in seconds of the last snapshot holding the connection.
+    pub last_seen: u64,
+    pub upload: u64,
+    pub download: u64,
+    pub chains: Vec<String>,
+    pub rule: String,
+    pub rule_payload: String,
+    pub network: String,
+    pub host: String,
+    pub source_ip: String,
+    pub source_port: String,
+    pub destination_ip: String,
+    pub destination_port: String,
+    /// Process path, or only its name when the core reports no path.
+    pub process_path: String,
+}
+
+impl ConnectionRecord {
+    pub fn new(connection: &Connection, now: u64) -> Self {
+        let metadata = &connection.metadata;
+        Self {
+            id: connection.id.clone(),
+            first_seen: now,
+            last_seen: now,
+            upload: connection.upload as u64,
+            download: connection.download as u64,
+            chains: connection.chains.clone(),
+            rule: connection.rule.clone(),
+            rule_payload: connection.rule_payload.clone(),
+            network: metadata.network.clone(),
+            host: metadata.host.clone(),
+            source_ip: metadata.source_ip.clone(),
+            source_port: metadata.source_port.clone(),
+            destination_ip: metadata.destination_ip.clone(),
+            destination_port: metadata.destination_port.clone(),
+            // mihomo may only report the process name.
+            process_path: match metadata.process_path.as_str() {
+                "" => metadata.process_name().to_owned(),
+                path => path.to_owned(),
+            },
+        }
+    }
+
+    /// The policy the rule chose, i.e. the outermost entry of the chain.
+    pub fn policy(&self) -> &str {
+        self.chains.last().map(String::as_str).unwrap_or_default()
+    }
+
+    /// The host, or the destination IP when there is none.
+    pub fn destination(&self) -> &str {
+        match self.host.as_str() {
+            "" => &self.destination_ip,
+            host => host,
+        }
+    }
+}
+
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// Follows connections across snapshots.
+#[derive(Default)]
+pub struct ConnectionTracker {
+    open: HashMap<String, ConnectionRecord>,
+}
+
+impl ConnectionTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take a snapshot taken at `now`, returning the records of the
+    /// connections that disappeared since the previous one.
+    pub fn update(&mut self, snapshot: &ConnectionVec, now: u64) -> Vec<ConnectionRecord> {
+        let mut open = HashMap::with_capacity(snapshot.connections.len());
+        for connection in snapshot.connections.iter() {
+            let record = match self.open.remove(&connection.id) {
+                Some(mut record) => {
+                    record.last_seen = now;
+                    record.upload = record.upload.max(connection.upload as u64);
+                    record.download = record.download.max(connection.download as u64);
+                    record
+                }
+                None => ConnectionRecord::new(connection, now),
+            };
+            open.insert(connection.id.clone(), record);
+        }
+
+        let mut closed: Vec<ConnectionRecord> = std::mem::replace(&mut self.open, open).into_values().collect();
+        closed.sort_by(|a, b| a.first_seen.cmp(&b.first_seen).then(a.id.cmp(&b.id)));
+        closed
+    }
+
+    /// The records of the connections still open.
+    pub fn finish(self) -> Vec<ConnectionRecord> {
+        let mut open: Vec<ConnectionRecord> = self.open.into_values().collect();
+        open.sort_by(|a, b| a.first_seen.cmp(&b.first_seen).then(a.id.cmp(&b.id)));
+        open
+    }
+
+    /// Poll `/connections` every `interval` until `duration` passes or
+    /// `stop` completes, writing every finished connection to `writer`, then
+    /// the connections still open. Failed polls go to `on_error` and are
+    /// retried on the next tick.
+    pub async fn record<E>(
+        mut self,
+        client: ClashRequestBuilder,
+        mut writer: RecordWriter,
+        interval: Duration,
+        duration: Option<Duration>,
+        stop: impl Future<Output = ()>,
+        mut on_error: E,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        E: FnMut(&dyn std::error::Error),
+    {
+        let deadline = duration.map(|d| tokio::time::Instant::now() + d);
+        tokio::pin!(stop);
+        let result = loop {
+            let polled = tokio::select! {
+                polled = client.clone().connections().send() => polled,
+                _ = &mut stop => break Ok(()),
+            };
+            match polled {
+                Ok(snapshot) => {
+                    let now = unix_now();
+                    if let Err(e) = writer.write(&self.update(&snapshot, now), now) {
+                        break Err(e);
+                    }
+                }
+                Err(e) => on_error(e.as_ref()),
+            }
+
+            if deadline.is_some_and(|d| tokio::time::Instant::now() + interval > d) {
+                break Ok(());
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = &mut stop => break Ok(()),
+            }
+        };
+        let finished = writer.write(&self.finish(), unix_now());
+        result.and(finished)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordFormat {
+    /// One JSON object per line
+    Jsonl,
+    /// Comma-separated values with a header, and times in RFC 3339
+    Csv,
+}
+
+impl RecordFormat {
+    /// CSV for paths ending in `.csv`, JSON lines otherwise.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        match path.as_ref().extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => RecordFormat::Csv,
+            _ => RecordFormat::Jsonl,
+        }
+    }
+}
+
+const CSV_HEADER: &str = "id,first_seen,last_seen,upload,download,chains,rule,rule_payload,network,host,\
+    source_ip,source_port,destination_ip,destination_port,process_path";
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+fn csv_line(record: &ConnectionRecord) -> String {
+    let time = |secs| format_rfc3339(UNIX_EPOCH + Duration::from_secs(secs));
+    let chains = record.chains.iter().rev().cloned().collect::<Vec<_>>().join(" > ");
+    [
+        record.id.as_str(),
+        &time(record.first_seen),
+        &time(record.last_seen),
+        &record.upload.to_string(),
+        &record.download.to_string(),
+        &chains,
+        &record.rule,
+        &record.rule_payload,
+        &record.network,
+        &record.host,
+        &record.source_ip,
+        &record.source_port,
+        &record.destination_ip,
+        &record.destination_port,
+        &record.process_path,
+    ]
+    .map(csv_field)
+    .join(",")
+}
+
+/// Appends records to a file, moving it aside to `<path>.<time>` once it
+/// grows past a size or has been written to for a period.
+pub struct RecordWriter {
+    path: PathBuf,
+    format: RecordFormat,
+    rotate_size: Option<u64>,
+    rotate_every: Option<Duration>,
+    /// Unix time in seconds of the first write to the current file.
+    opened: Option<u64>,
+}
+
+impl RecordWriter {
+    pub fn new(path: impl Into<PathBuf>, format: RecordFormat) -> Self {
+        Self { path: path.into(), format, rotate_size: None, rotate_every: None, opened: None }
+    }
+
+    pub fn rotate_size(mut self, bytes: u64) -> Self {
+        self.rotate_size = Some(bytes);
+        self
+    }
+
+    pub fn rotate_every(mut self, period: Duration) -> Self {
+        self.rotate_every = Some(period);
+        self
+    }
+
+    fn rotate(&mut self, now: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let len = std::fs::metadata(&self.path).map_or(0, |m| m.len());
+        let full = self.rotate_size.is_some_and(|size| len >= size);
+        let expired = match (self.rotate_every, self.opened) {
+            (Some(period), Some(opened)) => now.saturating_sub(opened) >= period.as_secs(),
+            _ => false,
+        };
+        if len > 0 && (full || expired) {
+            let stamp = format_rfc3339(UNIX_EPOCH + Duration::from_secs(now)).replace(':', "-");
+            let mut rotated = self.path.clone().into_os_string();
+            rotated.push(format!(".{}", stamp));
+            std::fs::rename(&self.path, rotated)?;
+            self.opened = None;
+        }
+        Ok(())
+    }
+
+    /// Append `records` at `now`, rotating the file first if it is due.
+    pub fn write(&mut self, records: &[ConnectionRecord], now: u64) -> Result<(), Box<dyn std::error::Error>> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        self.rotate(now)?;
+        self.opened.get_or_insert(now);
+
+        match self.format {
+            RecordFormat::Jsonl => jsonl::append(&self.path, records),
+            RecordFormat::Csv => {
+                let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+                if file.metadata()?.len() == 0 {
+                    writeln!(file, "{}", CSV_HEADER)?;
+                }
+                for record in records {
+                    writeln!(file, "{}", csv_line(record))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{connection, snapshot, MockController};
+
+    #[test]
+    fn test_tracker() {
+        let mut tracker = ConnectionTracker::new();
+        let a = |up| connection("a", "example.com", "93.184.216.34", &["node", "Proxy"], up, up * 2);
+        let b = connection("b", "", "1.1.1.1", &["DIRECT"], 1, 0);
+
+        assert!(tracker.update(&snapshot(&[a(1), b.clone()]), 100).is_empty());
+        let closed = tracker.update(&snapshot(&[a(5)]), 102);
+        assert_eq!(closed.len(), 1);
+        assert_eq!((closed[0].destination(), closed[0].policy()), ("1.1.1.1", "DIRECT"));
+
+        let open = tracker.finish();
+        assert_eq!((open[0].first_seen, open[0].last_seen, open[0].upload, open[0].download), (100, 102, 5, 10));
+
+        let path = std::env::temp_dir().join(format!("clashrs-ctl-history-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        jsonl::append(&path, &closed).unwrap();
+        jsonl::append(&path, &open).unwrap();
+        let read: Vec<ConnectionRecord> = jsonl::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(read, [closed, open].concat());
+    }
+
+    #[tokio::test]
+    async fn test_record_survives_errors() {
+        let dir = std::env::temp_dir().join(format!("clashrs-ctl-record-errors-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("conns.jsonl");
+
+        // The first polls fail until the route exists.
+        let mock = MockController::new();
+        let client = mock.start().await;
+        let body = format!(
+            r#"{{"downloadTotal":0,"uploadTotal":0,"connections":[{}]}}"#,
+            connection("a", "example.com", "1.1.1.1", &["DIRECT"], 1, 2)
+        );
+        let mut errors = 0;
+        let writer = RecordWriter::new(&path, RecordFormat::Jsonl);
+        let record = ConnectionTracker::new().record(
+            client,
+            writer,
+            Duration::from_millis(10),
+            Some(Duration::from_secs(1)),
+            std::future::pending(),
+            |_| errors += 1,
+        );
+        let route = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            mock.route("GET", "connections", 200, &body);
+        };
+        let (result, _) = tokio::join!(record, route);
+        result.unwrap();
+        assert!(errors > 0);
+
+        let records: Vec<ConnectionRecord> = jsonl::read(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(records.iter().map(|r| (r.id.as_str(), r.download)).collect::<Vec<_>>(), vec![("a", 2)]);
+    }
+
+    #[test]
+    fn test_writer_rotation() {
+        let dir = std::env::temp_dir().join(format!("clashrs-ctl-record-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("conns.csv");
+        assert_eq!(RecordFormat::from_path(&path), RecordFormat::Csv);
+
+        let mut record = ConnectionRecord::new(&snapshot(&[connection("a", "a,b.com", "1.1.1.1", &["node", "Proxy"], 1, 0)]).connections[0], 0);
+        record.last_seen = 90;
+        let mut writer = RecordWriter::new(&path, RecordFormat::Csv).rotate_every(Duration::from_secs(3600));
+        writer.write(&[record.clone()], 1704067200).unwrap();
+        writer.write(&[record.clone()], 1704067200 + 1800).unwrap();
+        writer.write(&[record], 1704067200 + 3600).unwrap();
+
+        let current = std::fs::read_to_string(&path).unwrap();
+        let rotated = std::fs::read_to_string(dir.join("conns.csv.2024-01-01T01-00-00Z")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!((current.lines().count(), rotated.lines().count()), (2, 3));
+        assert!(current.starts_with("id,first_seen,"));
+        assert_eq!(
+            current.lines().nth(1).unwrap(),
+            "a,1970-01-01T00:00:00Z,1970-01-01T00:01:30Z,1,0,Proxy > node,Match,,tcp,\"a,b.com\",\
+            192.168.1.2,5000,1.1.1.1,443,/usr/bin/curl"
+        );
+    }
+}
@@ -0,0 +1,278 @@
+//! Count which rules actually match traffic, from `/connections` snapshots
+//! or the rule-match lines of the log stream.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::Duration,
+};
+
+use futures::StreamExt;
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    config::ConfigLogLevel,
+    connection::ConnectionVec,
+    rule::{Rule, RuleList},
+    stream::log::Log,
+    ClashRequest, ClashRequestBuilder,
+};
+
+/// Hits of one rule over the collection period.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RuleHits {
+    /// Index in the rule list, or `None` for rules the list does not have,
+    /// e.g. after a reload.
+    pub index: Option<usize>,
+    #[serde(flatten)]
+    pub rule: Rule,
+    pub hits: u64,
+    pub upload: u64,
+    pub download: u64,
+    /// Number of distinct destination hosts.
+    pub hosts: usize,
+}
+
+/// Rules ranked by hits, then bytes, with zero-hit rules last.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HitReport {
+    pub rules: Vec<RuleHits>,
+}
+
+#[derive(Default)]
+struct Counter {
+    hits: u64,
+    upload: u64,
+    download: u64,
+    hosts: BTreeSet<String>,
+}
+
+type Key = (String, String, String);
+
+fn key(r#type: &str, payload: &str, policy: &str) -> Key {
+    let rule = Rule {
+        r#type: r#type.to_owned(),
+        payload: payload.to_owned(),
+        proxy: policy.to_owned(),
+    };
+    (rule.normalized_type(), payload.to_lowercase(), policy.to_owned())
+}
+
+pub struct HitCollector {
+    /// The rule list followed by rules seen in traffic but not in the list.
+    rules: Vec<Rule>,
+    list_len: usize,
+    indices: HashMap<Key, usize>,
+    counters: Vec<Counter>,
+    /// Last seen `(slot, upload, download)` of each connection.
+    seen: HashMap<String, (usize, u64, u64)>,
+    log_pattern: Regex,
+}
+
+impl HitCollector {
+    pub fn new(rules: &RuleList) -> Self {
+        let rules: Vec<Rule> = rules.iter().cloned().collect();
+        let mut indices = HashMap::new();
+        for (index, rule) in rules.iter().enumerate() {
+            // Only the first of identical rules can ever match.
+            indices.entry(key(&rule.r#type, &rule.payload, &rule.proxy)).or_insert(index);
+        }
+
+        Self {
+            counters: rules.iter().map(|_| Counter::default()).collect(),
+            list_len: rules.len(),
+            rules,
+            indices,
+            seen: HashMap::new(),
+            log_pattern: Regex::new(r"-->\s+(\S+)\s+match\s+(\w+)\((.*)\)\s+using\s+([^\[\s]+)").unwrap(),
+        }
+    }
+
+    /// The counter slot of a rule, adding rules missing from the list.
+    fn slot(&mut self, r#type: &str, payload: &str, policy: &str) -> usize {
+        let k = key(r#type, payload, policy);
+        if let Some(slot) = self.indices.get(&k) {
+            return *slot;
+        }
+
+        self.rules.push(Rule {
+            r#type: r#type.to_owned(),
+            payload: payload.to_owned(),
+            proxy: policy.to_owned(),
+        });
+        self.counters.push(Counter::default());
+        self.indices.insert(k, self.counters.len() - 1);
+        self.counters.len() - 1
+    }
+
+    /// Record a snapshot of `/connections`. Each connection counts as one
+    /// hit when first seen; its bytes count as they grow.
+    pub fn record_connections(&mut self, connections: &ConnectionVec) {
+        for connection in connections.connections.iter() {
+            let upload = connection.upload as u64;
+            let download = connection.download as u64;
+
+            let (slot, last_upload, last_download) = match self.seen.get(&connection.id) {
+                Some(seen) => *seen,
+                None => {
+                    // The rule's policy is the outermost entry of the chain.
+                    let policy = connection.chains.last().map(String::as_str).unwrap_or_default();
+                    let slot = self.slot(&connection.rule, &connection.rule_payload, policy);
+                    let host = connection.metadata.destination().to_owned();
+
+                    let counter = &mut self.counters[slot];
+                    counter.hits += 1;
+                    counter.hosts.insert(host);
+                    (slot, 0, 0)
+                }
+            };
+
+            let counter = &mut self.counters[slot];
+            counter.upload += upload.saturating_sub(last_upload);
+            counter.download += download.saturating_sub(last_download);
+            self.seen.insert(connection.id.clone(), (slot, upload.max(last_upload), download.max(last_download)));
+        }
+    }
+
+    /// Record a log line such as
+    /// `[TCP] 127.0.0.1:5000 --> example.com:443 match DomainSuffix(example.com) using Proxy[node]`.
+    /// Returns whether the line named a rule. Logs carry no byte counts.
+    pub fn record_log(&mut self, log: &Log) -> bool {
+        let captures = match self.log_pattern.captures(&log.payload) {
+            Some(captures) => captures,
+            None => return false,
+        };
+        let host = captures[1].rsplit_once(':').map_or(&captures[1], |(host, _)| host).to_owned();
+        let (r#type, payload, policy) = (captures[2].to_owned(), captures[3].to_owned(), captures[4].to_owned());
+
+        let slot = self.slot(&r#type, &payload, &policy);
+        let counter = &mut self.counters[slot];
+        counter.hits += 1;
+        counter.hosts.insert(host);
+        true
+    }
+
+    /// Poll `/connections` every `interval` for `duration`.
+    pub async fn sample_connections(
+        &mut self,
+        client: ClashRequestBuilder,
+        duration: Duration,
+        interval: Duration,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let deadline = tokio::time::Instant::now() + duration;
+        loop {
+            let connections = client.clone().connections().send().await?;
+            self.record_connections(&connections);
+
+            if tokio::time::Instant::now() + interval > deadline {
+                return Ok(());
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Consume the log stream for `duration`.
+    pub async fn consume_logs(&mut self, client: ClashRequestBuilder, duration: Duration) -> Result<(), Box<dyn std::error::Error>> {
+        let mut stream = client.logs().level(ConfigLogLevel::Info).send().await?;
+        let deadline = tokio::time::sleep(duration);
+        tokio::pin!(deadline);
+
+        loop {
+            tokio::select! {
+                _ = &mut deadline => return Ok(()),
+                log = stream.next() => match log {
+                    Some(Ok(log)) => {
+                        self.record_log(&log);
+                    }
+                    Some(Err(e)) => return Err(e),
+                    None => return Ok(()),
+                },
+            }
+        }
+    }
+
+    pub fn report(&self) -> HitReport {
+        let mut rules: Vec<RuleHits> = self.rules
+            .iter()
+            .zip(self.counters.iter())
+            .enumerate()
+            .map(|(slot, (rule, counter))| RuleHits {
+                index: (slot < self.list_len).then_some(slot),
+                rule: rule.clone(),
+                hits: counter.hits,
+                upload: counter.upload,
+                download: counter.download,
+                hosts: counter.hosts.len(),
+            })
+            .collect();
+
+        rules.sort_by(|a, b| {
+            b.hits
+                .cmp(&a.hits)
+                .then((b.upload + b.download).cmp(&(a.upload + a.download)))
+                .then(a.index.unwrap_or(usize::MAX).cmp(&b.index.unwrap_or(usize::MAX)))
+        });
+        HitReport { rules }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{ConnectionFixture, MockController};
+
+    fn rules() -> RuleList {
+        ["DOMAIN-SUFFIX,google.com,Proxy", "GEOIP,CN,DIRECT", "MATCH,Proxy"]
+            .iter()
+            .filter_map(|l| Rule::from_line(l))
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    fn connection(id: &str, rule: &str, payload: &str, host: &str, upload: u64, download: u64) -> String {
+        ConnectionFixture { id, host, chains: &["node", "Proxy"], upload, download, rule, payload, ..Default::default() }.json()
+    }
+
+    #[tokio::test]
+    async fn test_sample_connections() {
+        let first = format!(
+            r#"{{"downloadTotal":0,"uploadTotal":0,"connections":[{},{}]}}"#,
+            connection("1", "DomainSuffix", "google.com", "www.google.com", 10, 100),
+            connection("2", "Match", "", "example.com", 1, 1),
+        );
+        let second = format!(
+            r#"{{"downloadTotal":0,"uploadTotal":0,"connections":[{},{}]}}"#,
+            connection("1", "DomainSuffix", "google.com", "www.google.com", 30, 300),
+            connection("3", "DomainSuffix", "google.com", "mail.google.com", 5, 5),
+        );
+
+        let mut collector = HitCollector::new(&rules());
+        let mock = MockController::new();
+        mock.route("GET", "connections", 200, &first);
+        let client = mock.start().await;
+        collector.sample_connections(client.clone(), Duration::ZERO, Duration::from_millis(10)).await.unwrap();
+        mock.route("GET", "connections", 200, &second);
+        collector.sample_connections(client, Duration::ZERO, Duration::from_millis(10)).await.unwrap();
+
+        let report = collector.report();
+        let top = &report.rules[0];
+        assert_eq!((top.index, top.hits, top.upload, top.download, top.hosts), (Some(0), 2, 35, 305, 2));
+        assert_eq!((report.rules[1].index, report.rules[1].hits), (Some(2), 1));
+        assert_eq!((report.rules[2].index, report.rules[2].hits), (Some(1), 0));
+    }
+
+    #[test]
+    fn test_record_log() {
+        let mut collector = HitCollector::new(&rules());
+        let log = |payload: &str| Log { r#type: ConfigLogLevel::Info, payload: payload.to_owned() };
+
+        assert!(collector.record_log(&log("[TCP] 127.0.0.1:5000(chrome) --> 114.114.114.114:53 match GeoIP(CN) using DIRECT")));
+        assert!(collector.record_log(&log("[TCP] 127.0.0.1:5001 --> example.org:443 match RuleSet(ads) using REJECT")));
+        assert!(!collector.record_log(&log("[TCP] 127.0.0.1:5002 --> example.org:443 doesn't match any rule using DIRECT")));
+
+        let report = collector.report();
+        assert_eq!((report.rules[0].index, report.rules[0].hosts), (Some(1), 1));
+        assert_eq!((report.rules[1].index, report.rules[1].rule.proxy.as_str()), (None, "REJECT"));
+        assert_eq!(report.rules.len(), 4);
+    }
+}
@@ -0,0 +1,60 @@
+//! Append-only JSON lines files, one record per line, as written by
+//! `connection record` and `usage record`.
+
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::Path,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Append records to a JSON lines file, creating it if missing.
+pub fn append<T: Serialize>(path: impl AsRef<Path>, records: &[T]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    for record in records {
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+    }
+    Ok(())
+}
+
+/// Read the records of a JSON lines file, skipping blank lines.
+///
+/// A last line without a newline that does not parse is the remains of an
+/// interrupted append and is skipped; any other bad line is an error.
+pub fn read<T: DeserializeOwned>(path: impl AsRef<Path>) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+    let content = std::fs::read_to_string(path)?;
+    let complete = content.ends_with('\n');
+    let lines: Vec<_> = content.lines().collect();
+    let mut records = Vec::with_capacity(lines.len());
+    for (index, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(line) {
+            Ok(record) => records.push(record),
+            Err(_) if !complete && index + 1 == lines.len() => {}
+            Err(e) => return Err(format!("Line {}: {}", index + 1, e).into()),
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_truncated_line() {
+        let path = std::env::temp_dir().join(format!("clashrs-ctl-jsonl-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        append(&path, &[1, 2]).unwrap();
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"[3,").unwrap();
+        assert_eq!(read::<u32>(&path).unwrap(), [1, 2]);
+
+        std::fs::write(&path, "1\n[3,\n2\n").unwrap();
+        let error = read::<u32>(&path).unwrap_err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(error.to_string().starts_with("Line 2:"));
+    }
+}
@@ -4,6 +4,31 @@ pub mod rule;
 pub mod version;
 pub mod connection;
 pub mod stream;
+pub mod profile;
+pub mod diff;
+pub mod confirm;
+pub mod subscription;
+pub mod overlay;
+pub mod library;
+pub mod file;
+pub mod clash_core;
+pub mod dns;
+pub mod capability;
+pub mod matcher;
+pub mod lint;
+pub mod hits;
+pub mod history;
+pub mod jsonl;
+pub mod simulate;
+pub mod filter;
+pub mod rate;
+pub mod summary;
+pub mod reap;
+pub mod usage;
+
+#[cfg(any(test, feature = "test-support"))]
+#[doc(hidden)]
+pub mod mock;
 
 use async_trait::async_trait;
 use rule::ClashRule;
@@ -11,6 +36,8 @@ use config::ClashConfig;
 use proxy::ClashProxy;
 use stream::{traffic::ClashTraffic, log::ClashLog};
 use connection::ClashConnections;
+use clash_core::ClashCore;
+use dns::ClashDns;
 
 macro_rules! fn_to_specified_request {
     ($func:ident, $to:ident) => {
@@ -42,6 +69,11 @@ pub trait ClashRequest {
     fn get_query_parameter(&self) -> String;
     fn get_body(&self) -> String;
 
+    /// The optional core feature this request relies on, if any.
+    fn feature(&self) -> Option<capability::Feature> {
+        None
+    }
+
     async fn send(self) -> Result<Self::Response, Box<dyn std::error::Error>>;
 }
 
@@ -109,6 +141,24 @@ async fn put_request<T>(request: T) -> Result<reqwest::StatusCode, Box<dyn std::
     Ok(c)
 }
 
+async fn post_request<T>(request: T) -> Result<reqwest::StatusCode, Box<dyn std::error::Error>>
+    where T: ClashRequest
+{
+    let mut c = Client::new()
+        .post(format!("http://{}:{}/{}?{}",
+                     request.get_dest(),
+                     request.get_port(),
+                     request.get_path(),
+                     request.get_query_parameter()))
+        .body(request.get_body().to_owned());
+    if let Some(secret) = request.get_secret() {
+        c = c.header("Authorization", format!("Bearer {}", secret));
+    }
+    let c = c.send().await?
+        .status();
+    Ok(c)
+}
+
 async fn patch_request<T>(request: T) -> Result<reqwest::StatusCode, Box<dyn std::error::Error>>
     where T: ClashRequest
 {
@@ -127,6 +177,32 @@ async fn patch_request<T>(request: T) -> Result<reqwest::StatusCode, Box<dyn std
     Ok(c)
 }
 
+async fn delete_request<T>(request: T) -> Result<reqwest::StatusCode, Box<dyn std::error::Error>>
+    where T: ClashRequest
+{
+    delete_request_with(&Client::new(), request).await
+}
+
+/// `DELETE` through `client`, to reuse its connections across requests.
+async fn delete_request_with<T>(client: &Client, request: T) -> Result<reqwest::StatusCode, Box<dyn std::error::Error>>
+    where T: ClashRequest
+{
+    let mut c = client
+        .delete(format!("http://{}:{}/{}?{}",
+                     request.get_dest(),
+                     request.get_port(),
+                     request.get_path(),
+                     request.get_query_parameter()))
+        .body(request.get_body().to_owned());
+    if let Some(secret) = request.get_secret() {
+        c = c.header("Authorization", format!("Bearer {}", secret));
+    }
+    let c = c.send().await?
+        .status();
+    Ok(c)
+}
+
+#[derive(Clone)]
 pub struct ClashRequestBuilder {
     ip: Option<String>,     // default: 127.0.0.1
     port: Option<u16>,   // default: 9090
@@ -178,7 +254,9 @@ impl ClashRequestBuilder {
         version, ClashVersion;
         proxies, ClashProxy;
         config, ClashConfig;
-        rule, ClashRule
+        rule, ClashRule;
+        core, ClashCore;
+        dns, ClashDns
     ];
 }
 
@@ -0,0 +1,242 @@
+//! A managed directory of named profiles, e.g. `home`, `office` and
+//! `travel`, and a small state file recording which one is active.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Serialize, Deserialize};
+
+use crate::{file, profile::Profile, ClashRequest, ClashRequestBuilder};
+
+const PROFILE_EXTENSION: &str = "yaml";
+const STATE_FILE: &str = "state.json";
+
+#[derive(Debug)]
+pub enum LibraryError {
+    NotFound(String),
+    Exists(String),
+    InvalidName(String),
+}
+
+impl std::fmt::Display for LibraryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use LibraryError::*;
+        match self {
+            NotFound(name) => write!(f, "Profile not found: {}", name),
+            Exists(name) => write!(f, "Profile already exists: {}", name),
+            InvalidName(name) => write!(f, "Invalid profile name: {}", name),
+        }
+    }
+}
+
+impl std::error::Error for LibraryError {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct LibraryState {
+    /// The profile loaded by the last `use`.
+    pub active: Option<String>,
+    /// Unix timestamp of the last `use`.
+    pub loaded_at: Option<u64>,
+    /// Selector choices saved per profile when switching away from it.
+    #[serde(default)]
+    pub selections: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+/// What happened when re-applying saved selector choices.
+#[derive(Debug, Default)]
+pub struct UseReport {
+    pub restored: Vec<(String, String)>,
+    pub failed: Vec<(String, String)>,
+}
+
+pub struct ProfileLibrary {
+    dir: PathBuf,
+}
+
+impl ProfileLibrary {
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self { dir: dir.as_ref().to_owned() }
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, PROFILE_EXTENSION))
+    }
+
+    fn check_name(name: &str) -> Result<(), LibraryError> {
+        if !file::is_valid_name(name) {
+            Err(LibraryError::InvalidName(name.to_owned()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn existing(&self, name: &str) -> Result<PathBuf, LibraryError> {
+        Self::check_name(name)?;
+        let path = self.path(name);
+        if path.is_file() {
+            Ok(path)
+        } else {
+            Err(LibraryError::NotFound(name.to_owned()))
+        }
+    }
+
+    /// Names of the profiles in the library, sorted.
+    pub fn list(&self) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(Box::new(e)),
+        };
+
+        let mut names = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == PROFILE_EXTENSION) {
+                names.extend(path.file_stem().and_then(|s| s.to_str()).map(str::to_owned));
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Copy the profile at `source` into the library as `name`. The file
+    /// has to parse as a profile.
+    pub fn add(&self, name: &str, source: impl AsRef<Path>, replace: bool) -> Result<(), Box<dyn std::error::Error>> {
+        Self::check_name(name)?;
+        let path = self.path(name);
+        if path.exists() && !replace {
+            return Err(Box::new(LibraryError::Exists(name.to_owned())));
+        }
+
+        let content = std::fs::read_to_string(source)?;
+        Profile::try_from(content.clone())?;
+
+        std::fs::create_dir_all(&self.dir)?;
+        file::write_atomic(path, content)?;
+        Ok(())
+    }
+
+    pub fn remove(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::remove_file(self.existing(name)?)?;
+
+        let mut state = self.state()?;
+        state.selections.remove(name);
+        if state.active.as_deref() == Some(name) {
+            state.active = None;
+            state.loaded_at = None;
+        }
+        self.save_state(&state)
+    }
+
+    pub fn state(&self) -> Result<LibraryState, Box<dyn std::error::Error>> {
+        match std::fs::read_to_string(self.dir.join(STATE_FILE)) {
+            Ok(content) => Ok(serde_json::from_str(&content)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(LibraryState::default()),
+            Err(e) => Err(Box::new(e)),
+        }
+    }
+
+    pub fn save_state(&self, state: &LibraryState) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::create_dir_all(&self.dir)?;
+        file::write_atomic(self.dir.join(STATE_FILE), serde_json::to_string_pretty(state)?)?;
+        Ok(())
+    }
+
+    /// Load profile `name` through `ClashConfigLoad` and mark it active.
+    ///
+    /// The selector choices of the previously active profile are saved
+    /// first. With `restore`, the choices saved for `name` are applied
+    /// again after loading it.
+    pub async fn use_profile(&self, name: &str, client: ClashRequestBuilder, restore: bool) -> Result<UseReport, Box<dyn std::error::Error>> {
+        let path = self.existing(name)?;
+        let mut state = self.state()?;
+
+        if let Some(active) = state.active.clone() {
+            // Best effort: the core may not be running a library profile.
+            if let Ok(proxies) = client.clone().proxies().send().await {
+                state.selections.insert(active, proxies.selections());
+            }
+        }
+
+        client.clone().config().load(file::path_str(&path)?).send().await?;
+
+        let mut report = UseReport::default();
+        if restore {
+            for (group, choice) in state.selections.get(name).cloned().unwrap_or_default() {
+                let res = client.clone().proxies().get(&group).change(&choice).send().await;
+                match res {
+                    Ok(()) => report.restored.push((group, choice)),
+                    Err(_) => report.failed.push((group, choice)),
+                }
+            }
+        }
+
+        state.active = Some(name.to_owned());
+        state.loaded_at = SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
+        self.save_state(&state)?;
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::MockController;
+
+    fn library(tag: &str) -> (ProfileLibrary, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("clashrsctl-library-{}-{}", tag, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        (ProfileLibrary::new(&dir), dir)
+    }
+
+    #[test]
+    fn test_add_list_remove() {
+        let (library, dir) = library("crud");
+
+        library.add("home", "./clash-profile", false).unwrap();
+        library.add("office", "./clash-profile.reload", false).unwrap();
+        assert!(library.add("home", "./clash-profile", false).is_err());
+        assert!(library.add("../escape", "./clash-profile", false).is_err());
+        assert_eq!(library.list().unwrap(), vec!["home", "office"]);
+
+        library.remove("home").unwrap();
+        assert_eq!(library.list().unwrap(), vec!["office"]);
+        assert!(library.remove("home").is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_use_profile_restores_selections() {
+        let (library, dir) = library("use");
+        library.add("home", "./clash-profile", false).unwrap();
+        library.add("office", "./clash-profile.reload", false).unwrap();
+
+        let mock = MockController::new();
+        mock.route("GET", "proxies", 200, r#"{"proxies":{"PROXY":{"type":"Selector","now":"node-jp","all":["node-jp"]}}}"#)
+            .route("PUT", "configs", 204, "")
+            .route("PUT", "proxies/PROXY", 204, "");
+        let client = mock.start().await;
+
+        library.use_profile("home", client.clone(), true).await.unwrap();
+        library.use_profile("office", client.clone(), true).await.unwrap();
+        let report = library.use_profile("home", client, true).await.unwrap();
+
+        assert_eq!(report.restored, vec![("PROXY".to_owned(), "node-jp".to_owned())]);
+        let state = library.state().unwrap();
+        assert_eq!(state.active.as_deref(), Some("home"));
+        assert!(state.loaded_at.is_some());
+
+        let loads: Vec<_> = mock.requests().into_iter().filter(|r| r.method == "PUT" && r.path == "configs").collect();
+        assert_eq!(loads.len(), 3);
+        assert_eq!(loads[0].query, "force=false");
+
+        let changes: Vec<_> = mock.requests().into_iter().filter(|r| r.path == "proxies/PROXY").collect();
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].body.contains("node-jp"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+//! Find rules that can never match or that repeat earlier ones.
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde::{Serialize, Deserialize};
+
+use crate::rule::kind::{RuleKind, RuleParseError, TypedRule};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", tag = "issue")]
+pub enum Issue {
+    /// An earlier rule with another policy matches everything this one
+    /// does, so it is never reached.
+    Shadowed { by: usize },
+    /// The same rule appears earlier.
+    Duplicate { of: usize },
+    /// An earlier rule with the same policy covers this one, e.g. a CIDR
+    /// contained in an earlier CIDR.
+    Redundant { within: usize },
+    /// The policy is neither a proxy, a group nor built in.
+    UnknownPolicy { policy: String },
+    /// The rule could not be parsed.
+    Invalid { error: String },
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Issue::*;
+        match self {
+            Shadowed { by } => write!(f, "unreachable, shadowed by #{}", by),
+            Duplicate { of } => write!(f, "duplicate of #{}", of),
+            Redundant { within } => write!(f, "redundant, covered by #{} with the same policy", within),
+            UnknownPolicy { policy } => write!(f, "unknown policy {}", policy),
+            Invalid { error } => write!(f, "invalid rule: {}", error),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Finding {
+    pub index: usize,
+    pub rule: String,
+    #[serde(flatten)]
+    pub issue: Issue,
+}
+
+fn domain_covered_by_suffix(domain: &str, suffix: &str) -> bool {
+    let suffix = suffix.trim_start_matches('.');
+    domain.eq_ignore_ascii_case(suffix)
+        || domain.to_lowercase().ends_with(&format!(".{}", suffix.to_lowercase()))
+}
+
+fn resolve_covered(earlier: &RuleKind, later: &RuleKind) -> bool {
+    // A `no-resolve` rule skips connections to hosts, which a rule
+    // without it still matches.
+    !earlier.no_resolve() || later.no_resolve()
+}
+
+/// Whether every connection matched by `later` is matched by `earlier`.
+/// Errs on the side of `false` for kinds it cannot compare.
+pub fn covers(earlier: &RuleKind, later: &RuleKind) -> bool {
+    use RuleKind::*;
+
+    match (earlier, later) {
+        (Match, _) => true,
+        (Or(kinds), _) => kinds.iter().any(|k| covers(k, later)),
+        (_, And(kinds)) => kinds.iter().any(|k| covers(earlier, k)),
+        (_, Or(kinds)) => kinds.iter().all(|k| covers(earlier, k)),
+        (Domain(a), Domain(b)) => a.eq_ignore_ascii_case(b),
+        (DomainSuffix(s), Domain(d) | DomainSuffix(d)) => domain_covered_by_suffix(d, s),
+        (DomainKeyword(k), Domain(d) | DomainSuffix(d) | DomainKeyword(d)) => {
+            d.to_lowercase().contains(&k.to_lowercase())
+        }
+        (IpCidr { net: a, .. }, IpCidr { net: b, .. }) | (IpSuffix { net: a, .. }, IpSuffix { net: b, .. }) => {
+            a.contains(b) && resolve_covered(earlier, later)
+        }
+        (SrcIpCidr(a), SrcIpCidr(b)) | (SrcIpSuffix(a), SrcIpSuffix(b)) => a.contains(b),
+        (DstPort(a), DstPort(b)) | (SrcPort(a), SrcPort(b)) | (InPort(a), InPort(b)) => {
+            b.0.iter().all(|(low, high)| a.0.iter().any(|(l, h)| l <= low && high <= h))
+        }
+        (GeoIp { country: a, .. }, GeoIp { country: b, .. }) => a.eq_ignore_ascii_case(b) && resolve_covered(earlier, later),
+        (RuleSet { name: a, .. }, RuleSet { name: b, .. }) => a == b && resolve_covered(earlier, later),
+        (Process(a), Process(b)) => a == b,
+        (Process(a), ProcessPath(b)) => b.rsplit(['/', '\\']).next() == Some(a.as_str()),
+        (a, b) => a == b,
+    }
+}
+
+/// Lint `rules`, each given with the line it was parsed from, in order.
+/// Policies are checked against `policies` when given; `SUB-RULE` targets
+/// are not policies and are skipped.
+pub fn lint(rules: &[(String, Result<TypedRule, RuleParseError>)], policies: Option<&BTreeSet<String>>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut seen: HashMap<&TypedRule, usize> = HashMap::new();
+    let mut earlier: Vec<(usize, &TypedRule)> = Vec::new();
+
+    for (index, (source, rule)) in rules.iter().enumerate() {
+        let rule = match rule {
+            Ok(rule) => rule,
+            Err(e) => {
+                findings.push(Finding { index, rule: source.clone(), issue: Issue::Invalid { error: e.to_string() } });
+                continue;
+            }
+        };
+        let line = rule.to_line();
+
+        if let Some(policies) = policies {
+            if !matches!(rule.kind, RuleKind::SubRule(_)) && !policies.contains(&rule.policy) {
+                findings.push(Finding {
+                    index,
+                    rule: line.clone(),
+                    issue: Issue::UnknownPolicy { policy: rule.policy.clone() },
+                });
+            }
+        }
+
+        let issue = match seen.get(rule) {
+            Some(of) => Some(Issue::Duplicate { of: *of }),
+            None => earlier
+                .iter()
+                .find(|(_, e)| covers(&e.kind, &rule.kind))
+                .map(|(i, e)| match e.policy == rule.policy {
+                    true => Issue::Redundant { within: *i },
+                    false => Issue::Shadowed { by: *i },
+                }),
+        };
+
+        match issue {
+            Some(issue) => findings.push(Finding { index, rule: line, issue }),
+            None => earlier.push((index, rule)),
+        }
+        seen.entry(rule).or_insert(index);
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lint_lines(lines: &[&str], policies: Option<&BTreeSet<String>>) -> Vec<(usize, Issue)> {
+        let rules: Vec<_> = lines.iter().map(|l| (l.to_string(), TypedRule::parse_line(l))).collect();
+        lint(&rules, policies).into_iter().map(|f| (f.index, f.issue)).collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        let findings = lint_lines(&[
+            "DOMAIN-SUFFIX,google.com,Proxy",
+            "DOMAIN,mail.google.com,DIRECT",
+            "DOMAIN-SUFFIX,google.com,Proxy",
+            "IP-CIDR,10.0.0.0/8,DIRECT",
+            "IP-CIDR,10.1.0.0/16,DIRECT",
+            "IP-CIDR,10.2.0.0/16,Proxy,no-resolve",
+            "IP-CIDR,192.168.0.0/16,DIRECT,no-resolve",
+            "IP-CIDR,192.168.1.0/24,DIRECT",
+            "DOMAIN-KEYWORD,ads,REJECT",
+            "AND,((DOMAIN,ads.example.com),(NETWORK,UDP)),DIRECT",
+            "MATCH,Proxy",
+            "DOMAIN,late.example.com,DIRECT",
+        ], None);
+
+        assert_eq!(findings, vec![
+            (1, Issue::Shadowed { by: 0 }),
+            (2, Issue::Duplicate { of: 0 }),
+            (4, Issue::Redundant { within: 3 }),
+            (5, Issue::Shadowed { by: 3 }),
+            (9, Issue::Shadowed { by: 8 }),
+            (11, Issue::Shadowed { by: 10 }),
+        ]);
+    }
+
+    #[test]
+    fn test_unknown_policy() {
+        let policies: BTreeSet<String> = ["DIRECT", "Proxy"].iter().map(|s| s.to_string()).collect();
+        let findings = lint_lines(&[
+            "DOMAIN,a.com,Proxy",
+            "DOMAIN,b.com,Missing",
+            "SUB-RULE,(NETWORK,TCP),tcp-rules",
+            "IP-CIDR,10.0.0.0/33,DIRECT",
+        ], Some(&policies));
+
+        assert_eq!(findings[0], (1, Issue::UnknownPolicy { policy: "Missing".to_owned() }));
+        assert!(matches!(findings[1], (3, Issue::Invalid { .. })));
+        assert_eq!(findings.len(), 2);
+
+        let rules = vec![("IP-CIDR,10.0.0.0/33,DIRECT".to_owned(), TypedRule::parse_line("IP-CIDR,10.0.0.0/33,DIRECT"))];
+        assert_eq!(lint(&rules, None)[0].rule, "IP-CIDR,10.0.0.0/33,DIRECT");
+    }
+}
@@ -0,0 +1,471 @@
+//! Offline rule evaluation: which rule, and so which policy, would the
+//! core pick for a connection?
+
+use std::{collections::HashMap, net::IpAddr};
+
+use ipnet::IpNet;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::rule::{
+    kind::{Network, PortSet, RuleKind, RuleParseError, TypedRule},
+    Rule, RuleList,
+};
+
+/// The connection to evaluate. Fields left as `None` never match a rule
+/// that needs them.
+#[derive(Debug, Clone, Default)]
+pub struct Query {
+    pub host: Option<String>,
+    pub ip: Option<IpAddr>,
+    pub dst_port: Option<u16>,
+    /// `TCP` when left unset.
+    pub network: Option<Network>,
+    /// Port of the inbound the connection arrived on.
+    pub in_port: Option<u16>,
+    pub src_ip: Option<IpAddr>,
+    pub src_port: Option<u16>,
+    /// Process name or full path.
+    pub process: Option<String>,
+}
+
+impl Query {
+    /// Build a query from `host`, `host:port`, `ip`, `ip:port` or
+    /// `[ipv6]:port`.
+    pub fn parse(target: &str) -> Option<Self> {
+        let (addr, port) = if let Some(rest) = target.strip_prefix('[') {
+            let (addr, rest) = rest.split_once(']')?;
+            let port = match rest.strip_prefix(':') {
+                Some(port) => Some(port.parse().ok()?),
+                None => None,
+            };
+            (addr, port)
+        } else if target.matches(':').count() == 1 {
+            let (addr, port) = target.split_once(':')?;
+            (addr, Some(port.parse().ok()?))
+        } else {
+            (target, None)
+        };
+
+        if addr.is_empty() {
+            return None;
+        }
+
+        let mut query = Self { dst_port: port, ..Self::default() };
+        match addr.parse::<IpAddr>() {
+            Ok(ip) => query.ip = Some(ip),
+            Err(_) => query.host = Some(addr.trim_end_matches('.').to_lowercase()),
+        }
+        Some(query)
+    }
+
+    pub fn ip(self, ip: IpAddr) -> Self {
+        Self { ip: Some(ip), ..self }
+    }
+
+    pub fn src_ip(self, ip: IpAddr) -> Self {
+        Self { src_ip: Some(ip), ..self }
+    }
+
+    pub fn src_port(self, port: u16) -> Self {
+        Self { src_port: Some(port), ..self }
+    }
+
+    pub fn process(self, process: &str) -> Self {
+        Self { process: Some(process.to_owned()), ..self }
+    }
+
+    pub fn network(self, network: Network) -> Self {
+        Self { network: Some(network), ..self }
+    }
+
+    pub fn in_port(self, port: u16) -> Self {
+        Self { in_port: Some(port), ..self }
+    }
+}
+
+/// Country lookup for `GEOIP` rules.
+pub trait GeoIpResolver {
+    /// The ISO country code of `ip`, e.g. `CN`.
+    fn country(&self, ip: IpAddr) -> Option<String>;
+}
+
+/// Membership lookup for `RULE-SET` rules.
+pub trait RuleSetResolver {
+    /// Whether the rule set `name` matches `query`, or `None` if unknown.
+    fn matches(&self, name: &str, query: &Query) -> Option<bool>;
+}
+
+/// The first rule matching a query.
+#[derive(Serialize, Debug, Clone)]
+pub struct Match {
+    pub index: usize,
+    pub rule: Rule,
+}
+
+impl Match {
+    pub fn policy(&self) -> &str {
+        &self.rule.proxy
+    }
+}
+
+/// The result of evaluating a query against a rule list.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Evaluation {
+    pub matched: Option<Match>,
+    /// Indices of rules before the match that could not be decided, e.g.
+    /// `GEOIP` without a resolver. Any of them might have matched first.
+    pub undecided: Vec<usize>,
+}
+
+fn cidr_matches(net: &IpNet, ip: Option<IpAddr>) -> Option<bool> {
+    Some(ip.is_some_and(|ip| net.contains(&ip)))
+}
+
+fn ports_match(ports: &PortSet, port: Option<u16>) -> Option<bool> {
+    Some(port.is_some_and(|port| ports.contains(port)))
+}
+
+fn process_name(process: &str) -> &str {
+    process.rsplit(['/', '\\']).next().unwrap_or(process)
+}
+
+/// A rule the matcher cannot evaluate. It is never decided.
+#[derive(Debug, Clone)]
+pub enum InvalidRule {
+    /// The rule does not parse.
+    Unparsable { index: usize, line: String, error: RuleParseError },
+    /// A regex of the rule does not compile, one entry per pattern.
+    Regex { index: usize, pattern: String, error: String },
+}
+
+impl InvalidRule {
+    /// Index of the rule in the list.
+    pub fn index(&self) -> usize {
+        match self {
+            Self::Unparsable { index, .. } | Self::Regex { index, .. } => *index,
+        }
+    }
+}
+
+impl std::fmt::Display for InvalidRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unparsable { index, line, error } => write!(f, "Rule {}: cannot parse `{}`: {}", index, line, error),
+            Self::Regex { index, pattern, error } => write!(f, "Rule {}: invalid regex `{}`: {}", index, pattern, error),
+        }
+    }
+}
+
+pub struct Matcher<'a> {
+    rules: Vec<(Rule, Option<RuleKind>)>,
+    /// The regexes of the rules, compiled once.
+    regexes: HashMap<String, Regex>,
+    invalid: Vec<InvalidRule>,
+    geoip: Option<&'a dyn GeoIpResolver>,
+    rule_set: Option<&'a dyn RuleSetResolver>,
+}
+
+impl<'a> Matcher<'a> {
+    pub fn new(rules: &RuleList) -> Self {
+        let mut invalid = Vec::new();
+        let rules = rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| match rule.kind() {
+                Ok(kind) => (rule.clone(), Some(kind)),
+                Err(error) => {
+                    invalid.push(InvalidRule::Unparsable { index, line: rule.to_line(), error });
+                    (rule.clone(), None)
+                }
+            })
+            .collect();
+        Self::build(rules, invalid)
+    }
+
+    /// Match against parsed rules, e.g. from a profile, keeping options
+    /// like `no-resolve` that the controller does not report.
+    pub fn from_typed(rules: impl IntoIterator<Item = TypedRule>) -> Self {
+        Self::build(rules.into_iter().map(|r| (Rule::from(&r), Some(r.kind))).collect(), Vec::new())
+    }
+
+    /// Match against profile lines as parsed for [`crate::lint::lint`].
+    /// Lines that failed to parse stay in place, so indices match the
+    /// profile, and are reported by [`Matcher::invalid`].
+    pub fn from_parsed(rules: &[(String, Result<TypedRule, RuleParseError>)]) -> Self {
+        let mut invalid = Vec::new();
+        let rules = rules
+            .iter()
+            .enumerate()
+            .map(|(index, (line, rule))| match rule {
+                Ok(rule) => (Rule::from(rule), Some(rule.kind.clone())),
+                Err(error) => {
+                    invalid.push(InvalidRule::Unparsable { index, line: line.clone(), error: error.clone() });
+                    let rule = Rule::from_line(line).unwrap_or_else(|| Rule { r#type: line.clone(), payload: String::new(), proxy: String::new() });
+                    (rule, None)
+                }
+            })
+            .collect();
+        Self::build(rules, invalid)
+    }
+
+    fn build(rules: Vec<(Rule, Option<RuleKind>)>, invalid: Vec<InvalidRule>) -> Self {
+        let mut matcher = Self { rules: Vec::new(), regexes: HashMap::new(), invalid, geoip: None, rule_set: None };
+        for (index, (_, kind)) in rules.iter().enumerate() {
+            if let Some(kind) = kind {
+                matcher.compile(index, kind);
+            }
+        }
+        matcher.rules = rules;
+        matcher
+    }
+
+    fn compile(&mut self, index: usize, kind: &RuleKind) {
+        use RuleKind::*;
+        match kind {
+            DomainRegex(pattern) | ProcessNameRegex(pattern) | ProcessPathRegex(pattern) => {
+                if self.regexes.contains_key(pattern) {
+                    return;
+                }
+                match Regex::new(pattern) {
+                    Ok(regex) => {
+                        self.regexes.insert(pattern.clone(), regex);
+                    }
+                    Err(e) => self.invalid.push(InvalidRule::Regex { index, pattern: pattern.clone(), error: e.to_string() }),
+                }
+            }
+            And(kinds) | Or(kinds) => kinds.iter().for_each(|kind| self.compile(index, kind)),
+            Not(kind) => self.compile(index, kind),
+            _ => {}
+        }
+    }
+
+    /// The rules that did not parse and the regexes that did not compile,
+    /// in rule order within each kind.
+    pub fn invalid(&self) -> &[InvalidRule] {
+        &self.invalid
+    }
+
+    fn regex_matches(&self, pattern: &str, value: Option<&str>) -> Option<bool> {
+        let value = match value {
+            Some(value) => value,
+            None => return Some(false),
+        };
+        Some(self.regexes.get(pattern)?.is_match(value))
+    }
+
+    pub fn geoip(self, resolver: &'a dyn GeoIpResolver) -> Self {
+        Self { geoip: Some(resolver), ..self }
+    }
+
+    pub fn rule_set(self, resolver: &'a dyn RuleSetResolver) -> Self {
+        Self { rule_set: Some(resolver), ..self }
+    }
+
+    /// Whether `kind` matches `query`, or `None` if it cannot be decided.
+    pub fn rule_matches(&self, kind: &RuleKind, query: &Query) -> Option<bool> {
+        use RuleKind::*;
+
+        // The core resolves the host for IP rules unless told not to.
+        let resolves = matches!(kind, IpCidr { .. } | IpSuffix { .. } | GeoIp { .. } | IpAsn { .. } | IpSet { .. });
+        if resolves && !kind.no_resolve() && query.ip.is_none() && query.host.is_some() {
+            return None;
+        }
+
+        let host = query.host.as_deref();
+        match kind {
+            Domain(domain) => Some(host.is_some_and(|h| h == domain.to_lowercase())),
+            DomainSuffix(suffix) => {
+                let suffix = suffix.trim_start_matches('.').to_lowercase();
+                Some(host.is_some_and(|h| h == suffix || h.ends_with(&format!(".{}", suffix))))
+            }
+            DomainKeyword(keyword) => Some(host.is_some_and(|h| h.contains(&keyword.to_lowercase()))),
+            DomainRegex(pattern) => self.regex_matches(pattern, host),
+            IpCidr { net, .. } => cidr_matches(net, query.ip),
+            SrcIpCidr(net) => cidr_matches(net, query.src_ip),
+            DstPort(ports) => ports_match(ports, query.dst_port),
+            SrcPort(ports) => ports_match(ports, query.src_port),
+            InPort(ports) => ports_match(ports, query.in_port),
+            Network(network) => Some(query.network.unwrap_or(self::Network::Tcp) == *network),
+            Process(name) => Some(query.process.as_deref().is_some_and(|p| process_name(p) == name)),
+            ProcessPath(path) => Some(query.process.as_deref() == Some(path.as_str())),
+            ProcessNameRegex(pattern) => self.regex_matches(pattern, query.process.as_deref().map(process_name)),
+            ProcessPathRegex(pattern) => self.regex_matches(pattern, query.process.as_deref()),
+            GeoIp { country, .. } => {
+                let ip = match query.ip {
+                    Some(ip) => ip,
+                    None => return Some(false),
+                };
+                Some(self.geoip?.country(ip)?.eq_ignore_ascii_case(country))
+            }
+            RuleSet { name, .. } => self.rule_set?.matches(name, query),
+            And(kinds) => {
+                let mut result = Some(true);
+                for kind in kinds {
+                    match self.rule_matches(kind, query) {
+                        Some(false) => return Some(false),
+                        Some(true) => {}
+                        None => result = None,
+                    }
+                }
+                result
+            }
+            Or(kinds) => {
+                let mut result = Some(false);
+                for kind in kinds {
+                    match self.rule_matches(kind, query) {
+                        Some(true) => return Some(true),
+                        Some(false) => {}
+                        None => result = None,
+                    }
+                }
+                result
+            }
+            Not(kind) => self.rule_matches(kind, query).map(|m| !m),
+            Match => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Find the first rule matching `query`.
+    pub fn evaluate(&self, query: &Query) -> Evaluation {
+        let mut evaluation = Evaluation::default();
+        for (index, (rule, kind)) in self.rules.iter().enumerate() {
+            match kind.as_ref().and_then(|kind| self.rule_matches(kind, query)) {
+                Some(true) => {
+                    evaluation.matched = Some(Match { index, rule: rule.clone() });
+                    break;
+                }
+                Some(false) => {}
+                None => evaluation.undecided.push(index),
+            }
+        }
+        evaluation
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rules(lines: &[&str]) -> RuleList {
+        lines.iter().filter_map(|l| Rule::from_line(l)).collect::<Vec<_>>().into()
+    }
+
+    struct Geo;
+
+    impl GeoIpResolver for Geo {
+        fn country(&self, ip: IpAddr) -> Option<String> {
+            match ip.to_string().as_str() {
+                "114.114.114.114" => Some("CN".to_owned()),
+                _ => Some("US".to_owned()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let q = Query::parse("Example.com:443").unwrap();
+        assert_eq!(q.host.as_deref(), Some("example.com"));
+        assert_eq!(q.dst_port, Some(443));
+
+        let q = Query::parse("[2001:db8::1]:53").unwrap();
+        assert_eq!(q.ip, Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(q.dst_port, Some(53));
+
+        assert!(Query::parse("2001:db8::1").unwrap().ip.is_some());
+        assert!(Query::parse("example.com:https").is_none());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let list = rules(&[
+            "DOMAIN,exact.example.com,A",
+            "DOMAIN-SUFFIX,google.com,B",
+            "DOMAIN-KEYWORD,tracker,REJECT",
+            "IP-CIDR,10.0.0.0/8,DIRECT,no-resolve",
+            "SRC-IP-CIDR,192.168.1.0/24,LAN",
+            "DST-PORT,8000-9000,HighPort",
+            "PROCESS-NAME,steam,Games",
+            "GEOIP,CN,DIRECT",
+            "MATCH,Proxy",
+        ]);
+        let geo = Geo;
+        let matcher = Matcher::new(&list).geoip(&geo);
+        let policy = |q: Query| matcher.evaluate(&q).matched.map(|m| (m.index, m.policy().to_owned()));
+
+        assert_eq!(policy(Query::parse("exact.example.com").unwrap()), Some((0, "A".to_owned())));
+        assert_eq!(policy(Query::parse("mail.google.com:443").unwrap()), Some((1, "B".to_owned())));
+        assert_eq!(policy(Query::parse("google.com").unwrap()), Some((1, "B".to_owned())));
+        assert_eq!(policy(Query::parse("notgoogle.com").unwrap()), Some((8, "Proxy".to_owned())));
+        assert_eq!(policy(Query::parse("ads.tracker.net").unwrap()), Some((2, "REJECT".to_owned())));
+        assert_eq!(policy(Query::parse("10.1.2.3:80").unwrap()), Some((3, "DIRECT".to_owned())));
+        assert_eq!(policy(Query::parse("a.com").unwrap().src_ip("192.168.1.7".parse().unwrap())), Some((4, "LAN".to_owned())));
+        assert_eq!(policy(Query::parse("a.com:8443").unwrap()), Some((5, "HighPort".to_owned())));
+        assert_eq!(policy(Query::parse("a.com").unwrap().process("/usr/bin/steam")), Some((6, "Games".to_owned())));
+        assert_eq!(policy(Query::parse("114.114.114.114").unwrap()), Some((7, "DIRECT".to_owned())));
+    }
+
+    #[test]
+    fn test_logical_rules() {
+        let list = rules(&[
+            "AND,((NETWORK,UDP),(DST-PORT,443)),REJECT",
+            "NOT,((DOMAIN-SUFFIX,example.com)),Proxy",
+            "MATCH,DIRECT",
+        ]);
+        let matcher = Matcher::new(&list);
+        let index = |q: Query| matcher.evaluate(&q).matched.unwrap().index;
+
+        assert_eq!(index(Query::parse("example.com:443").unwrap().network(Network::Udp)), 0);
+        assert_eq!(index(Query::parse("a.example.com:443").unwrap()), 2);
+        assert_eq!(index(Query::parse("other.org:443").unwrap()), 1);
+    }
+
+    #[test]
+    fn test_regex_rules() {
+        let list = rules(&[
+            "DOMAIN-REGEX,^ad[0-9]+\\.,REJECT",
+            "OR,((DOMAIN-REGEX,[z-a]),(PROCESS-NAME-REGEX,^curl$)),DIRECT",
+            "MATCH,Proxy",
+        ]);
+        let matcher = Matcher::new(&list);
+        assert_eq!(matcher.invalid().len(), 1);
+        assert!(matches!(&matcher.invalid()[0], InvalidRule::Regex { index: 1, pattern, .. } if pattern == "[z-a]"));
+
+        assert_eq!(matcher.evaluate(&Query::parse("ad12.example.com").unwrap()).matched.unwrap().index, 0);
+        assert_eq!(matcher.evaluate(&Query::parse("a.com").unwrap().process("/usr/bin/curl")).matched.unwrap().index, 1);
+        let evaluation = matcher.evaluate(&Query::parse("a.com").unwrap());
+        assert_eq!((evaluation.undecided, evaluation.matched.unwrap().index), (vec![1], 2));
+    }
+
+    #[test]
+    fn test_unparsable_rules() {
+        let lines = ["IP-CIDR,10.0.0.0/33,DIRECT", "DOMAIN,a.com", "DOMAIN,b.com,Proxy", "MATCH,DIRECT"];
+        let parsed: Vec<_> = lines.iter().map(|l| (l.to_string(), TypedRule::parse_line(l))).collect();
+        let matcher = Matcher::from_parsed(&parsed);
+        assert_eq!(matcher.invalid().iter().map(InvalidRule::index).collect::<Vec<_>>(), vec![0, 1]);
+        assert!(matches!(&matcher.invalid()[1], InvalidRule::Unparsable { line, .. } if line == "DOMAIN,a.com"));
+        let evaluation = matcher.evaluate(&Query::parse("b.com").unwrap());
+        assert_eq!((evaluation.undecided, evaluation.matched.unwrap().index), (vec![0, 1], 2));
+
+        let matcher = Matcher::new(&rules(&["IP-CIDR,10.0.0.0/33,DIRECT", "MATCH,DIRECT"]));
+        assert!(matches!(matcher.invalid(), [InvalidRule::Unparsable { index: 0, .. }]));
+        assert_eq!(matcher.evaluate(&Query::parse("10.0.0.1").unwrap()).matched.unwrap().index, 1);
+    }
+
+    #[test]
+    fn test_undecided_without_resolver() {
+        let list = rules(&["GEOIP,CN,DIRECT", "RULE-SET,ads,REJECT", "MATCH,Proxy"]);
+        let evaluation = Matcher::new(&list).evaluate(&Query::parse("1.2.3.4").unwrap());
+
+        assert_eq!(evaluation.undecided, vec![0, 1]);
+        assert_eq!(evaluation.matched.unwrap().index, 2);
+
+        // The core would resolve the host for IP rules without no-resolve.
+        let list = ["IP-CIDR,10.0.0.0/8,DIRECT", "IP-CIDR,1.0.0.0/8,DIRECT,no-resolve", "MATCH,Proxy"]
+            .iter()
+            .map(|l| TypedRule::parse_line(l).unwrap());
+        let evaluation = Matcher::from_typed(list).evaluate(&Query::parse("example.com").unwrap());
+        assert_eq!(evaluation.undecided, vec![0]);
+    }
+}
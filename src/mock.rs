@@ -0,0 +1,211 @@
+//! A minimal stand-in for the clash controller, used by tests that cannot
+//! rely on a clash server listening on port 9090.
+
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::{connection::{Connection, ConnectionVec}, ClashRequestBuilder};
+
+#[derive(Debug, Clone)]
+pub struct Recorded {
+    pub method: String,
+    pub path: String,
+    pub query: String,
+    pub body: String,
+}
+
+type Route = (String, String, u16, String, Vec<(String, String)>);
+
+#[derive(Clone, Default)]
+pub struct MockController {
+    routes: Arc<Mutex<Vec<Route>>>,
+    requests: Arc<Mutex<Vec<Recorded>>>,
+}
+
+impl MockController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer `method path` (without the query string) with `status` and
+    /// `body`. Later routes for the same request take precedence.
+    pub fn route(&self, method: &str, path: &str, status: u16, body: &str) -> &Self {
+        self.route_with_headers(method, path, status, body, &[])
+    }
+
+    pub fn route_with_headers(&self, method: &str, path: &str, status: u16, body: &str, headers: &[(&str, &str)]) -> &Self {
+        let headers = headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        self.routes.lock().unwrap().push((method.to_owned(), path.to_owned(), status, body.to_owned(), headers));
+        self
+    }
+
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Start serving and return a request builder pointing at the mock.
+    pub async fn start(&self) -> ClashRequestBuilder {
+        let port = self.serve().await;
+        ClashRequestBuilder::new().ip("127.0.0.1").port(port)
+    }
+
+    /// Start serving and return the port listened on.
+    pub async fn serve(&self) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let this = self.clone();
+
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let this = this.clone();
+                tokio::spawn(async move {
+                    let request = match read_request(&mut socket).await {
+                        Some(request) => request,
+                        None => return,
+                    };
+                    let (status, body, headers) = this.respond(&request);
+                    this.requests.lock().unwrap().push(request);
+
+                    let headers: String = headers.iter().map(|(k, v)| format!("{}: {}\r\n", k, v)).collect();
+                    let response = format!(
+                        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        headers,
+                        body.len(),
+                        body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+
+        port
+    }
+
+    fn respond(&self, request: &Recorded) -> (u16, String, Vec<(String, String)>) {
+        let path = &request.path;
+        self.routes
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|(method, p, _, _, _)| *method == request.method && p == path)
+            .map(|(_, _, status, body, headers)| (*status, body.clone(), headers.clone()))
+            .unwrap_or((404, "".to_owned(), Vec::new()))
+    }
+}
+
+async fn read_request(socket: &mut tokio::net::TcpStream) -> Option<Recorded> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    let header_end = loop {
+        let n = socket.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let length = head
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("content-length").then(|| value.trim().parse::<usize>().ok())?
+        })
+        .unwrap_or(0);
+
+    while buf.len() < header_end + length {
+        let n = socket.read(&mut chunk).await.ok()?;
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    let mut request_line = head.lines().next()?.split_whitespace();
+    let method = request_line.next()?.to_owned();
+    let target = request_line.next()?.trim_start_matches('/');
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let body = String::from_utf8_lossy(&buf[header_end..]).to_string();
+
+    Some(Recorded { method, path: path.to_owned(), query: query.to_owned(), body })
+}
+
+/// A connection as `/connections` reports it. Fields left to `default()`
+/// describe a curl connection from 192.168.1.2, caught by `MATCH` and
+/// started at 2024-01-01.
+#[derive(Debug, Clone)]
+pub struct ConnectionFixture<'a> {
+    pub id: &'a str,
+    pub host: &'a str,
+    pub dst: &'a str,
+    pub chains: &'a [&'a str],
+    pub upload: u64,
+    pub download: u64,
+    pub rule: &'a str,
+    pub payload: &'a str,
+    pub start: &'a str,
+    pub process: &'a str,
+}
+
+impl Default for ConnectionFixture<'_> {
+    fn default() -> Self {
+        Self {
+            id: "",
+            host: "",
+            dst: "",
+            chains: &[],
+            upload: 0,
+            download: 0,
+            rule: "Match",
+            payload: "",
+            start: "2024-01-01T00:00:00Z",
+            process: "/usr/bin/curl",
+        }
+    }
+}
+
+impl ConnectionFixture<'_> {
+    pub fn json(&self) -> String {
+        format!(
+            r#"{{"id":"{}","chains":{:?},"rule":"{}","rulePayload":"{}","upload":{},"download":{},
+            "start":"{}","metadata":{{"network":"tcp","type":"HTTP","sourceIP":"192.168.1.2",
+            "destinationIP":"{}","sourcePort":"5000","destinationPort":"443","host":"{}","dnsMode":"normal",
+            "processPath":"{}"}}}}"#,
+            self.id, self.chains, self.rule, self.payload, self.upload, self.download, self.start, self.dst, self.host, self.process
+        )
+    }
+
+    pub fn parse(&self) -> Connection {
+        serde_json::from_str(&self.json()).unwrap()
+    }
+}
+
+/// A connection as `/connections` reports it, with the defaults of
+/// [`ConnectionFixture`].
+pub fn connection(id: &str, host: &str, dst: &str, chains: &[&str], upload: u64, download: u64) -> String {
+    ConnectionFixture { id, host, dst, chains, upload, download, ..Default::default() }.json()
+}
+
+/// A `/connections` snapshot of `connections`, with zero global totals.
+pub fn snapshot(connections: &[String]) -> ConnectionVec {
+    snapshot_with_totals(0, 0, connections)
+}
+
+pub fn snapshot_with_totals(upload_total: u64, download_total: u64, connections: &[String]) -> ConnectionVec {
+    format!(
+        r#"{{"downloadTotal":{},"uploadTotal":{},"connections":[{}]}}"#,
+        download_total,
+        upload_total,
+        connections.join(",")
+    )
+    .try_into()
+    .unwrap()
+}
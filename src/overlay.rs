@@ -0,0 +1,351 @@
+//! Merge a base profile with local overlays, so that hand-written rules
+//! and groups survive a subscription refresh.
+//!
+//! An overlay is a YAML file like:
+//!
+//! ```yaml
+//! general:
+//!   mixed-port: 7890
+//!   allow-lan: true
+//! prepend-rules:
+//!   - DOMAIN-SUFFIX,corp.example.com,DIRECT
+//! append-rules:
+//!   - GEOIP,CN,DIRECT
+//! proxies:
+//!   - {name: home, type: socks5, server: 192.168.1.2, port: 1080}
+//! proxy-groups:
+//!   - {name: Work, type: select, proxies: [home, DIRECT]}
+//! group-filters:
+//!   PROXY: {include: "JP|HK", exclude: "(?i)expire"}
+//! ```
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+use serde_yaml::{Mapping, Value};
+
+use crate::{profile::Profile, proxy::BUILTIN_PROXIES, rule::Rule};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case")]
+pub struct Overlay {
+    /// Where the overlay came from, used in conflict reports.
+    #[serde(skip)]
+    pub source: String,
+
+    /// General settings that replace the base ones key by key.
+    #[serde(default)]
+    pub general: Mapping,
+    #[serde(default)]
+    pub prepend_rules: Vec<String>,
+    #[serde(default)]
+    pub append_rules: Vec<String>,
+    /// Extra proxies. A proxy named like a base proxy replaces it.
+    #[serde(default)]
+    pub proxies: Vec<Mapping>,
+    /// Extra groups. A group named like a base group replaces it.
+    #[serde(default)]
+    pub proxy_groups: Vec<Mapping>,
+    /// Member filters keyed by group name.
+    #[serde(default)]
+    pub group_filters: BTreeMap<String, MemberFilter>,
+}
+
+/// Keep only the proxies of a group whose names match `include` and do
+/// not match `exclude`. Members that are groups or built-in policies
+/// are never filtered out.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct MemberFilter {
+    #[serde(default)]
+    pub include: Option<String>,
+    #[serde(default)]
+    pub exclude: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conflict {
+    /// Two overlays set a general setting to different values.
+    Setting { key: String, first: String, second: String },
+    /// An overlay proxy replaced one from the base or an earlier overlay.
+    Proxy { name: String, source: String },
+    /// An overlay group replaced one from the base or an earlier overlay.
+    Group { name: String, source: String },
+    /// A filter names a group that does not exist.
+    UnknownGroup { name: String, source: String },
+    /// A filter removed every member of a group.
+    EmptyGroup { name: String },
+    /// A group member is neither a proxy, a group nor built in.
+    UnknownMember { group: String, member: String },
+    /// A rule points at a policy that does not exist.
+    UnknownPolicy { rule: String },
+}
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Conflict::*;
+        match self {
+            Setting { key, first, second } => write!(f, "setting `{}` is set by both {} and {}", key, first, second),
+            Proxy { name, source } => write!(f, "proxy `{}` is replaced by {}", name, source),
+            Group { name, source } => write!(f, "group `{}` is replaced by {}", name, source),
+            UnknownGroup { name, source } => write!(f, "{} filters unknown group `{}`", source, name),
+            EmptyGroup { name } => write!(f, "group `{}` has no members left after filtering", name),
+            UnknownMember { group, member } => write!(f, "group `{}` refers to unknown member `{}`", group, member),
+            UnknownPolicy { rule } => write!(f, "rule `{}` refers to an unknown policy", rule),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum OverlayError {
+    InvalidRegex { group: String, error: regex::Error },
+}
+
+impl std::fmt::Display for OverlayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverlayError::InvalidRegex { group, error } => write!(f, "Invalid filter for group `{}`: {}", group, error),
+        }
+    }
+}
+
+impl std::error::Error for OverlayError {}
+
+impl TryFrom<String> for Overlay {
+    type Error = serde_yaml::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        serde_yaml::from_str(&value)
+    }
+}
+
+impl Overlay {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = std::fs::read_to_string(path.as_ref())?;
+        Ok(Self {
+            source: path.as_ref().display().to_string(),
+            ..Self::try_from(content)?
+        })
+    }
+}
+
+/// The merged profile together with everything that looked suspicious
+/// while merging.
+#[derive(Debug)]
+pub struct Merged {
+    pub profile: Profile,
+    pub conflicts: Vec<Conflict>,
+}
+
+fn entry_name(entry: &Mapping) -> Option<String> {
+    entry.get("name").and_then(Value::as_str).map(str::to_owned)
+}
+
+fn value_to_string(value: &Value) -> String {
+    serde_yaml::to_string(value).map(|s| s.trim().to_owned()).unwrap_or_default()
+}
+
+// Add `entries` to `list`, replacing entries with the same name in place.
+// Returns the names that were replaced.
+fn upsert(list: &mut Vec<Mapping>, entries: &[Mapping]) -> Vec<String> {
+    let mut replaced = Vec::new();
+    for entry in entries {
+        let name = entry_name(entry);
+        match list.iter().position(|e| name.is_some() && entry_name(e) == name) {
+            Some(i) => {
+                list[i] = entry.clone();
+                replaced.extend(name);
+            }
+            None => list.push(entry.clone()),
+        }
+    }
+    replaced
+}
+
+fn compile(group: &str, pattern: &Option<String>) -> Result<Option<Regex>, OverlayError> {
+    pattern
+        .as_ref()
+        .map(|p| Regex::new(p))
+        .transpose()
+        .map_err(|error| OverlayError::InvalidRegex { group: group.to_owned(), error })
+}
+
+/// Apply `overlays` to `base` in order.
+///
+/// Prepended rules of all overlays come first, in overlay order, then the
+/// base rules, then the appended rules. Later overlays win on general
+/// settings, proxies and groups; each such case is reported as a
+/// conflict rather than an error.
+pub fn merge(base: &Profile, overlays: &[Overlay]) -> Result<Merged, OverlayError> {
+    let mut profile = base.clone();
+    let mut conflicts = Vec::new();
+    let mut set_by: HashMap<Value, (String, Value)> = HashMap::new();
+
+    for overlay in overlays {
+        for (key, value) in overlay.general.iter() {
+            if let Some((source, previous)) = set_by.get(key) {
+                if previous != value {
+                    conflicts.push(Conflict::Setting {
+                        key: value_to_string(key),
+                        first: source.clone(),
+                        second: overlay.source.clone(),
+                    });
+                }
+            }
+            set_by.insert(key.clone(), (overlay.source.clone(), value.clone()));
+            profile.general.insert(key.clone(), value.clone());
+        }
+
+        for name in upsert(&mut profile.proxies, &overlay.proxies) {
+            conflicts.push(Conflict::Proxy { name, source: overlay.source.clone() });
+        }
+        for name in upsert(&mut profile.proxy_groups, &overlay.proxy_groups) {
+            conflicts.push(Conflict::Group { name, source: overlay.source.clone() });
+        }
+    }
+
+    let proxies: HashSet<String> = profile.proxy_names().map(str::to_owned).collect();
+    for overlay in overlays {
+        for (group_name, filter) in overlay.group_filters.iter() {
+            let include = compile(group_name, &filter.include)?;
+            let exclude = compile(group_name, &filter.exclude)?;
+
+            let group = match profile.proxy_groups.iter_mut().find(|g| entry_name(g).as_ref() == Some(group_name)) {
+                Some(group) => group,
+                None => {
+                    conflicts.push(Conflict::UnknownGroup { name: group_name.clone(), source: overlay.source.clone() });
+                    continue;
+                }
+            };
+
+            if let Some(Value::Sequence(members)) = group.get_mut("proxies") {
+                members.retain(|member| match member.as_str() {
+                    Some(name) if proxies.contains(name) => {
+                        include.as_ref().is_none_or(|re| re.is_match(name))
+                            && !exclude.as_ref().is_some_and(|re| re.is_match(name))
+                    }
+                    _ => true,
+                });
+                if members.is_empty() {
+                    conflicts.push(Conflict::EmptyGroup { name: group_name.clone() });
+                }
+            }
+        }
+    }
+
+    let prepend = overlays.iter().flat_map(|o| o.prepend_rules.iter().cloned());
+    let append = overlays.iter().flat_map(|o| o.append_rules.iter().cloned());
+    profile.rules = prepend.chain(base.rules.iter().cloned()).chain(append).collect();
+
+    conflicts.extend(check_references(&profile));
+    Ok(Merged { profile, conflicts })
+}
+
+fn check_references(profile: &Profile) -> Vec<Conflict> {
+    let known: HashSet<&str> = profile
+        .proxy_names()
+        .chain(profile.group_names())
+        .chain(BUILTIN_PROXIES.iter().copied())
+        .collect();
+
+    let mut conflicts = Vec::new();
+    for group in profile.proxy_groups.iter() {
+        let group_name = entry_name(group).unwrap_or_default();
+        if let Some(Value::Sequence(members)) = group.get("proxies") {
+            for member in members.iter().filter_map(Value::as_str) {
+                if !known.contains(member) {
+                    conflicts.push(Conflict::UnknownMember { group: group_name.clone(), member: member.to_owned() });
+                }
+            }
+        }
+    }
+
+    for line in profile.rules.iter() {
+        if let Some(rule) = Rule::from_line(line) {
+            if !known.contains(rule.proxy.as_str()) {
+                conflicts.push(Conflict::UnknownPolicy { rule: line.clone() });
+            }
+        }
+    }
+    conflicts
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BASE: &str = r#"
+mixed-port: 7890
+mode: rule
+proxies:
+  - {name: JP 01, type: ss, server: jp1.example.com, port: 1, cipher: aes-128-gcm, password: x}
+  - {name: HK 01, type: ss, server: hk1.example.com, port: 1, cipher: aes-128-gcm, password: x}
+  - {name: Expire 2026-12-31, type: ss, server: x.example.com, port: 1, cipher: aes-128-gcm, password: x}
+proxy-groups:
+  - {name: PROXY, type: select, proxies: [JP 01, HK 01, Expire 2026-12-31, DIRECT]}
+rules:
+  - MATCH,PROXY
+"#;
+
+    fn overlay(source: &str, yaml: &str) -> Overlay {
+        Overlay { source: source.to_owned(), ..Overlay::try_from(yaml.to_owned()).unwrap() }
+    }
+
+    #[test]
+    fn test_merge_overlays() {
+        let base = Profile::try_from(BASE.to_owned()).unwrap();
+        let local = overlay("local.yaml", r#"
+general: {allow-lan: true, mixed-port: 7891}
+prepend-rules:
+  - DOMAIN-SUFFIX,corp.example.com,Work
+append-rules:
+  - GEOIP,CN,DIRECT
+proxies:
+  - {name: home, type: socks5, server: 192.168.1.2, port: 1080}
+proxy-groups:
+  - {name: Work, type: select, proxies: [home, DIRECT]}
+group-filters:
+  PROXY: {include: "JP|HK|Expire", exclude: "(?i)expire"}
+"#);
+
+        let merged = merge(&base, &[local]).unwrap();
+        let profile = merged.profile;
+
+        assert!(merged.conflicts.is_empty(), "{:?}", merged.conflicts);
+        assert_eq!(profile.setting("mixed-port").and_then(Value::as_u64), Some(7891));
+        assert_eq!(profile.setting("allow-lan").and_then(Value::as_bool), Some(true));
+        assert_eq!(profile.rules, vec!["DOMAIN-SUFFIX,corp.example.com,Work", "MATCH,PROXY", "GEOIP,CN,DIRECT"]);
+        assert_eq!(profile.group_names().collect::<Vec<_>>(), vec!["PROXY", "Work"]);
+
+        let members: Vec<_> = profile.proxy_groups[0]["proxies"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .filter_map(Value::as_str)
+            .collect();
+        assert_eq!(members, vec!["JP 01", "HK 01", "DIRECT"]);
+    }
+
+    #[test]
+    fn test_merge_conflicts() {
+        let base = Profile::try_from(BASE.to_owned()).unwrap();
+        let first = overlay("a.yaml", "general: {mode: global}\nproxies: [{name: JP 01, type: direct}]\n");
+        let second = overlay("b.yaml", "general: {mode: direct}\nprepend-rules:\n  - DOMAIN,a.com,Nowhere\ngroup-filters: {Missing: {include: x}}\n");
+
+        let conflicts = merge(&base, &[first, second]).unwrap().conflicts;
+
+        assert!(conflicts.contains(&Conflict::Setting { key: "mode".to_owned(), first: "a.yaml".to_owned(), second: "b.yaml".to_owned() }));
+        assert!(conflicts.contains(&Conflict::Proxy { name: "JP 01".to_owned(), source: "a.yaml".to_owned() }));
+        assert!(conflicts.contains(&Conflict::UnknownGroup { name: "Missing".to_owned(), source: "b.yaml".to_owned() }));
+        assert!(conflicts.contains(&Conflict::UnknownPolicy { rule: "DOMAIN,a.com,Nowhere".to_owned() }));
+    }
+
+    #[test]
+    fn test_invalid_filter() {
+        let base = Profile::try_from(BASE.to_owned()).unwrap();
+        let bad = overlay("bad.yaml", "group-filters: {PROXY: {include: \"(\"}}\n");
+
+        assert!(merge(&base, &[bad]).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+use serde_yaml::{Mapping, Value};
+
+use crate::rule::{
+    kind::{RuleParseError, TypedRule},
+    Rule, RuleList,
+};
+
+/// A clash profile as written on disk, e.g. `clash-profile`.
+///
+/// Proxies and groups are kept as raw mappings so that fields this crate
+/// does not know about survive a round trip.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Profile {
+    #[serde(flatten)]
+    pub general: Mapping,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub proxies: Vec<Mapping>,
+    #[serde(rename(serialize = "proxy-groups", deserialize = "proxy-groups"), default, skip_serializing_if = "Vec::is_empty")]
+    pub proxy_groups: Vec<Mapping>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<String>,
+}
+
+impl TryFrom<String> for Profile {
+    type Error = serde_yaml::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        serde_yaml::from_str(&value)
+    }
+}
+
+fn entry_name(entry: &Mapping) -> Option<&str> {
+    entry.get("name").and_then(Value::as_str)
+}
+
+impl Profile {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(Self::try_from(content)?)
+    }
+
+    pub fn to_yaml(&self) -> Result<String, serde_yaml::Error> {
+        serde_yaml::to_string(self)
+    }
+
+    /// Write the profile to `path` atomically, so that the core never
+    /// loads a half-written file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn std::error::Error>> {
+        crate::file::write_atomic(path, self.to_yaml()?)?;
+        Ok(())
+    }
+
+    /// Look up a general setting such as `mixed-port`.
+    pub fn setting(&self, key: &str) -> Option<&Value> {
+        self.general.get(key)
+    }
+
+    pub fn proxy_names(&self) -> impl Iterator<Item = &str> {
+        self.proxies.iter().filter_map(entry_name)
+    }
+
+    pub fn group_names(&self) -> impl Iterator<Item = &str> {
+        self.proxy_groups.iter().filter_map(entry_name)
+    }
+
+    /// Parse the rule lines, skipping any that are malformed.
+    pub fn rule_list(&self) -> RuleList {
+        self.rules
+            .iter()
+            .filter_map(|line| Rule::from_line(line))
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    /// The rules with their kinds and options parsed, by line.
+    pub fn typed_rules(&self) -> Vec<Result<TypedRule, RuleParseError>> {
+        self.rules.iter().map(|line| TypedRule::parse_line(line)).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Profile;
+
+    const PROFILE: &str = r#"
+mixed-port: 7890
+mode: Rule
+proxies:
+  - name: "node-jp"
+    type: ss
+    server: jp.example.com
+    port: 443
+proxy-groups:
+  - name: Proxy
+    type: select
+    proxies: [node-jp, DIRECT]
+rules:
+  - DOMAIN-SUFFIX,google.com,Proxy
+  - MATCH,DIRECT
+"#;
+
+    #[test]
+    fn test_parse_profile() {
+        let profile = Profile::try_from(PROFILE.to_owned()).unwrap();
+
+        assert_eq!(profile.setting("mixed-port").and_then(|v| v.as_u64()), Some(7890));
+        assert_eq!(profile.proxy_names().collect::<Vec<_>>(), vec!["node-jp"]);
+        assert_eq!(profile.group_names().collect::<Vec<_>>(), vec!["Proxy"]);
+        assert_eq!(profile.rule_list().iter().count(), 2);
+        assert!(profile.setting("rules").is_none());
+    }
+
+    #[test]
+    fn test_profile_round_trip() {
+        let profile = Profile::try_from(PROFILE.to_owned()).unwrap();
+        let again = Profile::try_from(profile.to_yaml().unwrap()).unwrap();
+
+        assert_eq!(again.rules, profile.rules);
+        assert_eq!(again.proxies, profile.proxies);
+        assert_eq!(again.general, profile.general);
+    }
+}
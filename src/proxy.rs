@@ -5,7 +5,7 @@ use serde::{Serialize, Deserialize};
 use serde_json::{Value, Map};
 use urlencoding::encode;
 
-use crate::{ClashRequest, get_with_status_code_request};
+use crate::{ClashRequest, ClashRequestBuilder, get_with_status_code_request};
 
 use super::{get_request, put_request};
 
@@ -56,10 +56,53 @@ pub struct ProxyList {
     proxies: Map<String, Value>,
 }
 
+/// Names the core provides without them being declared in a profile.
+pub const BUILTIN_PROXIES: [&str; 6] = ["DIRECT", "REJECT", "GLOBAL", "PASS", "REJECT-DROP", "COMPATIBLE"];
+
+/// Proxy types the controller reports for proxy groups.
+pub const GROUP_TYPES: [&str; 5] = ["Selector", "URLTest", "Fallback", "LoadBalance", "Relay"];
+
 impl ProxyList {
     pub fn iter(&self) -> serde_json::map::Iter {
         self.proxies.iter()
     }
+
+    /// The info of a proxy or group by name.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.proxies.get(name)
+    }
+
+    /// The delay of the latest test in the history of a proxy, `Some(0)`
+    /// if that test failed.
+    pub fn last_delay(&self, name: &str) -> Option<u64> {
+        self.get(name)?["history"].as_array()?.last()?["delay"].as_u64()
+    }
+
+    fn is_group(info: &Value) -> bool {
+        info["type"].as_str().is_some_and(|t| GROUP_TYPES.contains(&t))
+    }
+
+    /// Names of the proxy groups, excluding the built-in `GLOBAL`.
+    pub fn group_names(&self) -> impl Iterator<Item = &str> {
+        self.iter()
+            .filter(|(name, info)| Self::is_group(info) && !BUILTIN_PROXIES.contains(&name.as_str()))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// The member currently selected by each `Selector` group.
+    pub fn selections(&self) -> std::collections::BTreeMap<String, String> {
+        self.iter()
+            .filter(|(_, info)| info["type"] == "Selector")
+            .filter_map(|(name, info)| Some((name.clone(), info["now"].as_str()?.to_owned())))
+            .collect()
+    }
+
+    /// Names of the proxies that are neither groups nor built-in.
+    pub fn proxy_names(&self) -> impl Iterator<Item = &str> {
+        self.iter()
+            .filter(|(name, info)| !Self::is_group(info) && !BUILTIN_PROXIES.contains(&name.as_str()))
+            .map(|(name, _)| name.as_str())
+    }
 }
 
 // impl std::convert::From<String> for ProxyList {
@@ -183,6 +226,19 @@ impl ClashRequest for ClashProxyChange {
     }
 }
 
+/// Select `new_proxy` in `group`, then close the connections still going
+/// through its previous selection, returning the result for each.
+pub async fn change_and_close_stale(
+    client: ClashRequestBuilder,
+    group: &str,
+    new_proxy: &str,
+) -> Result<Vec<(String, Result<(), Box<dyn Error>>)>, Box<dyn Error>> {
+    client.clone().proxies().get(group).change(new_proxy).send().await?;
+    let connections = client.clone().connections().send().await?;
+    let stale = connections.stale(group, new_proxy);
+    Ok(client.connections().close_ids(stale.iter().map(|c| &c.id)).await)
+}
+
 pub struct ClashProxyDelay {
     ip: String,
     port: u16,
@@ -241,7 +297,7 @@ impl ClashRequest for ClashProxyDelay {
     }
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
 pub struct ProxyDelay {
     pub delay: u64,
 }
@@ -401,5 +457,29 @@ mod test {
             .await
             .unwrap();
     }
+
+    #[tokio::test]
+    async fn test_change_and_close_stale() {
+        use crate::mock::connection;
+        use crate::mock::MockController;
+
+        let connections = [
+            connection("old", "example.com", "1.1.1.1", &["node-us", "Proxy"], 0, 0),
+            connection("new", "example.com", "1.1.1.1", &["node-jp", "Proxy"], 0, 0),
+            connection("direct", "example.com", "1.1.1.1", &["DIRECT"], 0, 0),
+        ];
+        let body = format!(r#"{{"downloadTotal":0,"uploadTotal":0,"connections":[{}]}}"#, connections.join(","));
+        let mock = MockController::new();
+        mock.route("PUT", "proxies/Proxy", 204, "")
+            .route("GET", "connections", 200, &body)
+            .route("DELETE", "connections/old", 204, "");
+        let client = mock.start().await;
+
+        let results = super::change_and_close_stale(client, "Proxy", "node-jp").await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, "old");
+        assert!(results[0].1.is_ok());
+        assert!(mock.requests()[0].body.contains("node-jp"));
+    }
 }
 
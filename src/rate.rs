@@ -0,0 +1,159 @@
+//! Throughput from the deltas between two `/connections` snapshots.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, SystemTime},
+};
+
+use clap::ValueEnum;
+use serde::{Serialize, Deserialize};
+
+pub use crate::connection::Rate;
+use crate::connection::{Connection, ConnectionVec};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConnectionRate {
+    pub id: String,
+    /// The host, or the destination IP when there is none.
+    pub host: String,
+    pub process: String,
+    pub chains: Vec<String>,
+    pub rate: Rate,
+    /// Bytes transferred since the connection started.
+    pub upload: u64,
+    pub download: u64,
+    #[serde(skip)]
+    pub start: Option<SystemTime>,
+}
+
+impl ConnectionRate {
+    pub fn age(&self, now: SystemTime) -> Option<Duration> {
+        now.duration_since(self.start?).ok()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HostRate {
+    pub host: String,
+    pub connections: usize,
+    pub rate: Rate,
+    pub upload: u64,
+    pub download: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    /// Current throughput, highest first
+    Rate,
+    /// Bytes transferred, highest first
+    Total,
+    /// Time since the connection started, oldest first
+    Age,
+    /// Host name, alphabetically
+    Host,
+}
+
+/// Rates of the connections present in the later of two snapshots.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RateSnapshot {
+    pub elapsed: Duration,
+    pub connections: Vec<ConnectionRate>,
+    pub total: Rate,
+}
+
+impl RateSnapshot {
+    /// Rates over `elapsed` between `prev` and `next`. Connections new in
+    /// `next` are assumed to have started after `prev` was taken.
+    pub fn between(prev: &ConnectionVec, next: &ConnectionVec, elapsed: Duration) -> Self {
+        let before: HashMap<&str, &Connection> = prev.connections.iter().map(|c| (c.id.as_str(), c)).collect();
+
+        let connections: Vec<ConnectionRate> = next
+            .connections
+            .iter()
+            .map(|c| {
+                let (up, down) = before.get(c.id.as_str()).map_or((0, 0), |p| (p.upload, p.download));
+                ConnectionRate {
+                    id: c.id.clone(),
+                    host: c.metadata.destination().to_owned(),
+                    process: c.metadata.process_name().to_owned(),
+                    chains: c.chains.clone(),
+                    rate: Rate::new(c.upload.saturating_sub(up) as u64, c.download.saturating_sub(down) as u64, elapsed),
+                    upload: c.upload as u64,
+                    download: c.download as u64,
+                    start: c.start,
+                }
+            })
+            .collect();
+
+        let total = connections.iter().fold(Rate::default(), |sum, c| sum + c.rate);
+        Self { elapsed, connections, total }
+    }
+
+    /// Rates summed per host, highest rate first.
+    pub fn by_host(&self) -> Vec<HostRate> {
+        let mut hosts: BTreeMap<&str, HostRate> = BTreeMap::new();
+        for c in self.connections.iter() {
+            let host = hosts.entry(&c.host).or_insert_with(|| HostRate {
+                host: c.host.clone(),
+                connections: 0,
+                rate: Rate::default(),
+                upload: 0,
+                download: 0,
+            });
+            host.connections += 1;
+            host.rate = host.rate + c.rate;
+            host.upload += c.upload;
+            host.download += c.download;
+        }
+
+        let mut hosts: Vec<HostRate> = hosts.into_values().collect();
+        hosts.sort_by(|a, b| b.rate.total().total_cmp(&a.rate.total()));
+        hosts
+    }
+
+    pub fn sort(&mut self, key: SortKey) {
+        let connections = &mut self.connections;
+        match key {
+            SortKey::Rate => connections.sort_by(|a, b| b.rate.total().total_cmp(&a.rate.total())),
+            SortKey::Total => connections.sort_by_key(|c| std::cmp::Reverse(c.upload + c.download)),
+            // Unknown start times sort last.
+            SortKey::Age => connections.sort_by_key(|c| c.start.map_or((1, SystemTime::UNIX_EPOCH), |s| (0, s))),
+            SortKey::Host => connections.sort_by(|a, b| a.host.cmp(&b.host)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{connection, snapshot};
+
+    #[test]
+    fn test_rates() {
+        let prev = snapshot(&[
+            connection("a", "example.com", "1.1.1.1", &["DIRECT"], 1000, 4000),
+            connection("b", "example.com", "1.1.1.1", &["DIRECT"], 0, 0),
+        ]);
+        let next = snapshot(&[
+            connection("a", "example.com", "1.1.1.1", &["DIRECT"], 3000, 8000),
+            connection("b", "example.com", "1.1.1.1", &["DIRECT"], 500, 1000),
+            connection("c", "", "8.8.8.8", &["DIRECT"], 100, 0),
+        ]);
+
+        let mut rates = RateSnapshot::between(&prev, &next, Duration::from_secs(2));
+        assert_eq!((rates.total.upload, rates.total.download), (1300.0, 2500.0));
+
+        rates.sort(SortKey::Rate);
+        let order: Vec<&str> = rates.connections.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(order, vec!["a", "b", "c"]);
+
+        let hosts = rates.by_host();
+        assert_eq!((hosts[0].host.as_str(), hosts[0].connections, hosts[0].rate.upload), ("example.com", 2, 1250.0));
+        assert_eq!((hosts[0].rate.download, hosts[0].download), (2500.0, 9000));
+        assert_eq!((hosts[1].host.as_str(), hosts[1].upload), ("8.8.8.8", 100));
+
+        rates.sort(SortKey::Host);
+        assert_eq!(rates.connections[0].host, "8.8.8.8");
+        assert_eq!(Rate::new(10, 10, Duration::ZERO), Rate::default());
+    }
+}
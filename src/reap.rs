@@ -0,0 +1,269 @@
+//! Close connections that are idle, too old, too large or match a filter,
+//! by polling `/connections`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    connection::{Connection, ConnectionVec},
+    filter::Filter,
+    ClashRequest, ClashRequestBuilder,
+};
+
+/// A reason to close a connection.
+#[derive(Debug, Clone)]
+pub enum Policy {
+    /// No bytes transferred for this long.
+    Idle(Duration),
+    /// Open for longer than this.
+    MaxAge(Duration),
+    /// More than this many bytes transferred in total.
+    MaxBytes(u64),
+    /// Matches a filter expression.
+    Filter { source: String, filter: Filter },
+}
+
+impl Policy {
+    pub fn filter(source: &str) -> Result<Self, crate::filter::FilterError> {
+        Ok(Policy::Filter { source: source.to_owned(), filter: Filter::parse(source)? })
+    }
+}
+
+impl std::fmt::Display for Policy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Policy::Idle(d) => write!(f, "idle for {}s", d.as_secs()),
+            Policy::MaxAge(d) => write!(f, "older than {}s", d.as_secs()),
+            Policy::MaxBytes(b) => write!(f, "more than {} bytes", b),
+            Policy::Filter { source, .. } => write!(f, "filter `{}`", source),
+        }
+    }
+}
+
+/// A connection the reaper closed, or would have closed in a dry run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Reaped {
+    /// Unix time in seconds.
+    pub time: u64,
+    pub id: String,
+    pub host: String,
+    pub process: String,
+    pub chains: Vec<String>,
+    pub upload: u64,
+    pub download: u64,
+    /// The first policy the connection violated.
+    pub policy: String,
+    pub dry_run: bool,
+    /// Why closing failed, if it did.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub error: Option<String>,
+}
+
+/// Tracks byte growth across snapshots to find the connections to reap.
+pub struct Reaper {
+    policies: Vec<Policy>,
+    /// Bytes of each open connection and when they last changed.
+    activity: HashMap<String, (u64, SystemTime)>,
+    /// Connections closed or listed in a dry run, so each is reported
+    /// only once. Failed closes stay out to be retried.
+    reaped: HashSet<String>,
+}
+
+impl Reaper {
+    pub fn new(policies: Vec<Policy>) -> Self {
+        Self { policies, activity: HashMap::new(), reaped: HashSet::new() }
+    }
+
+    fn violation(&self, connection: &Connection, now: SystemTime) -> Option<&Policy> {
+        let bytes = (connection.upload + connection.download) as u64;
+        self.policies.iter().find(|policy| match policy {
+            Policy::Idle(idle) => self
+                .activity
+                .get(&connection.id)
+                .and_then(|(_, changed)| now.duration_since(*changed).ok())
+                .is_some_and(|d| d >= *idle),
+            Policy::MaxAge(max) => connection.age_at(now).is_some_and(|age| age > *max),
+            Policy::MaxBytes(max) => bytes > *max,
+            Policy::Filter { filter, .. } => filter.matches(connection, now),
+        })
+    }
+
+    /// Take a snapshot taken at `now`, returning the connections to reap
+    /// that were not closed, or listed in a dry run, before. A connection
+    /// is idle from the first snapshot in which its byte count stopped
+    /// growing.
+    pub fn check(&mut self, snapshot: &ConnectionVec, now: SystemTime, dry_run: bool) -> Vec<Reaped> {
+        let mut activity = HashMap::with_capacity(snapshot.connections.len());
+        for connection in snapshot.connections.iter() {
+            let bytes = (connection.upload + connection.download) as u64;
+            let changed = match self.activity.get(&connection.id) {
+                Some((before, changed)) if *before == bytes => *changed,
+                _ => now,
+            };
+            activity.insert(connection.id.clone(), (bytes, changed));
+        }
+        self.activity = activity;
+        self.reaped.retain(|id| self.activity.contains_key(id));
+
+        let time = now.duration_since(SystemTime::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let mut reaped = Vec::new();
+        for connection in snapshot.connections.iter() {
+            if self.reaped.contains(&connection.id) {
+                continue;
+            }
+            if let Some(policy) = self.violation(connection, now) {
+                reaped.push(Reaped {
+                    time,
+                    id: connection.id.clone(),
+                    host: connection.metadata.destination().to_owned(),
+                    process: connection.metadata.process_name().to_owned(),
+                    chains: connection.chains.clone(),
+                    upload: connection.upload as u64,
+                    download: connection.download as u64,
+                    policy: policy.to_string(),
+                    dry_run,
+                    error: None,
+                });
+            }
+        }
+        if dry_run {
+            self.reaped.extend(reaped.iter().map(|r| r.id.clone()));
+        }
+        reaped
+    }
+
+    /// Record that `id` was closed, so later snapshots skip it.
+    pub fn closed(&mut self, id: &str) {
+        self.reaped.insert(id.to_owned());
+    }
+
+    /// Poll `/connections` every `interval` until interrupted, closing the
+    /// connections to reap unless `dry_run`, and passing each to `on_reap`.
+    /// Failed polls go to `on_error` and are retried on the next tick.
+    pub async fn run<F, E>(
+        mut self,
+        client: ClashRequestBuilder,
+        interval: Duration,
+        dry_run: bool,
+        mut on_reap: F,
+        mut on_error: E,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: FnMut(&Reaped),
+        E: FnMut(&dyn std::error::Error),
+    {
+        loop {
+            let snapshot = match client.clone().connections().send().await {
+                Ok(snapshot) => snapshot,
+                Err(e) => {
+                    on_error(e.as_ref());
+                    tokio::time::sleep(interval).await;
+                    continue;
+                }
+            };
+            let mut reaped = self.check(&snapshot, SystemTime::now(), dry_run);
+
+            if !dry_run && !reaped.is_empty() {
+                let results = client.clone().connections().close_ids(reaped.iter().map(|r| &r.id)).await;
+                for (r, (_, result)) in reaped.iter_mut().zip(results) {
+                    match result {
+                        Ok(_) => self.closed(&r.id),
+                        Err(e) => r.error = Some(e.to_string()),
+                    }
+                }
+            }
+            for r in reaped.iter() {
+                on_reap(r);
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        mock::{connection, snapshot, MockController},
+    };
+
+    #[test]
+    fn test_check() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1704067200);
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut reaper = Reaper::new(vec![
+            Policy::Idle(Duration::from_secs(60)),
+            Policy::MaxBytes(1000),
+            Policy::filter(r#"chains contains "dead""#).unwrap(),
+        ]);
+
+        let idle = |up| connection("idle", "example.com", "1.1.1.1", &["DIRECT"], up, 0);
+        let busy = |down| connection("busy", "example.com", "1.1.1.1", &["DIRECT"], 0, down);
+        let dead = connection("dead", "", "8.8.8.8", &["dead", "Proxy"], 0, 0);
+
+        let reaped = reaper.check(&snapshot(&[idle(10), busy(10), dead.clone()]), at(0), false);
+        let ids: Vec<(&str, &str)> = reaped.iter().map(|r| (r.id.as_str(), r.policy.as_str())).collect();
+        assert_eq!(ids, vec![("dead", "filter `chains contains \"dead\"`")]);
+
+        // Not closed yet, so it is reported again.
+        let reaped = reaper.check(&snapshot(&[idle(10), busy(20), dead.clone()]), at(30), false);
+        assert_eq!(reaped.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), vec!["dead"]);
+        reaper.closed("dead");
+        let reaped = reaper.check(&snapshot(&[idle(10), busy(2000), dead]), at(61), false);
+        let ids: Vec<(&str, &str)> = reaped.iter().map(|r| (r.id.as_str(), r.policy.as_str())).collect();
+        assert_eq!(ids, vec![("idle", "idle for 60s"), ("busy", "more than 1000 bytes")]);
+
+        let mut reaper = Reaper::new(vec![Policy::MaxAge(Duration::from_secs(3600))]);
+        assert!(reaper.check(&snapshot(&[idle(0)]), at(3600), true).is_empty());
+        assert!(reaper.check(&snapshot(&[idle(0)]), at(3601), true)[0].dry_run);
+    }
+
+    #[tokio::test]
+    async fn test_run_closes() {
+        let body = format!(
+            r#"{{"downloadTotal":0,"uploadTotal":0,"connections":[{}]}}"#,
+            connection("a", "example.com", "1.1.1.1", &["DIRECT"], 0, 2000)
+        );
+        let mock = MockController::new();
+        mock.route("GET", "connections", 200, &body).route("DELETE", "connections/a", 204, "");
+        let client = mock.start().await;
+
+        let mut reaped = Vec::new();
+        let reaper = Reaper::new(vec![Policy::MaxBytes(1000)]);
+        let run = reaper.run(client, Duration::from_millis(10), false, |r| reaped.push(r.clone()), |e| panic!("{}", e));
+        let _ = tokio::time::timeout(Duration::from_secs(1), run).await;
+
+        assert_eq!(reaped.len(), 1);
+        assert_eq!(reaped[0].error, None);
+        assert!(mock.requests().iter().any(|r| r.method == "DELETE" && r.path == "connections/a"));
+    }
+
+    #[tokio::test]
+    async fn test_run_retries() {
+        let mock = MockController::new();
+        let client = mock.start().await;
+        let mut errors = 0;
+        let run = Reaper::new(vec![Policy::MaxBytes(1000)]).run(client, Duration::from_millis(10), false, |_| {}, |_| errors += 1);
+        assert!(tokio::time::timeout(Duration::from_secs(1), run).await.is_err());
+        assert!(errors > 1);
+
+        let body = format!(
+            r#"{{"downloadTotal":0,"uploadTotal":0,"connections":[{}]}}"#,
+            connection("a", "example.com", "1.1.1.1", &["DIRECT"], 0, 2000)
+        );
+        let mock = MockController::new();
+        mock.route("GET", "connections", 200, &body).route("DELETE", "connections/a", 500, "");
+        let client = mock.start().await;
+
+        let mut reaped = Vec::new();
+        let run = Reaper::new(vec![Policy::MaxBytes(1000)]).run(client, Duration::from_millis(10), false, |r| reaped.push(r.clone()), |_| {});
+        let _ = tokio::time::timeout(Duration::from_secs(1), run).await;
+
+        assert!(reaped.len() > 1);
+        assert!(reaped.iter().all(|r| r.error.is_some()));
+    }
+}
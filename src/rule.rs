@@ -1,7 +1,10 @@
+pub mod kind;
+
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
 
 use super::{ClashRequest, ClashRequestBuilder};
+use kind::{split_top_level, RuleKind, RuleParseError, TypedRule};
 
 pub struct ClashRule {
     ip: String,
@@ -57,12 +60,12 @@ impl ClashRequest for ClashRule {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RuleList {
-    rules: Vec<Rule>,
+    pub rules: Vec<Rule>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Rule {
     pub r#type: String,
     pub payload: String,
@@ -87,6 +90,84 @@ impl RuleList {
     pub fn iter(&self) -> impl Iterator<Item = &Rule> {
         self.rules.iter()
     }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Rule> {
+        self.rules.get(index)
+    }
+
+    /// The rules with their kinds parsed.
+    pub fn typed(&self) -> impl Iterator<Item = Result<TypedRule, RuleParseError>> + '_ {
+        self.rules.iter().map(TypedRule::try_from)
+    }
+}
+
+impl From<Vec<Rule>> for RuleList {
+    fn from(rules: Vec<Rule>) -> Self {
+        Self { rules }
+    }
+}
+
+impl Rule {
+    /// Parse a rule line of a profile, e.g. `DOMAIN-SUFFIX,google.com,Proxy`.
+    /// Trailing options such as `no-resolve` are dropped, since the
+    /// controller does not report them either.
+    pub fn from_line(line: &str) -> Option<Self> {
+        let fields = split_top_level(line).ok()?;
+        match fields[..] {
+            [r#type, proxy] if !r#type.is_empty() => Some(Self {
+                r#type: r#type.to_owned(),
+                payload: "".to_owned(),
+                proxy: proxy.to_owned(),
+            }),
+            [r#type, payload, proxy, ..] if !r#type.is_empty() => Some(Self {
+                r#type: r#type.to_owned(),
+                payload: payload.to_owned(),
+                proxy: proxy.to_owned(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Parse the type and payload of the rule.
+    pub fn kind(&self) -> Result<RuleKind, RuleParseError> {
+        RuleKind::parse(&self.r#type, &self.payload, &[])
+    }
+
+    /// The rule in the line syntax of a profile, keeping unparsable rules
+    /// as they are.
+    pub fn to_line(&self) -> String {
+        match TypedRule::try_from(self) {
+            Ok(rule) => rule.to_line(),
+            Err(_) if self.payload.is_empty() => format!("{},{}", self.r#type, self.proxy),
+            Err(_) => format!("{},{},{}", self.r#type, self.payload, self.proxy),
+        }
+    }
+
+    /// The rule type in a form shared by profiles and the controller.
+    /// Profiles write `DOMAIN-SUFFIX` while the controller reports
+    /// `DomainSuffix`; both become `DOMAINSUFFIX`.
+    pub fn normalized_type(&self) -> String {
+        let t: String = self.r#type
+            .chars()
+            .filter(|c| *c != '-' && *c != '_')
+            .collect::<String>()
+            .to_uppercase();
+
+        match t.as_str() {
+            "IPCIDR6" => "IPCIDR".to_owned(),
+            "SRCIPCIDR6" => "SRCIPCIDR".to_owned(),
+            "PROCESSNAME" => "PROCESS".to_owned(),
+            _ => t,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -100,4 +181,32 @@ mod tests {
         let rule_list = req.await.unwrap();
         println!("{:?}", rule_list);
     }
+
+    #[test]
+    fn test_rule_from_line() {
+        let rule = Rule::from_line("IP-CIDR,10.0.0.0/8,DIRECT,no-resolve").unwrap();
+        assert_eq!(rule.payload, "10.0.0.0/8");
+        assert_eq!(rule.proxy, "DIRECT");
+
+        let rule = Rule::from_line("MATCH,Proxy").unwrap();
+        assert_eq!(rule.payload, "");
+        assert_eq!(rule.proxy, "Proxy");
+
+        assert!(Rule::from_line("MATCH").is_none());
+
+        let rule = Rule::from_line("AND,((DOMAIN,a.com),(NETWORK,UDP)),REJECT").unwrap();
+        assert_eq!(rule.payload, "((DOMAIN,a.com),(NETWORK,UDP))");
+        assert_eq!(rule.to_line(), "AND,((DOMAIN,a.com),(NETWORK,UDP)),REJECT");
+    }
+
+    #[test]
+    fn test_normalized_type() {
+        let profile = Rule::from_line("DOMAIN-SUFFIX,google.com,Proxy").unwrap();
+        let running = Rule {
+            r#type: "DomainSuffix".to_owned(),
+            payload: "google.com".to_owned(),
+            proxy: "Proxy".to_owned(),
+        };
+        assert_eq!(profile.normalized_type(), running.normalized_type());
+    }
 }
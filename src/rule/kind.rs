@@ -0,0 +1,552 @@
+//! A typed model of the rules Clash and Meta understand, convertible both
+//! ways between the controller's JSON (`{"type":"DomainSuffix", ...}`) and
+//! the profile's line syntax (`DOMAIN-SUFFIX,google.com,Proxy`).
+
+use std::{fmt, str::FromStr};
+
+use ipnet::IpNet;
+
+use super::Rule;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleParseError {
+    /// The line lacks a type, payload or policy.
+    MissingField(String),
+    InvalidPayload { r#type: String, payload: String },
+    /// Parentheses of a logical rule do not balance.
+    Unbalanced(String),
+}
+
+impl fmt::Display for RuleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use RuleParseError::*;
+        match self {
+            MissingField(line) => write!(f, "Missing field in rule: {}", line),
+            InvalidPayload { r#type, payload } => write!(f, "Invalid payload for {}: {}", r#type, payload),
+            Unbalanced(s) => write!(f, "Unbalanced parentheses: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for RuleParseError {}
+
+/// Port ranges as written in Meta, e.g. `80/443/8000-9000`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PortSet(pub Vec<(u16, u16)>);
+
+impl PortSet {
+    pub fn contains(&self, port: u16) -> bool {
+        self.0.iter().any(|(low, high)| *low <= port && port <= *high)
+    }
+}
+
+impl FromStr for PortSet {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ranges = Vec::new();
+        for part in s.split('/') {
+            let (low, high) = match part.split_once('-') {
+                Some((low, high)) => (low.trim().parse().map_err(|_| ())?, high.trim().parse().map_err(|_| ())?),
+                None => {
+                    let p = part.trim().parse().map_err(|_| ())?;
+                    (p, p)
+                }
+            };
+            if low > high {
+                return Err(());
+            }
+            ranges.push((low, high));
+        }
+        Ok(Self(ranges))
+    }
+}
+
+impl fmt::Display for PortSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self.0
+            .iter()
+            .map(|(low, high)| if low == high { low.to_string() } else { format!("{}-{}", low, high) })
+            .collect();
+        write!(f, "{}", parts.join("/"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Network {
+    Tcp,
+    Udp,
+}
+
+impl FromStr for Network {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "tcp" => Ok(Network::Tcp),
+            "udp" => Ok(Network::Udp),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Network::Tcp => write!(f, "TCP"),
+            Network::Udp => write!(f, "UDP"),
+        }
+    }
+}
+
+/// What a rule matches on. IP based kinds carry the `no-resolve` option,
+/// which the controller does not report.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RuleKind {
+    Domain(String),
+    DomainSuffix(String),
+    DomainKeyword(String),
+    DomainRegex(String),
+    Geosite(String),
+    GeoIp { country: String, no_resolve: bool },
+    SrcGeoIp(String),
+    IpAsn { asn: String, no_resolve: bool },
+    SrcIpAsn(String),
+    /// `IP-CIDR` and `IP-CIDR6`.
+    IpCidr { net: IpNet, no_resolve: bool },
+    SrcIpCidr(IpNet),
+    IpSuffix { net: IpNet, no_resolve: bool },
+    SrcIpSuffix(IpNet),
+    IpSet { name: String, no_resolve: bool },
+    SrcPort(PortSet),
+    DstPort(PortSet),
+    InPort(PortSet),
+    InType(String),
+    InUser(String),
+    InName(String),
+    Process(String),
+    ProcessPath(String),
+    ProcessNameRegex(String),
+    ProcessPathRegex(String),
+    Uid(String),
+    Dscp(String),
+    Network(Network),
+    RuleSet { name: String, no_resolve: bool },
+    Script(String),
+    /// `SUB-RULE`; the policy of the rule names the sub-rule.
+    SubRule(Box<RuleKind>),
+    And(Vec<RuleKind>),
+    Or(Vec<RuleKind>),
+    Not(Box<RuleKind>),
+    Match,
+    /// A type this model does not know, kept verbatim.
+    Unknown { r#type: String, payload: String },
+}
+
+/// Split `s` on commas outside of parentheses.
+pub(crate) fn split_top_level(s: &str) -> Result<Vec<&str>, RuleParseError> {
+    let mut fields = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1).ok_or_else(|| RuleParseError::Unbalanced(s.to_owned()))?,
+            ',' if depth == 0 => {
+                fields.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(RuleParseError::Unbalanced(s.to_owned()));
+    }
+    fields.push(s[start..].trim());
+    Ok(fields)
+}
+
+fn strip_parens(s: &str) -> Result<&str, RuleParseError> {
+    s.trim()
+        .strip_prefix('(')
+        .and_then(|s| s.strip_suffix(')'))
+        .ok_or_else(|| RuleParseError::Unbalanced(s.to_owned()))
+}
+
+/// Parse a parenthesized `TYPE,payload[,options]` as found inside logical
+/// rules.
+fn parse_condition(s: &str) -> Result<RuleKind, RuleParseError> {
+    let fields = split_top_level(strip_parens(s)?)?;
+    match fields[..] {
+        [r#type] => RuleKind::parse(r#type, "", &[]),
+        [r#type, payload, ref options @ ..] => RuleKind::parse(r#type, payload, options),
+        _ => Err(RuleParseError::MissingField(s.to_owned())),
+    }
+}
+
+fn parse_conditions(payload: &str) -> Result<Vec<RuleKind>, RuleParseError> {
+    split_top_level(strip_parens(payload)?)?
+        .into_iter()
+        .filter(|s| !s.is_empty())
+        .map(parse_condition)
+        .collect()
+}
+
+fn normalize(r#type: &str) -> String {
+    r#type
+        .chars()
+        .filter(|c| *c != '-' && *c != '_')
+        .collect::<String>()
+        .to_uppercase()
+}
+
+impl RuleKind {
+    /// Parse a rule type as written in a profile or reported by the
+    /// controller, its payload and trailing options such as `no-resolve`.
+    pub fn parse(r#type: &str, payload: &str, options: &[&str]) -> Result<Self, RuleParseError> {
+        use RuleKind::*;
+
+        let no_resolve = options.iter().any(|o| o.eq_ignore_ascii_case("no-resolve"));
+        let invalid = || RuleParseError::InvalidPayload {
+            r#type: r#type.to_owned(),
+            payload: payload.to_owned(),
+        };
+        let net = || payload.parse::<IpNet>().map_err(|_| invalid());
+        let ports = || payload.parse::<PortSet>().map_err(|_| invalid());
+        let text = || payload.to_owned();
+
+        let kind = match normalize(r#type).as_str() {
+            "DOMAIN" => Domain(text()),
+            "DOMAINSUFFIX" => DomainSuffix(text()),
+            "DOMAINKEYWORD" => DomainKeyword(text()),
+            "DOMAINREGEX" => DomainRegex(text()),
+            "GEOSITE" => Geosite(text()),
+            "GEOIP" => GeoIp { country: text(), no_resolve },
+            "SRCGEOIP" => SrcGeoIp(text()),
+            "IPASN" => IpAsn { asn: text(), no_resolve },
+            "SRCIPASN" => SrcIpAsn(text()),
+            "IPCIDR" | "IPCIDR6" => IpCidr { net: net()?, no_resolve },
+            "SRCIPCIDR" | "SRCIPCIDR6" => SrcIpCidr(net()?),
+            "IPSUFFIX" => IpSuffix { net: net()?, no_resolve },
+            "SRCIPSUFFIX" => SrcIpSuffix(net()?),
+            "IPSET" => IpSet { name: text(), no_resolve },
+            "SRCPORT" => SrcPort(ports()?),
+            "DSTPORT" => DstPort(ports()?),
+            "INPORT" => InPort(ports()?),
+            "INTYPE" => InType(text()),
+            "INUSER" => InUser(text()),
+            "INNAME" => InName(text()),
+            "PROCESS" | "PROCESSNAME" => Process(text()),
+            "PROCESSPATH" => ProcessPath(text()),
+            "PROCESSNAMEREGEX" => ProcessNameRegex(text()),
+            "PROCESSPATHREGEX" => ProcessPathRegex(text()),
+            "UID" => Uid(text()),
+            "DSCP" => Dscp(text()),
+            "NETWORK" => Network(payload.parse().map_err(|_| invalid())?),
+            "RULESET" => RuleSet { name: text(), no_resolve },
+            "SCRIPT" => Script(text()),
+            "SUBRULE" | "SUBRULES" => SubRule(Box::new(parse_condition(payload)?)),
+            "AND" | "OR" => {
+                let conditions = parse_conditions(payload)?;
+                if conditions.is_empty() {
+                    return Err(invalid());
+                }
+                if normalize(r#type) == "AND" { And(conditions) } else { Or(conditions) }
+            }
+            "NOT" => {
+                let mut conditions = parse_conditions(payload)?;
+                if conditions.len() != 1 {
+                    return Err(invalid());
+                }
+                Not(Box::new(conditions.remove(0)))
+            }
+            "MATCH" | "FINAL" => Match,
+            _ => Unknown { r#type: r#type.to_owned(), payload: text() },
+        };
+        Ok(kind)
+    }
+
+    /// The type as written in a profile, e.g. `DOMAIN-SUFFIX`.
+    pub fn line_type(&self) -> String {
+        use RuleKind::*;
+        let t = match self {
+            Domain(_) => "DOMAIN",
+            DomainSuffix(_) => "DOMAIN-SUFFIX",
+            DomainKeyword(_) => "DOMAIN-KEYWORD",
+            DomainRegex(_) => "DOMAIN-REGEX",
+            Geosite(_) => "GEOSITE",
+            GeoIp { .. } => "GEOIP",
+            SrcGeoIp(_) => "SRC-GEOIP",
+            IpAsn { .. } => "IP-ASN",
+            SrcIpAsn(_) => "SRC-IP-ASN",
+            IpCidr { net: IpNet::V4(_), .. } => "IP-CIDR",
+            IpCidr { net: IpNet::V6(_), .. } => "IP-CIDR6",
+            SrcIpCidr(_) => "SRC-IP-CIDR",
+            IpSuffix { .. } => "IP-SUFFIX",
+            SrcIpSuffix(_) => "SRC-IP-SUFFIX",
+            IpSet { .. } => "IP-SET",
+            SrcPort(_) => "SRC-PORT",
+            DstPort(_) => "DST-PORT",
+            InPort(_) => "IN-PORT",
+            InType(_) => "IN-TYPE",
+            InUser(_) => "IN-USER",
+            InName(_) => "IN-NAME",
+            Process(_) => "PROCESS-NAME",
+            ProcessPath(_) => "PROCESS-PATH",
+            ProcessNameRegex(_) => "PROCESS-NAME-REGEX",
+            ProcessPathRegex(_) => "PROCESS-PATH-REGEX",
+            Uid(_) => "UID",
+            Dscp(_) => "DSCP",
+            Network(_) => "NETWORK",
+            RuleSet { .. } => "RULE-SET",
+            Script(_) => "SCRIPT",
+            SubRule(_) => "SUB-RULE",
+            And(_) => "AND",
+            Or(_) => "OR",
+            Not(_) => "NOT",
+            Match => "MATCH",
+            Unknown { r#type, .. } => return r#type.clone(),
+        };
+        t.to_owned()
+    }
+
+    /// The type as the controller reports it, e.g. `DomainSuffix`.
+    pub fn controller_type(&self) -> String {
+        use RuleKind::*;
+        let t = match self {
+            Domain(_) => "Domain",
+            DomainSuffix(_) => "DomainSuffix",
+            DomainKeyword(_) => "DomainKeyword",
+            DomainRegex(_) => "DomainRegex",
+            Geosite(_) => "GeoSite",
+            GeoIp { .. } => "GeoIP",
+            SrcGeoIp(_) => "SrcGeoIP",
+            IpAsn { .. } => "IPASN",
+            SrcIpAsn(_) => "SrcIPASN",
+            IpCidr { .. } => "IPCIDR",
+            SrcIpCidr(_) => "SrcIPCIDR",
+            IpSuffix { .. } => "IPSuffix",
+            SrcIpSuffix(_) => "SrcIPSuffix",
+            IpSet { .. } => "IPSet",
+            SrcPort(_) => "SrcPort",
+            DstPort(_) => "DstPort",
+            InPort(_) => "InPort",
+            InType(_) => "InType",
+            InUser(_) => "InUser",
+            InName(_) => "InName",
+            Process(_) => "Process",
+            ProcessPath(_) => "ProcessPath",
+            ProcessNameRegex(_) => "ProcessNameRegex",
+            ProcessPathRegex(_) => "ProcessPathRegex",
+            Uid(_) => "Uid",
+            Dscp(_) => "DSCP",
+            Network(_) => "Network",
+            RuleSet { .. } => "RuleSet",
+            Script(_) => "Script",
+            SubRule(_) => "SubRules",
+            And(_) => "AND",
+            Or(_) => "OR",
+            Not(_) => "NOT",
+            Match => "Match",
+            Unknown { r#type, .. } => return r#type.clone(),
+        };
+        t.to_owned()
+    }
+
+    /// The payload without options, e.g. `10.0.0.0/8` or
+    /// `((DOMAIN,a.com),(NETWORK,UDP))`.
+    pub fn payload(&self) -> String {
+        use RuleKind::*;
+        match self {
+            Domain(s) | DomainSuffix(s) | DomainKeyword(s) | DomainRegex(s) | Geosite(s) | SrcGeoIp(s)
+            | SrcIpAsn(s) | InType(s) | InUser(s) | InName(s) | Process(s) | ProcessPath(s)
+            | ProcessNameRegex(s) | ProcessPathRegex(s) | Uid(s) | Dscp(s) | Script(s) => s.clone(),
+            GeoIp { country, .. } => country.clone(),
+            IpAsn { asn, .. } => asn.clone(),
+            IpCidr { net, .. } | IpSuffix { net, .. } | SrcIpCidr(net) | SrcIpSuffix(net) => net.to_string(),
+            IpSet { name, .. } | RuleSet { name, .. } => name.clone(),
+            SrcPort(ports) | DstPort(ports) | InPort(ports) => ports.to_string(),
+            Network(network) => network.to_string(),
+            SubRule(kind) => kind.condition(),
+            And(kinds) | Or(kinds) => {
+                let conditions: Vec<String> = kinds.iter().map(RuleKind::condition).collect();
+                format!("({})", conditions.join(","))
+            }
+            Not(kind) => format!("({})", kind.condition()),
+            Match => "".to_owned(),
+            Unknown { payload, .. } => payload.clone(),
+        }
+    }
+
+    pub fn no_resolve(&self) -> bool {
+        use RuleKind::*;
+        match self {
+            GeoIp { no_resolve, .. } | IpAsn { no_resolve, .. } | IpCidr { no_resolve, .. }
+            | IpSuffix { no_resolve, .. } | IpSet { no_resolve, .. } | RuleSet { no_resolve, .. } => *no_resolve,
+            _ => false,
+        }
+    }
+
+    /// `TYPE,payload[,no-resolve]` without a policy.
+    fn fields(&self) -> String {
+        let mut s = self.line_type();
+        if *self != RuleKind::Match {
+            s.push(',');
+            s.push_str(&self.payload());
+        }
+        if self.no_resolve() {
+            s.push_str(",no-resolve");
+        }
+        s
+    }
+
+    /// The rule as a condition of a logical rule, e.g. `(NETWORK,UDP)`.
+    fn condition(&self) -> String {
+        format!("({})", self.fields())
+    }
+}
+
+/// A rule with its kind parsed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TypedRule {
+    pub kind: RuleKind,
+    pub policy: String,
+}
+
+impl TypedRule {
+    pub fn new(kind: RuleKind, policy: &str) -> Self {
+        Self { kind, policy: policy.to_owned() }
+    }
+
+    /// Parse a rule line of a profile, e.g. `IP-CIDR,10.0.0.0/8,DIRECT,no-resolve`.
+    pub fn parse_line(line: &str) -> Result<Self, RuleParseError> {
+        let fields = split_top_level(line)?;
+        let missing = || RuleParseError::MissingField(line.to_owned());
+
+        let r#type = fields.first().filter(|t| !t.is_empty()).ok_or_else(missing)?;
+        if matches!(normalize(r#type).as_str(), "MATCH" | "FINAL") {
+            let policy = fields.get(1).ok_or_else(missing)?;
+            return Ok(Self::new(RuleKind::Match, policy));
+        }
+
+        match fields[..] {
+            [r#type, payload, policy, ref options @ ..] => Ok(Self::new(RuleKind::parse(r#type, payload, options)?, policy)),
+            _ => Err(missing()),
+        }
+    }
+
+    /// The rule in the line syntax of a profile.
+    pub fn to_line(&self) -> String {
+        match self.kind {
+            RuleKind::Match => format!("MATCH,{}", self.policy),
+            _ => {
+                let mut line = format!("{},{},{}", self.kind.line_type(), self.kind.payload(), self.policy);
+                if self.kind.no_resolve() {
+                    line.push_str(",no-resolve");
+                }
+                line
+            }
+        }
+    }
+}
+
+impl fmt::Display for TypedRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_line())
+    }
+}
+
+impl FromStr for TypedRule {
+    type Err = RuleParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_line(s)
+    }
+}
+
+impl TryFrom<&Rule> for TypedRule {
+    type Error = RuleParseError;
+
+    fn try_from(rule: &Rule) -> Result<Self, Self::Error> {
+        Ok(Self::new(RuleKind::parse(&rule.r#type, &rule.payload, &[])?, &rule.proxy))
+    }
+}
+
+impl From<&TypedRule> for Rule {
+    /// The rule as the controller reports it.
+    fn from(rule: &TypedRule) -> Self {
+        Self {
+            r#type: rule.kind.controller_type(),
+            payload: rule.kind.payload(),
+            proxy: rule.policy.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_line_round_trip() {
+        let lines = [
+            "DOMAIN-SUFFIX,google.com,Proxy",
+            "IP-CIDR,10.0.0.0/8,DIRECT,no-resolve",
+            "IP-CIDR6,2001:db8::/32,DIRECT",
+            "DST-PORT,80/443/8000-9000,Web",
+            "GEOIP,CN,DIRECT,no-resolve",
+            "RULE-SET,ads,REJECT",
+            "GEOSITE,category-ads-all,REJECT",
+            "IN-PORT,7890,Proxy",
+            "NETWORK,UDP,DIRECT",
+            "AND,((DOMAIN,example.com),(NETWORK,UDP)),REJECT",
+            "OR,((DST-PORT,53),(AND,((NETWORK,UDP),(IP-CIDR,8.8.8.8/32,no-resolve)))),DNS",
+            "NOT,((DOMAIN-KEYWORD,google)),DIRECT",
+            "SUB-RULE,(NETWORK,TCP),tcp-rules",
+            "MATCH,Proxy",
+        ];
+        for line in lines {
+            let rule = TypedRule::parse_line(line).unwrap();
+            assert_eq!(rule.to_line(), line);
+        }
+
+        let rule: TypedRule = "AND,((DOMAIN,example.com),(NETWORK,udp)),REJECT".parse().unwrap();
+        assert_eq!(
+            rule.kind,
+            RuleKind::And(vec![RuleKind::Domain("example.com".to_owned()), RuleKind::Network(Network::Udp)])
+        );
+    }
+
+    #[test]
+    fn test_controller_round_trip() {
+        let json = r#"[
+            {"type":"DomainSuffix","payload":"google.com","proxy":"Proxy"},
+            {"type":"IPCIDR","payload":"10.0.0.0/8","proxy":"DIRECT"},
+            {"type":"GeoIP","payload":"CN","proxy":"DIRECT"},
+            {"type":"RuleSet","payload":"ads","proxy":"REJECT","size":42},
+            {"type":"AND","payload":"((DOMAIN,example.com),(NETWORK,UDP))","proxy":"REJECT"},
+            {"type":"Match","payload":"","proxy":"Proxy"}
+        ]"#;
+        let rules: Vec<Rule> = serde_json::from_str(json).unwrap();
+        for rule in rules.iter() {
+            let typed = TypedRule::try_from(rule).unwrap();
+            assert_eq!(&Rule::from(&typed), rule);
+        }
+
+        let typed = TypedRule::try_from(&rules[1]).unwrap();
+        assert_eq!(typed.kind, RuleKind::IpCidr { net: "10.0.0.0/8".parse().unwrap(), no_resolve: false });
+        assert_eq!(typed.to_line(), "IP-CIDR,10.0.0.0/8,DIRECT");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(TypedRule::parse_line("IP-CIDR,10.0.0.0/33,DIRECT"), Err(RuleParseError::InvalidPayload { .. })));
+        assert!(matches!(TypedRule::parse_line("DST-PORT,9000-80,DIRECT"), Err(RuleParseError::InvalidPayload { .. })));
+        assert!(matches!(TypedRule::parse_line("AND,((DOMAIN,a.com),DIRECT"), Err(RuleParseError::Unbalanced(_))));
+        assert!(matches!(TypedRule::parse_line("NOT,((DOMAIN,a.com),(DOMAIN,b.com)),DIRECT"), Err(RuleParseError::InvalidPayload { .. })));
+        assert!(matches!(TypedRule::parse_line("DOMAIN,a.com"), Err(RuleParseError::MissingField(_))));
+
+        let unknown = TypedRule::parse_line("FANCY,thing,Proxy").unwrap();
+        assert_eq!(unknown.to_line(), "FANCY,thing,Proxy");
+    }
+}
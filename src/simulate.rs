@@ -0,0 +1,130 @@
+//! Replay recorded connections against a candidate rule set and report
+//! the destinations whose policy would change.
+
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    history::ConnectionRecord,
+    matcher::{Matcher, Query},
+    rule::kind::Network,
+};
+
+/// The policy the core falls back to when no rule matches.
+const FALLBACK_POLICY: &str = "DIRECT";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PolicyChange {
+    pub destination: String,
+    pub from: String,
+    pub to: String,
+    /// The candidate rule that would match, in line syntax.
+    pub rule: Option<String>,
+    pub connections: usize,
+    /// Whether an earlier rule could not be evaluated offline and might
+    /// match instead.
+    pub uncertain: bool,
+}
+
+impl std::fmt::Display for PolicyChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} -> {}", self.destination, self.from, self.to)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Simulation {
+    /// Connections replayed.
+    pub total: usize,
+    /// Connections whose policy would stay the same.
+    pub unchanged: usize,
+    pub changes: Vec<PolicyChange>,
+}
+
+/// The query the core would have evaluated for a recorded connection.
+pub fn query(record: &ConnectionRecord) -> Query {
+    let mut query = Query {
+        host: Some(record.host.to_lowercase()).filter(|h| !h.is_empty()),
+        ip: record.destination_ip.parse().ok(),
+        dst_port: record.destination_port.parse().ok(),
+        network: record.network.parse::<Network>().ok(),
+        src_ip: record.source_ip.parse().ok(),
+        src_port: record.source_port.parse().ok(),
+        ..Query::default()
+    };
+    if !record.process_path.is_empty() {
+        query = query.process(&record.process_path);
+    }
+    query
+}
+
+/// Evaluate every record with `matcher` and collect policy changes per
+/// destination, ordered by destination.
+pub fn simulate(records: &[ConnectionRecord], matcher: &Matcher) -> Simulation {
+    let mut simulation = Simulation { total: records.len(), ..Simulation::default() };
+    let mut changes: BTreeMap<(String, String, String), PolicyChange> = BTreeMap::new();
+
+    for record in records {
+        let evaluation = matcher.evaluate(&query(record));
+        let to = evaluation.matched.as_ref().map_or(FALLBACK_POLICY, |m| m.policy());
+        let from = record.policy();
+        if from == to {
+            simulation.unchanged += 1;
+            continue;
+        }
+
+        let key = (record.destination().to_owned(), from.to_owned(), to.to_owned());
+        let change = changes.entry(key).or_insert_with(|| PolicyChange {
+            destination: record.destination().to_owned(),
+            from: from.to_owned(),
+            to: to.to_owned(),
+            rule: evaluation.matched.as_ref().map(|m| m.rule.to_line()),
+            connections: 0,
+            uncertain: false,
+        });
+        change.connections += 1;
+        change.uncertain |= !evaluation.undecided.is_empty();
+    }
+
+    simulation.changes = changes.into_values().collect();
+    simulation
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        history::ConnectionTracker,
+        mock::{connection, snapshot},
+        rule::kind::TypedRule,
+    };
+
+    #[test]
+    fn test_simulate() {
+        let mut tracker = ConnectionTracker::new();
+        tracker.update(&snapshot(&[
+            connection("1", "api.example.com", "93.184.216.34", &["DIRECT"], 0, 0),
+            connection("2", "api.example.com", "93.184.216.34", &["DIRECT"], 0, 0),
+            connection("3", "www.google.com", "142.250.1.1", &["node-us", "proxy-us"], 0, 0),
+            connection("4", "", "10.0.0.1", &["DIRECT"], 0, 0),
+        ]), 0);
+        let records = tracker.finish();
+
+        let rules = [
+            "DOMAIN-SUFFIX,example.com,proxy-eu",
+            "DOMAIN-SUFFIX,google.com,proxy-us",
+            "IP-CIDR,10.0.0.0/8,DIRECT,no-resolve",
+            "MATCH,proxy-us",
+        ];
+        let matcher = Matcher::from_typed(rules.iter().map(|l| TypedRule::parse_line(l).unwrap()));
+        let simulation = simulate(&records, &matcher);
+
+        assert_eq!((simulation.total, simulation.unchanged), (4, 2));
+        assert_eq!(simulation.changes.len(), 1);
+        let change = &simulation.changes[0];
+        assert_eq!(change.to_string(), "api.example.com: DIRECT -> proxy-eu");
+        assert_eq!(change.connections, 2);
+        assert_eq!(change.rule.as_deref(), Some("DOMAIN-SUFFIX,example.com,proxy-eu"));
+    }
+}
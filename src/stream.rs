@@ -86,7 +86,12 @@ impl<T: std::marker::Unpin + for<'a> Deserialize<'a>> ClashStream<T> {
     }
 }
 
-async fn get_stream_request(request: impl ClashRequest) -> Result< Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>>>>, Box<dyn std::error::Error> > {
+pub(crate) async fn get_stream_request(request: impl ClashRequest) -> Result< Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>>>>, Box<dyn std::error::Error> > {
+    Ok(Box::pin(get_stream_response(request).await?.bytes_stream()))
+}
+
+/// Start a streaming `GET`, leaving the status to the caller.
+pub(crate) async fn get_stream_response(request: impl ClashRequest) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
     let mut c = Client::new()
         .get(format!("http://{}:{}/{}?{}",
                      request.get_dest(),
@@ -98,10 +103,6 @@ async fn get_stream_request(request: impl ClashRequest) -> Result< Pin<Box<dyn S
         c = c.header("Authorization", format!("Bearer {}", secret));
     }
 
-    let c = c.send()
-        .await?
-        .bytes_stream();
-
-    Ok(Box::pin(c))
+    Ok(c.send().await?)
 }
 
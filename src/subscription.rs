@@ -0,0 +1,502 @@
+//! Named subscriptions: download a profile from a URL, convert it when
+//! needed and write it out for the core to load.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::engine::{general_purpose::{GeneralPurpose, GeneralPurposeConfig}, DecodePaddingMode};
+use base64::{alphabet, Engine};
+use reqwest::Client;
+use serde::{Serialize, Deserialize};
+use serde_yaml::{Mapping, Value};
+
+use crate::profile::Profile;
+
+/// Providers tend to return clash YAML only to clients that look like clash.
+const DEFAULT_USER_AGENT: &str = "clash";
+
+/// Name of the selector generated for converted URI lists.
+pub const DEFAULT_GROUP: &str = "PROXY";
+
+#[derive(Debug)]
+pub enum SubscriptionError {
+    Exists(String),
+    NotFound(String),
+    InvalidName(String),
+    Http(u16),
+    UnknownFormat,
+}
+
+impl std::fmt::Display for SubscriptionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use SubscriptionError::*;
+        match self {
+            Exists(name) => write!(f, "Subscription already exists: {}", name),
+            NotFound(name) => write!(f, "Subscription not found: {}", name),
+            InvalidName(name) => write!(f, "Invalid subscription name: {}", name),
+            Http(code) => write!(f, "Subscription download failed: {}", code),
+            UnknownFormat => write!(f, "Subscription is neither clash YAML nor a URI list"),
+        }
+    }
+}
+
+impl std::error::Error for SubscriptionError {}
+
+/// Traffic and expiry reported in the `subscription-userinfo` header.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserInfo {
+    pub upload: u64,
+    pub download: u64,
+    pub total: u64,
+    /// Unix timestamp, if the subscription expires.
+    pub expire: Option<u64>,
+}
+
+impl UserInfo {
+    /// Parse a header like `upload=1; download=2; total=3; expire=4`.
+    pub fn parse(header: &str) -> Self {
+        let mut info = Self::default();
+        for field in header.split(';') {
+            let (key, value) = match field.split_once('=') {
+                Some(kv) => kv,
+                None => continue,
+            };
+            let value = value.trim().parse::<f64>().ok().map(|v| v as u64);
+            match key.trim() {
+                "upload" => info.upload = value.unwrap_or(0),
+                "download" => info.download = value.unwrap_or(0),
+                "total" => info.total = value.unwrap_or(0),
+                "expire" => info.expire = value.filter(|v| *v > 0),
+                _ => {}
+            }
+        }
+        info
+    }
+
+    pub fn used(&self) -> u64 {
+        self.upload + self.download
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Subscription {
+    pub name: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// Usage reported by the last update.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub userinfo: Option<UserInfo>,
+    /// Unix timestamp of the last successful update.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated: Option<u64>,
+}
+
+/// The raw result of downloading a subscription.
+pub struct Fetched {
+    pub body: String,
+    pub userinfo: Option<UserInfo>,
+}
+
+impl Subscription {
+    pub fn new(name: &str, url: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            url: url.to_owned(),
+            headers: BTreeMap::new(),
+            userinfo: None,
+            updated: None,
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name.to_owned(), value.to_owned());
+        self
+    }
+
+    pub async fn fetch(&self) -> Result<Fetched, Box<dyn std::error::Error>> {
+        let mut c = Client::new()
+            .get(&self.url)
+            .header("User-Agent", DEFAULT_USER_AGENT);
+        for (name, value) in self.headers.iter() {
+            c = c.header(name.as_str(), value.as_str());
+        }
+
+        let res = c.send().await?;
+        if !res.status().is_success() {
+            return Err(Box::new(SubscriptionError::Http(res.status().as_u16())));
+        }
+
+        let userinfo = res
+            .headers()
+            .get("subscription-userinfo")
+            .and_then(|v| v.to_str().ok())
+            .map(UserInfo::parse);
+
+        Ok(Fetched { body: res.text().await?, userinfo })
+    }
+
+    /// Download the subscription, convert it and write the profile to
+    /// `path`. The returned profile is ready for `ClashConfigLoad`.
+    pub async fn update(&mut self, path: impl AsRef<Path>) -> Result<Profile, Box<dyn std::error::Error>> {
+        let fetched = self.fetch().await?;
+        let profile = decode(&fetched.body)?;
+        profile.save(path)?;
+
+        self.userinfo = fetched.userinfo;
+        self.updated = SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs());
+        Ok(profile)
+    }
+}
+
+/// Subscriptions persisted as a JSON file.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SubscriptionStore {
+    #[serde(skip)]
+    path: PathBuf,
+    subscriptions: Vec<Subscription>,
+}
+
+impl SubscriptionStore {
+    /// Open the store at `path`. A missing file is an empty store.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref().to_owned();
+        let mut store: Self = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(e) => return Err(Box::new(e)),
+        };
+        store.path = path;
+        Ok(store)
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        crate::file::write_atomic(&self.path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Subscription> {
+        self.subscriptions.iter()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Subscription> {
+        self.subscriptions.iter().find(|s| s.name == name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Subscription> {
+        self.subscriptions.iter_mut().find(|s| s.name == name)
+    }
+
+    /// Names become file names in the data directory.
+    fn check_name(name: &str) -> Result<(), SubscriptionError> {
+        if !crate::file::is_valid_name(name) {
+            Err(SubscriptionError::InvalidName(name.to_owned()))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn add(&mut self, subscription: Subscription) -> Result<(), SubscriptionError> {
+        Self::check_name(&subscription.name)?;
+        if self.get(&subscription.name).is_some() {
+            return Err(SubscriptionError::Exists(subscription.name));
+        }
+        self.subscriptions.push(subscription);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Result<Subscription, SubscriptionError> {
+        match self.subscriptions.iter().position(|s| s.name == name) {
+            Some(i) => Ok(self.subscriptions.remove(i)),
+            None => Err(SubscriptionError::NotFound(name.to_owned())),
+        }
+    }
+}
+
+/// Turn a subscription body into a profile. The body is either a clash
+/// YAML profile or a (usually base64 encoded) list of proxy URIs.
+pub fn decode(body: &str) -> Result<Profile, SubscriptionError> {
+    if let Ok(profile) = Profile::try_from(body.to_owned()) {
+        if !profile.proxies.is_empty() || profile.setting("proxy-providers").is_some() {
+            return Ok(profile);
+        }
+    }
+
+    let text = if body.contains("://") {
+        body.to_owned()
+    } else {
+        decode_base64(body)
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or(SubscriptionError::UnknownFormat)?
+    };
+
+    let proxies: Vec<Mapping> = text.lines().filter_map(|line| parse_uri(line.trim())).collect();
+    if proxies.is_empty() {
+        return Err(SubscriptionError::UnknownFormat);
+    }
+    Ok(profile_from_proxies(proxies))
+}
+
+fn profile_from_proxies(proxies: Vec<Mapping>) -> Profile {
+    let mut names: Vec<String> = Vec::new();
+    let mut unique = Vec::new();
+    for mut proxy in proxies {
+        let base = proxy.get("name").and_then(Value::as_str).unwrap_or("proxy").to_owned();
+        let mut name = base.clone();
+        let mut n = 1;
+        while names.contains(&name) {
+            n += 1;
+            name = format!("{} {}", base, n);
+        }
+        proxy.insert("name".into(), name.clone().into());
+        names.push(name);
+        unique.push(proxy);
+    }
+
+    let mut group = Mapping::new();
+    group.insert("name".into(), DEFAULT_GROUP.into());
+    group.insert("type".into(), "select".into());
+    group.insert("proxies".into(), Value::Sequence(names.into_iter().map(Value::from).collect()));
+
+    let mut general = Mapping::new();
+    general.insert("mixed-port".into(), 7890.into());
+    general.insert("mode".into(), "rule".into());
+
+    Profile {
+        general,
+        proxies: unique,
+        proxy_groups: vec![group],
+        rules: vec![format!("MATCH,{}", DEFAULT_GROUP)],
+    }
+}
+
+fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    let config = GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent);
+    let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+
+    [alphabet::STANDARD, alphabet::URL_SAFE]
+        .iter()
+        .find_map(|alphabet| GeneralPurpose::new(alphabet, config).decode(&s).ok())
+}
+
+fn split_host_port(s: &str) -> Option<(String, u16)> {
+    let (host, port) = s.rsplit_once(':')?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Some((host.to_owned(), port.parse().ok()?))
+}
+
+fn query_params(query: &str) -> BTreeMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|kv| kv.split_once('='))
+        .map(|(k, v)| (k.to_owned(), urlencoding::decode(v).map(|v| v.into_owned()).unwrap_or_else(|_| v.to_owned())))
+        .collect()
+}
+
+fn proxy_entry(name: &str, r#type: &str, server: String, port: u16) -> Mapping {
+    let mut proxy = Mapping::new();
+    proxy.insert("name".into(), name.into());
+    proxy.insert("type".into(), r#type.into());
+    proxy.insert("server".into(), server.into());
+    proxy.insert("port".into(), port.into());
+    proxy
+}
+
+/// Convert a `ss://`, `trojan://` or `vmess://` URI into a clash proxy.
+/// Other schemes are skipped.
+pub fn parse_uri(uri: &str) -> Option<Mapping> {
+    let (scheme, rest) = uri.split_once("://")?;
+    let (rest, name) = match rest.split_once('#') {
+        Some((rest, name)) => (rest, urlencoding::decode(name).ok()?.into_owned()),
+        None => (rest, String::new()),
+    };
+    let (rest, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let rest = rest.trim_end_matches('/');
+
+    match scheme {
+        "ss" => {
+            let decoded;
+            let (userinfo, server) = match rest.rsplit_once('@') {
+                Some((userinfo, server)) => {
+                    decoded = decode_base64(userinfo)
+                        .and_then(|b| String::from_utf8(b).ok())
+                        .or_else(|| Some(urlencoding::decode(userinfo).ok()?.into_owned()))?;
+                    (decoded.as_str(), server.to_owned())
+                }
+                None => {
+                    decoded = String::from_utf8(decode_base64(rest)?).ok()?;
+                    let (userinfo, server) = decoded.rsplit_once('@')?;
+                    (userinfo, server.to_owned())
+                }
+            };
+            let (cipher, password) = userinfo.split_once(':')?;
+            let (host, port) = split_host_port(&server)?;
+
+            let mut proxy = proxy_entry(&name, "ss", host, port);
+            proxy.insert("cipher".into(), cipher.into());
+            proxy.insert("password".into(), password.into());
+            proxy.insert("udp".into(), true.into());
+            Some(proxy)
+        }
+        "trojan" => {
+            let (password, server) = rest.rsplit_once('@')?;
+            let (host, port) = split_host_port(server)?;
+            let params = query_params(query);
+
+            let mut proxy = proxy_entry(&name, "trojan", host, port);
+            proxy.insert("password".into(), urlencoding::decode(password).ok()?.into_owned().into());
+            if let Some(sni) = params.get("sni").or_else(|| params.get("peer")) {
+                proxy.insert("sni".into(), sni.as_str().into());
+            }
+            if params.get("allowInsecure").is_some_and(|v| v == "1" || v == "true") {
+                proxy.insert("skip-cert-verify".into(), true.into());
+            }
+            proxy.insert("udp".into(), true.into());
+            Some(proxy)
+        }
+        "vmess" => {
+            let json: serde_json::Value = serde_json::from_slice(&decode_base64(rest)?).ok()?;
+            let field = |key: &str| match &json[key] {
+                serde_json::Value::String(s) => Some(s.clone()),
+                serde_json::Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            };
+            let name = field("ps").unwrap_or(name);
+            let port = field("port")?.parse().ok()?;
+
+            let mut proxy = proxy_entry(&name, "vmess", field("add")?, port);
+            proxy.insert("uuid".into(), field("id")?.into());
+            proxy.insert("alterId".into(), field("aid").and_then(|a| a.parse::<u64>().ok()).unwrap_or(0).into());
+            proxy.insert("cipher".into(), field("scy").unwrap_or_else(|| "auto".to_owned()).into());
+            if field("tls").as_deref() == Some("tls") {
+                proxy.insert("tls".into(), true.into());
+                if let Some(sni) = field("sni") {
+                    proxy.insert("servername".into(), sni.into());
+                }
+            }
+            if let Some(network) = field("net").filter(|n| n != "tcp") {
+                if network == "ws" {
+                    let mut opts = Mapping::new();
+                    if let Some(path) = field("path") {
+                        opts.insert("path".into(), path.into());
+                    }
+                    if let Some(host) = field("host").filter(|h| !h.is_empty()) {
+                        let mut headers = Mapping::new();
+                        headers.insert("Host".into(), host.into());
+                        opts.insert("headers".into(), headers.into());
+                    }
+                    proxy.insert("ws-opts".into(), opts.into());
+                }
+                proxy.insert("network".into(), network.into());
+            }
+            Some(proxy)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base64::Engine;
+
+    use super::*;
+    use crate::mock::MockController;
+
+    fn encode(s: &str) -> String {
+        base64::engine::general_purpose::STANDARD.encode(s)
+    }
+
+    #[test]
+    fn test_parse_userinfo() {
+        let info = UserInfo::parse("upload=100; download=200; total=1073741824; expire=1767225600");
+        assert_eq!(info.used(), 300);
+        assert_eq!(info.total, 1073741824);
+        assert_eq!(info.expire, Some(1767225600));
+
+        assert_eq!(UserInfo::parse("upload=0; download=0; total=0; expire=").expire, None);
+    }
+
+    #[test]
+    fn test_parse_uris() {
+        let ss = format!("ss://{}@jp.example.com:8388#Tokyo%2001", encode("aes-256-gcm:secret"));
+        let proxy = parse_uri(&ss).unwrap();
+        assert_eq!(proxy.get("name").unwrap(), "Tokyo 01");
+        assert_eq!(proxy.get("cipher").unwrap(), "aes-256-gcm");
+        assert_eq!(proxy.get("port").unwrap(), 8388);
+
+        let plain = parse_uri("ss://2022-blake3-aes-128-gcm:pa%2Fss@sg.example.com:443#SG").unwrap();
+        assert_eq!(plain.get("password").unwrap(), "pa/ss");
+
+        let legacy = format!("ss://{}#legacy", encode("chacha20-ietf-poly1305:pw@1.2.3.4:443"));
+        assert_eq!(parse_uri(&legacy).unwrap().get("server").unwrap(), "1.2.3.4");
+
+        let trojan = parse_uri("trojan://pass@hk.example.com:443?sni=cdn.example.com&allowInsecure=1#HK").unwrap();
+        assert_eq!(trojan.get("sni").unwrap(), "cdn.example.com");
+        assert_eq!(trojan.get("skip-cert-verify").unwrap(), &Value::Bool(true));
+
+        let vmess = format!("vmess://{}", encode(r#"{"v":"2","ps":"US","add":"us.example.com","port":"443","id":"uuid","aid":"0","net":"ws","path":"/ws","host":"us.example.com","tls":"tls"}"#));
+        let vmess = parse_uri(&vmess).unwrap();
+        assert_eq!(vmess.get("name").unwrap(), "US");
+        assert_eq!(vmess.get("network").unwrap(), "ws");
+        assert_eq!(vmess.get("tls").unwrap(), &Value::Bool(true));
+
+        assert!(parse_uri("ssr://whatever").is_none());
+    }
+
+    #[test]
+    fn test_decode_uri_list() {
+        let list = encode("trojan://a@a.example.com:443#node\ntrojan://b@b.example.com:443#node\n");
+        let profile = decode(&list).unwrap();
+
+        let names: Vec<_> = profile.proxy_names().collect();
+        assert_eq!(names, vec!["node", "node 2"]);
+        assert_eq!(profile.group_names().collect::<Vec<_>>(), vec![DEFAULT_GROUP]);
+        assert_eq!(profile.rules, vec!["MATCH,PROXY"]);
+
+        assert!(decode("not a subscription").is_err());
+    }
+
+    #[test]
+    fn test_store_names() {
+        let path = std::env::temp_dir().join(format!("clashrs-ctl-subscriptions-{}.json", std::process::id()));
+        let mut store = SubscriptionStore::open(&path).unwrap();
+        for name in ["../../x", "a/b", "a\\b", ".hidden", ""] {
+            assert!(matches!(store.add(Subscription::new(name, "http://example.com")), Err(SubscriptionError::InvalidName(_))));
+        }
+        store.add(Subscription::new("airport", "http://example.com")).unwrap();
+        store.save().unwrap();
+
+        assert_eq!(SubscriptionStore::open(&path).unwrap().iter().count(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_update_subscription() {
+        let mock = MockController::new();
+        mock.route_with_headers(
+            "GET",
+            "sub",
+            200,
+            "proxies:\n  - {name: a, type: ss, server: a.example.com, port: 1, cipher: aes-128-gcm, password: x}\n",
+            &[("subscription-userinfo", "upload=1; download=2; total=10; expire=0")],
+        );
+        let port = mock.serve().await;
+
+        let dir = std::env::temp_dir().join(format!("clashrsctl-sub-{}", port));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.yaml");
+
+        let mut sub = Subscription::new("a", &format!("http://127.0.0.1:{}/sub", port)).header("X-Token", "t");
+        sub.update(&path).await.unwrap();
+
+        assert_eq!(sub.userinfo.as_ref().map(UserInfo::used), Some(3));
+        assert!(sub.updated.is_some());
+        assert_eq!(Profile::from_file(&path).unwrap().proxy_names().collect::<Vec<_>>(), vec!["a"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
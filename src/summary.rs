@@ -0,0 +1,124 @@
+//! Group the current connections by who makes them and where they go.
+
+use std::collections::HashMap;
+
+use clap::ValueEnum;
+use serde::{Serialize, Deserialize};
+
+use crate::connection::{Connection, ConnectionVec};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    /// Destination host, or IP when there is no host
+    Host,
+    /// Process name
+    Process,
+    /// Source IP, i.e. the device on the LAN
+    Source,
+    /// Full proxy chain
+    Chain,
+    /// Matched rule
+    Rule,
+}
+
+impl GroupBy {
+    /// The group key of `connection`.
+    pub fn key(&self, connection: &Connection) -> String {
+        let metadata = &connection.metadata;
+        match self {
+            GroupBy::Host => metadata.destination().to_owned(),
+            GroupBy::Process => metadata.process_name().to_owned(),
+            GroupBy::Source => metadata.source_ip.clone(),
+            GroupBy::Chain => connection.chains.iter().rev().cloned().collect::<Vec<_>>().join(" > "),
+            GroupBy::Rule => match connection.rule_payload.as_str() {
+                "" => connection.rule.clone(),
+                payload => format!("{}({})", connection.rule, payload),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Destination {
+    pub host: String,
+    pub connections: usize,
+    pub bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionGroup {
+    pub key: String,
+    pub connections: usize,
+    pub upload: u64,
+    pub download: u64,
+    /// The destinations with the most bytes, highest first.
+    pub top_destinations: Vec<Destination>,
+}
+
+impl ConnectionVec {
+    /// Group the connections by `by`, largest total first, keeping the
+    /// `top` destinations with the most bytes in each group.
+    pub fn group_by(&self, by: GroupBy, top: usize) -> Vec<ConnectionGroup> {
+        let mut groups: HashMap<String, (ConnectionGroup, HashMap<&str, Destination>)> = HashMap::new();
+
+        for connection in self.connections.iter() {
+            let key = by.key(connection);
+            let (group, destinations) = groups.entry(key.clone()).or_insert_with(|| {
+                let group = ConnectionGroup { key, connections: 0, upload: 0, download: 0, top_destinations: Vec::new() };
+                (group, HashMap::new())
+            });
+            group.connections += 1;
+            group.upload += connection.upload as u64;
+            group.download += connection.download as u64;
+
+            let host = connection.metadata.destination();
+            let entry = destinations.entry(host).or_insert_with(|| Destination { host: host.to_owned(), connections: 0, bytes: 0 });
+            entry.connections += 1;
+            entry.bytes += (connection.upload + connection.download) as u64;
+        }
+
+        let mut groups: Vec<ConnectionGroup> = groups
+            .into_values()
+            .map(|(mut group, destinations)| {
+                let mut destinations: Vec<Destination> = destinations.into_values().collect();
+                destinations.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.host.cmp(&b.host)));
+                destinations.truncate(top);
+                group.top_destinations = destinations;
+                group
+            })
+            .collect();
+        groups.sort_by(|a, b| (b.upload + b.download).cmp(&(a.upload + a.download)).then(a.key.cmp(&b.key)));
+        groups
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{connection, snapshot};
+
+    #[test]
+    fn test_group_by() {
+        let connections = snapshot(&[
+            connection("a", "example.com", "1.1.1.1", &["node", "Proxy"], 100, 200),
+            connection("b", "example.com", "1.1.1.1", &["node", "Proxy"], 50, 0),
+            connection("c", "google.com", "2.2.2.2", &["DIRECT"], 500, 0),
+            connection("d", "", "8.8.8.8", &["node", "Proxy"], 10, 0),
+        ]);
+
+        let hosts = connections.group_by(GroupBy::Host, 3);
+        let keys: Vec<&str> = hosts.iter().map(|g| g.key.as_str()).collect();
+        assert_eq!(keys, vec!["google.com", "example.com", "8.8.8.8"]);
+        assert_eq!((hosts[1].connections, hosts[1].upload, hosts[1].download), (2, 150, 200));
+
+        let chains = connections.group_by(GroupBy::Chain, 1);
+        assert_eq!(chains[0].key, "DIRECT");
+        assert_eq!(chains[1].key, "Proxy > node");
+        assert_eq!(chains[1].top_destinations, vec![Destination { host: "example.com".to_owned(), connections: 2, bytes: 350 }]);
+
+        let sources = connections.group_by(GroupBy::Source, 3);
+        assert_eq!((sources.len(), sources[0].key.as_str()), (1, "192.168.1.2"));
+        assert_eq!(connections.group_by(GroupBy::Rule, 3)[0].key, "Match");
+    }
+}
@@ -0,0 +1,424 @@
+//! Data usage per device, destination, process and chain, accumulated from
+//! the byte deltas between `/connections` snapshots and kept in an
+//! append-only JSON lines file.
+
+use std::{
+    collections::HashMap,
+    path::Path,
+    str::FromStr,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use clap::ValueEnum;
+use serde::{Serialize, Deserialize};
+
+use crate::{
+    connection::{format_rfc3339, parse_rfc3339, Connection, ConnectionVec},
+    filter::parse_size,
+    history::unix_now,
+    jsonl, ClashRequest, ClashRequestBuilder,
+};
+
+/// Key of the bytes that cannot be traced to a connection.
+pub const UNATTRIBUTED: &str = "(unattributed)";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum UsageBy {
+    /// Source IP, i.e. the device on the LAN
+    Source,
+    /// Destination host, or IP when there is no host
+    Host,
+    /// Process name
+    Process,
+    /// Full proxy chain
+    Chain,
+}
+
+impl std::fmt::Display for UsageBy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            UsageBy::Source => "source",
+            UsageBy::Host => "host",
+            UsageBy::Process => "process",
+            UsageBy::Chain => "chain",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Bytes attributed to one source, host, process and chain in one sample.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UsageRecord {
+    /// Unix time in seconds of the sample.
+    pub time: u64,
+    pub source: String,
+    pub host: String,
+    pub process: String,
+    pub chain: String,
+    pub upload: u64,
+    pub download: u64,
+}
+
+impl UsageRecord {
+    pub fn key(&self, by: UsageBy) -> &str {
+        match by {
+            UsageBy::Source => &self.source,
+            UsageBy::Host => &self.host,
+            UsageBy::Process => &self.process,
+            UsageBy::Chain => &self.chain,
+        }
+    }
+}
+
+/// Source, host, process and chain.
+type Key = [String; 4];
+
+fn key(connection: &Connection) -> Key {
+    let metadata = &connection.metadata;
+    [
+        metadata.source_ip.clone(),
+        metadata.destination().to_owned(),
+        metadata.process_name().to_owned(),
+        connection.chains.iter().rev().cloned().collect::<Vec<_>>().join(" > "),
+    ]
+}
+
+struct Seen {
+    key: Key,
+    upload: u64,
+    download: u64,
+    /// Bytes over the last interval, used to share out the bytes of the
+    /// connection after it closes.
+    recent: u64,
+}
+
+/// Turns successive snapshots into usage records.
+#[derive(Default)]
+pub struct UsageTracker {
+    open: HashMap<String, Seen>,
+    /// Upload and download totals of the previous snapshot.
+    totals: Option<(u64, u64)>,
+}
+
+impl UsageTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take a snapshot taken at `now`, returning the bytes transferred since
+    /// the previous one. The first snapshot is only a baseline.
+    ///
+    /// Bytes of connections that closed between the snapshots show up in the
+    /// core's totals but not in any listed connection. They are shared out
+    /// among the connections that disappeared, in proportion to their
+    /// traffic over the previous interval, or recorded as [`UNATTRIBUTED`]
+    /// when none did.
+    pub fn update(&mut self, snapshot: &ConnectionVec, now: u64) -> Vec<UsageRecord> {
+        let mut usage: HashMap<Key, (u64, u64)> = HashMap::new();
+        let mut open = HashMap::with_capacity(snapshot.connections.len());
+        let (mut live_up, mut live_down) = (0, 0);
+
+        for connection in snapshot.connections.iter() {
+            let (upload, download) = (connection.upload as u64, connection.download as u64);
+            let (up, down) = match self.open.remove(&connection.id) {
+                Some(seen) => (upload.saturating_sub(seen.upload), download.saturating_sub(seen.download)),
+                None => (upload, download),
+            };
+            let key = key(connection);
+            if up + down > 0 {
+                let entry = usage.entry(key.clone()).or_default();
+                entry.0 += up;
+                entry.1 += down;
+            }
+            live_up += up;
+            live_down += down;
+            open.insert(connection.id.clone(), Seen { key, upload, download, recent: up + down });
+        }
+
+        let closed: Vec<Seen> = std::mem::replace(&mut self.open, open).into_values().collect();
+        let totals = (snapshot.upload_total as u64, snapshot.download_total as u64);
+        let Some(prev) = self.totals.replace(totals) else {
+            return Vec::new();
+        };
+
+        // A core restart resets the totals, losing what closed in between.
+        let residual = (
+            totals.0.saturating_sub(prev.0).saturating_sub(live_up),
+            totals.1.saturating_sub(prev.1).saturating_sub(live_down),
+        );
+        if residual != (0, 0) {
+            let weight: u64 = closed.iter().map(|s| s.recent + 1).sum();
+            if closed.is_empty() {
+                let entry = usage.entry([(); 4].map(|_| UNATTRIBUTED.to_owned())).or_default();
+                entry.0 += residual.0;
+                entry.1 += residual.1;
+            }
+            let (mut left_up, mut left_down) = residual;
+            for (i, seen) in closed.iter().enumerate() {
+                let share = |bytes: u64| (bytes as u128 * (seen.recent + 1) as u128 / weight as u128) as u64;
+                let (up, down) = match i + 1 == closed.len() {
+                    true => (left_up, left_down),
+                    false => (share(residual.0), share(residual.1)),
+                };
+                left_up -= up;
+                left_down -= down;
+                let entry = usage.entry(seen.key.clone()).or_default();
+                entry.0 += up;
+                entry.1 += down;
+            }
+        }
+
+        let mut records: Vec<UsageRecord> = usage
+            .into_iter()
+            .map(|([source, host, process, chain], (upload, download))| UsageRecord {
+                time: now,
+                source,
+                host,
+                process,
+                chain,
+                upload,
+                download,
+            })
+            .collect();
+        records.sort_by(|a, b| (&a.source, &a.host, &a.process, &a.chain).cmp(&(&b.source, &b.host, &b.process, &b.chain)));
+        records
+    }
+
+    /// Poll `/connections` every `interval` until interrupted, appending the
+    /// usage of every sample to `path` and passing it to `on_sample`. Failed
+    /// polls and writes go to `on_error`; a failed poll keeps the previous
+    /// snapshot as the baseline.
+    pub async fn record<F, E>(
+        mut self,
+        client: ClashRequestBuilder,
+        path: impl AsRef<Path>,
+        interval: Duration,
+        mut on_sample: F,
+        mut on_error: E,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        F: FnMut(&[UsageRecord]),
+        E: FnMut(&dyn std::error::Error),
+    {
+        loop {
+            match client.clone().connections().send().await {
+                Ok(snapshot) => {
+                    let records = self.update(&snapshot, unix_now());
+                    if !records.is_empty() {
+                        if let Err(e) = jsonl::append(&path, &records) {
+                            on_error(e.as_ref());
+                        }
+                        on_sample(&records);
+                    }
+                }
+                Err(e) => on_error(e.as_ref()),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+/// Parse `2026-10-01` as midnight UTC, or a full RFC 3339 timestamp, into
+/// Unix seconds.
+pub fn parse_date(s: &str) -> Option<u64> {
+    let time = match s.len() {
+        10 => parse_rfc3339(&format!("{}T00:00:00Z", s)),
+        _ => parse_rfc3339(s),
+    };
+    Some(time?.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+/// Unix seconds of the start of the UTC month holding `now`.
+pub fn month_start(now: u64) -> u64 {
+    let date = format_rfc3339(UNIX_EPOCH + Duration::from_secs(now));
+    parse_date(&format!("{}-01", &date[..7])).unwrap_or_default()
+}
+
+/// Usage of one key over a report period.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct UsageTotal {
+    pub key: String,
+    pub upload: u64,
+    pub download: u64,
+}
+
+/// Sum the records at or after `since` by `by`, largest total first.
+pub fn report(records: &[UsageRecord], by: UsageBy, since: u64) -> Vec<UsageTotal> {
+    let mut totals: HashMap<&str, (u64, u64)> = HashMap::new();
+    for record in records.iter().filter(|r| r.time >= since) {
+        let entry = totals.entry(record.key(by)).or_default();
+        entry.0 += record.upload;
+        entry.1 += record.download;
+    }
+
+    let mut totals: Vec<UsageTotal> = totals
+        .into_iter()
+        .map(|(key, (upload, download))| UsageTotal { key: key.to_owned(), upload, download })
+        .collect();
+    totals.sort_by(|a, b| (b.upload + b.download).cmp(&(a.upload + a.download)).then(a.key.cmp(&b.key)));
+    totals
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QuotaError {
+    /// Not of the form `by:key=size`.
+    Format(String),
+    InvalidSize(String),
+}
+
+impl std::fmt::Display for QuotaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuotaError::Format(s) => write!(f, "Expected a quota like source:192.168.1.2=50GB, got {}", s),
+            QuotaError::InvalidSize(s) => write!(f, "Invalid size: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for QuotaError {}
+
+/// A monthly limit on the bytes of one key, e.g. `source:192.168.1.2=50GB`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Quota {
+    pub by: UsageBy,
+    pub key: String,
+    pub limit: u64,
+}
+
+impl FromStr for Quota {
+    type Err = QuotaError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let format = || QuotaError::Format(s.to_owned());
+        let (target, size) = s.rsplit_once('=').ok_or_else(format)?;
+        let (by, key) = target.split_once(':').ok_or_else(format)?;
+        let by = UsageBy::from_str(by, true).map_err(|_| format())?;
+        let limit = parse_size(size).ok_or_else(|| QuotaError::InvalidSize(size.to_owned()))?;
+        Ok(Self { by, key: key.to_owned(), limit })
+    }
+}
+
+impl std::fmt::Display for Quota {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}={}", self.by, self.key, self.limit)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct QuotaAlert {
+    pub quota: Quota,
+    /// Bytes used in the month so far.
+    pub used: u64,
+}
+
+/// Tracks quotas over the current month, alerting once per quota and
+/// month when one is exceeded.
+pub struct QuotaMonitor {
+    quotas: Vec<(Quota, u64, bool)>,
+    period: u64,
+}
+
+impl QuotaMonitor {
+    /// Start at `now`, counting the `history` of the month so far.
+    pub fn new(quotas: Vec<Quota>, history: &[UsageRecord], now: u64) -> Self {
+        let mut monitor = Self { quotas: quotas.into_iter().map(|q| (q, 0, false)).collect(), period: month_start(now) };
+        monitor.add(history, now);
+        monitor
+    }
+
+    /// Count the records of the month of `now`, returning the quotas that
+    /// were exceeded for the first time.
+    pub fn add(&mut self, records: &[UsageRecord], now: u64) -> Vec<QuotaAlert> {
+        let period = month_start(now);
+        if period != self.period {
+            self.period = period;
+            for (_, used, alerted) in self.quotas.iter_mut() {
+                (*used, *alerted) = (0, false);
+            }
+        }
+
+        let mut alerts = Vec::new();
+        for (quota, used, alerted) in self.quotas.iter_mut() {
+            *used += records
+                .iter()
+                .filter(|r| r.time >= period && r.key(quota.by) == quota.key)
+                .map(|r| r.upload + r.download)
+                .sum::<u64>();
+            if !*alerted && *used > quota.limit {
+                *alerted = true;
+                alerts.push(QuotaAlert { quota: quota.clone(), used: *used });
+            }
+        }
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mock::{connection, snapshot_with_totals, MockController};
+
+    #[test]
+    fn test_tracker() {
+        let mut tracker = UsageTracker::new();
+        let a = |up| connection("a", "example.com", "1.1.1.1", &["node", "Proxy"], up, 0);
+        let b = |up| connection("b", "google.com", "2.2.2.2", &["DIRECT"], up, 0);
+
+        assert!(tracker.update(&snapshot_with_totals(150, 0, &[a(100), b(50)]), 100).is_empty());
+
+        // `b` sends 50 more bytes and closes between the snapshots.
+        let records = tracker.update(&snapshot_with_totals(300, 0, &[a(200)]), 102);
+        let hosts: Vec<(&str, &str, u64)> = records.iter().map(|r| (r.host.as_str(), r.chain.as_str(), r.upload)).collect();
+        assert_eq!(hosts, vec![("example.com", "Proxy > node", 100), ("google.com", "DIRECT", 50)]);
+        assert_eq!(records[0].source, "192.168.1.2");
+
+        let records = tracker.update(&snapshot_with_totals(320, 0, &[a(210)]), 104);
+        assert_eq!(records.iter().map(|r| (r.host.as_str(), r.upload)).collect::<Vec<_>>(), vec![
+            (UNATTRIBUTED, 10),
+            ("example.com", 10),
+        ]);
+    }
+
+    #[test]
+    fn test_report_and_quota() {
+        let record = |time, source: &str, upload| UsageRecord {
+            time,
+            source: source.to_owned(),
+            host: "example.com".to_owned(),
+            process: "curl".to_owned(),
+            chain: "DIRECT".to_owned(),
+            upload,
+            download: 0,
+        };
+        let october = parse_date("2026-10-01").unwrap();
+        assert_eq!(parse_date("2026-10-01T00:00:00Z"), Some(october));
+        assert_eq!(month_start(october + 86400 * 20), october);
+
+        let records = vec![record(october - 1, "a", 1000), record(october, "a", 10), record(october + 5, "b", 20)];
+        let sources = report(&records, UsageBy::Source, october);
+        assert_eq!(sources.iter().map(|t| (t.key.as_str(), t.upload)).collect::<Vec<_>>(), vec![("b", 20), ("a", 10)]);
+        assert_eq!(report(&records, UsageBy::Host, 0)[0].upload, 1030);
+
+        let quota: Quota = "source:a=1KB".parse().unwrap();
+        assert_eq!((quota.by, quota.limit), (UsageBy::Source, 1024));
+        assert!("a=1KB".parse::<Quota>().is_err());
+
+        let mut monitor = QuotaMonitor::new(vec![quota], &records, october + 10);
+        assert!(monitor.add(&[record(october + 20, "a", 1000)], october + 20).is_empty());
+        let alerts = monitor.add(&[record(october + 30, "a", 100)], october + 30);
+        assert_eq!(alerts[0].used, 1110);
+        assert!(monitor.add(&[record(october + 40, "a", 100)], october + 40).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_record_survives_errors() {
+        let mock = MockController::new();
+        let client = mock.start().await;
+        let path = std::env::temp_dir().join(format!("clashrs-ctl-usage-{}.jsonl", std::process::id()));
+        let mut errors = 0;
+        let record = UsageTracker::new().record(client, &path, Duration::from_millis(10), |_| {}, |_| errors += 1);
+        assert!(tokio::time::timeout(Duration::from_secs(1), record).await.is_err());
+        assert!(errors > 1);
+    }
+}
@@ -14,6 +14,80 @@ pub struct ClashVersion {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Version {
     pub version: String,
+    /// Reported as `true` by Clash.Meta and mihomo.
+    #[serde(default)]
+    pub meta: bool,
+    /// Reported as `true` by Clash Premium.
+    #[serde(default)]
+    pub premium: bool,
+}
+
+/// Which implementation of the clash core is running.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoreFlavor {
+    Clash,
+    Premium,
+    Meta,
+}
+
+impl std::fmt::Display for CoreFlavor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CoreFlavor::Clash => write!(f, "Clash"),
+            CoreFlavor::Premium => write!(f, "Clash Premium"),
+            CoreFlavor::Meta => write!(f, "Clash.Meta/mihomo"),
+        }
+    }
+}
+
+/// A version number such as `v1.18.1`. Premium builds are versioned by
+/// date, e.g. `2023.08.17`, which parses the same way.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SemVer {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl SemVer {
+    pub fn new(major: u64, minor: u64, patch: u64) -> Self {
+        Self { major, minor, patch }
+    }
+
+    /// Parse the leading `x.y.z` of a version string, ignoring a `v`
+    /// prefix and anything after the numbers, e.g. `-13-gdcc8d87`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim().trim_start_matches(|c: char| !c.is_ascii_digit());
+        let end = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+        let mut parts = s[..end].split('.').filter(|p| !p.is_empty()).map(|p| p.parse::<u64>());
+
+        let major = parts.next()?.ok()?;
+        let minor = parts.next().and_then(Result::ok).unwrap_or(0);
+        let patch = parts.next().and_then(Result::ok).unwrap_or(0);
+        Some(Self { major, minor, patch })
+    }
+}
+
+impl std::fmt::Display for SemVer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl Version {
+    pub fn flavor(&self) -> CoreFlavor {
+        if self.meta || self.version.to_lowercase().contains("meta") {
+            CoreFlavor::Meta
+        } else if self.premium {
+            CoreFlavor::Premium
+        } else {
+            CoreFlavor::Clash
+        }
+    }
+
+    pub fn semver(&self) -> Option<SemVer> {
+        SemVer::parse(&self.version)
+    }
 }
 
 impl TryFrom<String> for Version {
@@ -87,5 +161,26 @@ mod test {
 
         println!("{:?}", v);
     }
+
+    #[test]
+    fn test_flavor_and_semver() {
+        use super::{CoreFlavor, SemVer, Version};
+
+        let parse = |s: &str| Version::try_from(s.to_owned()).unwrap();
+
+        let clash = parse(r#"{"version":"v1.18.0"}"#);
+        assert_eq!(clash.flavor(), CoreFlavor::Clash);
+        assert_eq!(clash.semver(), Some(SemVer::new(1, 18, 0)));
+
+        let premium = parse(r#"{"premium":true,"version":"2023.08.17-13-gdcc8d87"}"#);
+        assert_eq!(premium.flavor(), CoreFlavor::Premium);
+        assert_eq!(premium.semver(), Some(SemVer::new(2023, 8, 17)));
+
+        let meta = parse(r#"{"meta":true,"version":"v1.18.5"}"#);
+        assert_eq!(meta.flavor(), CoreFlavor::Meta);
+        assert!(meta.semver().unwrap() > SemVer::new(1, 18, 0));
+
+        assert_eq!(parse(r#"{"version":"unknown"}"#).semver(), None);
+    }
 }
 